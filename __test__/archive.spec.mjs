@@ -0,0 +1,153 @@
+import test from 'ava'
+import { join } from 'path'
+import { createHash } from 'crypto'
+import { chmodSync, readFileSync, writeFileSync } from 'fs'
+import {
+  createArchiveCapsule,
+  createDataCapsule,
+  extractArchiveEntry,
+  extractDataCapsule,
+  listArchiveEntries,
+  loadCapsuleSet
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Archive Mode Tests
+
+function sha256(data) {
+  return createHash('sha256').update(data).digest('hex')
+}
+
+function smallFiles(count) {
+  return Array.from({ length: count }, (_, index) => ({
+    name: `docs/file-${String(index).padStart(3, '0')}.json`,
+    data: Buffer.from(JSON.stringify({ index, payload: 'x'.repeat(index * 10) }))
+  }))
+}
+
+for (const postProcessPadding of [true, false]) {
+  test(`small files share the capsules of one archive set (postProcessPadding = ${postProcessPadding})`, (t) => {
+    const tempDir = createTempDir()
+
+    try {
+      const files = smallFiles(100)
+      const archiveSet = createArchiveCapsule(files, tempDir, postProcessPadding, TEST_KEYS.BASIC)
+
+      t.is(archiveSet.metadata.kind, 'ARCHIVE')
+      t.is(archiveSet.metadata.capsuleCount, 1, '100 small files should fit in a single capsule')
+
+      const entries = listArchiveEntries(tempDir, TEST_KEYS.BASIC)
+      t.deepEqual(
+        entries.map((entry) => entry.name),
+        files.map((file) => file.name)
+      )
+      for (const [index, entry] of entries.entries()) {
+        t.is(entry.size, files[index].data.length)
+        t.is(entry.hash, sha256(files[index].data))
+        t.is(entry.mode, 0o644)
+      }
+
+      assertBuffersEqual(t, extractArchiveEntry(tempDir, files[42].name, TEST_KEYS.BASIC), files[42].data)
+    } finally {
+      cleanupTempDir(tempDir)
+    }
+  })
+}
+
+test('archives mix buffers and files and keep file modes', (t) => {
+  const tempDir = createTempDir()
+  const inputDir = createTempDir()
+
+  try {
+    const scriptPath = join(inputDir, 'run.sh')
+    writeFileSync(scriptPath, '#!/bin/sh\necho hello\n')
+    chmodSync(scriptPath, 0o755)
+    const largeData = createTestData(3 * 1024 * 1024)
+
+    const archiveSet = createArchiveCapsule(
+      [
+        { name: 'large.bin', data: largeData },
+        { name: 'bin/run.sh', path: scriptPath },
+        { name: 'readme.txt', data: Buffer.from('read me'), mode: 0o600 }
+      ],
+      tempDir,
+      true
+    )
+
+    const entries = listArchiveEntries(tempDir)
+    // Entries are ordered by name whatever order they were given in
+    t.deepEqual(
+      entries.map((entry) => [entry.name, entry.mode]),
+      [
+        ['bin/run.sh', process.platform === 'win32' ? 0o644 : 0o755],
+        ['large.bin', 0o644],
+        ['readme.txt', 0o600]
+      ]
+    )
+
+    assertBuffersEqual(t, extractArchiveEntry(tempDir, 'bin/run.sh'), readFileSync(scriptPath))
+    assertBuffersEqual(t, extractArchiveEntry(tempDir, 'large.bin'), largeData)
+    assertBuffersEqual(t, extractArchiveEntry(tempDir, 'readme.txt', null, archiveSet.id), Buffer.from('read me'))
+
+    // The whole archive still extracts like any other set
+    t.is(extractDataCapsule(tempDir).length, archiveSet.metadata.originalSize)
+  } finally {
+    cleanupTempDir(tempDir)
+    cleanupTempDir(inputDir)
+  }
+})
+
+test('the same files in any order give the same archive set', (t) => {
+  const firstDir = createTempDir()
+  const secondDir = createTempDir()
+
+  try {
+    const files = smallFiles(10)
+    const first = createArchiveCapsule(files, firstDir, true, TEST_KEYS.CONSENSUS)
+    const second = createArchiveCapsule([...files].reverse(), secondDir, true, TEST_KEYS.CONSENSUS)
+
+    t.is(first.id, second.id)
+    t.deepEqual(loadCapsuleSet(secondDir), second)
+  } finally {
+    cleanupTempDir(firstDir)
+    cleanupTempDir(secondDir)
+  }
+})
+
+test('invalid archive entries and reads are rejected', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const data = Buffer.from('data')
+    const create = (entries) => () => createArchiveCapsule(entries, tempDir, true)
+
+    t.throws(create([{ name: 'a', data }, { name: 'a', data }]), {
+      message: /Duplicate archive entry name: a/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+    for (const name of ['', '/etc/passwd', '../escape', 'a/./b', 'a\\b']) {
+      t.throws(create([{ name, data }]), { message: /Invalid archive entry name/, code: 'CAPSULE_INVALID_OPTION' })
+    }
+    t.throws(create([{ name: 'neither' }]), { code: 'CAPSULE_INVALID_OPTION' })
+    t.throws(create([{ name: 'both', data, path: 'file.txt' }]), { code: 'CAPSULE_INVALID_OPTION' })
+
+    createArchiveCapsule([{ name: 'a', data }], tempDir, true, TEST_KEYS.BASIC)
+    t.throws(() => extractArchiveEntry(tempDir, 'missing', TEST_KEYS.BASIC), {
+      message: /Archive entry missing not found/,
+      code: 'CAPSULE_ENTRY_NOT_FOUND'
+    })
+    t.throws(() => listArchiveEntries(tempDir, 'wrong-key'), { code: 'CAPSULE_DECRYPT_FAILED' })
+
+    const dataDir = join(tempDir, 'plain')
+    createDataCapsule(data, dataDir, true)
+    t.throws(() => listArchiveEntries(dataDir), { message: /is not an archive/, code: 'CAPSULE_INVALID_OPTION' })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
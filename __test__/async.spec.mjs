@@ -0,0 +1,141 @@
+import test from 'ava'
+import { readFileSync, readdirSync } from 'fs'
+import { join } from 'path'
+import {
+  createDataCapsuleAsync,
+  createDataCapsuleFromFileAsync,
+  extractDataCapsuleAsync,
+  extractDataCapsuleToFileAsync,
+  reconstructFileFromCapsulesAsync,
+  createDataCapsule,
+  loadCapsuleSet
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestFile,
+  createTestData,
+  assertValidCapsuleSet,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Async (worker thread) API Tests
+
+test('createDataCapsuleAsync returns a Promise resolving to a capsule set', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    const originalData = createTestData(TEST_SIZES.SMALL)
+    const pending = createDataCapsuleAsync(originalData, outputDir, true, TEST_KEYS.BASIC)
+    t.true(pending instanceof Promise, 'Should return a Promise')
+
+    const capsuleSet = await pending
+    assertValidCapsuleSet(t, capsuleSet)
+
+    const extracted = await extractDataCapsuleAsync(outputDir, TEST_KEYS.BASIC)
+    assertBuffersEqual(t, extracted, originalData, 'Async roundtrip should preserve data')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('async variants produce the same capsules as the sync API', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const originalData = createTestData(TEST_SIZES.MEDIUM)
+    const syncSet = createDataCapsule(originalData, join(tempDir, 'sync'), false, TEST_KEYS.BASIC)
+    const asyncSet = await createDataCapsuleAsync(originalData, join(tempDir, 'async'), false, TEST_KEYS.BASIC)
+
+    t.is(asyncSet.id, syncSet.id, 'Set ids should match')
+    t.deepEqual(
+      asyncSet.capsules.map((capsule) => capsule.hash),
+      syncSet.capsules.map((capsule) => capsule.hash),
+      'Capsule hashes should match'
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('concurrent writes of the same input into one directory do not collide', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const originalData = createTestData(TEST_SIZES.MULTI_MB)
+    const capsuleSets = await Promise.all(
+      Array.from({ length: 4 }, () => createDataCapsuleAsync(originalData, tempDir, true, TEST_KEYS.BASIC))
+    )
+
+    for (const capsuleSet of capsuleSets) {
+      t.deepEqual(capsuleSet, capsuleSets[0])
+    }
+    t.false(readdirSync(tempDir).some((file) => file.endsWith('.tmp')), 'No temporary files should be left')
+    assertBuffersEqual(t, await extractDataCapsuleAsync(tempDir, TEST_KEYS.BASIC), originalData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('file-based async variants roundtrip', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  const extractedFile = join(tempDir, 'extracted.dat')
+  const reconstructedFile = join(tempDir, 'reconstructed.dat')
+
+  try {
+    const originalData = createTestFile(TEST_SIZES.LARGE, inputFile)
+    await createDataCapsuleFromFileAsync(inputFile, capsulesDir, true, TEST_KEYS.STRONG)
+
+    await extractDataCapsuleToFileAsync(capsulesDir, extractedFile, TEST_KEYS.STRONG)
+    assertBuffersEqual(t, readFileSync(extractedFile), originalData, 'Extracted file should match')
+
+    const capsuleSet = loadCapsuleSet(capsulesDir)
+    await reconstructFileFromCapsulesAsync(capsuleSet, capsulesDir, reconstructedFile, TEST_KEYS.STRONG)
+    assertBuffersEqual(t, readFileSync(reconstructedFile), originalData, 'Reconstructed file should match')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('async variants reject instead of throwing', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    await createDataCapsuleAsync(createTestData(TEST_SIZES.TINY), outputDir, true, TEST_KEYS.BASIC)
+
+    await t.throwsAsync(extractDataCapsuleAsync(outputDir, 'wrong-key'), undefined, 'Wrong key should reject')
+    await t.throwsAsync(
+      extractDataCapsuleAsync(join(tempDir, 'missing')),
+      undefined,
+      'Missing capsule set should reject'
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('async operations do not block the event loop', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const originalData = createTestData(TEST_SIZES.LARGE)
+    let ticks = 0
+    const timer = setInterval(() => ticks++, 1)
+
+    await Promise.all([
+      createDataCapsuleAsync(originalData, join(tempDir, 'a'), true, TEST_KEYS.BASIC),
+      createDataCapsuleAsync(originalData, join(tempDir, 'b'), false, TEST_KEYS.BASIC)
+    ])
+    clearInterval(timer)
+
+    t.true(ticks > 0, 'Timers should keep firing while capsules are created')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
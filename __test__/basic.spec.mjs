@@ -28,7 +28,7 @@ test('getCapsuleSizes returns correct fixed sizes', (t) => {
 
 test('getConsensusVersion returns valid version', (t) => {
   const version = getConsensusVersion()
-  t.is(version, 'DIG_CAPSULE_V1', 'Should return correct consensus version')
+  t.is(version, 'DIG_CAPSULE_V2', 'Should return correct consensus version')
   t.is(typeof version, 'string', 'Version should be a string')
   t.true(version.length > 0, 'Version should not be empty')
 })
@@ -1,8 +1,10 @@
 import test from 'ava'
-import { writeFileSync } from 'fs'
+import { readFileSync, writeFileSync, statSync, truncateSync } from 'fs'
+import { createHash } from 'crypto'
 import { join } from 'path'
 import { 
   createDataCapsuleFromFile,
+  reconstructFileFromCapsules,
   isValidCapsuleFile,
   getCapsuleFileInfo
 } from '../index.js'
@@ -29,7 +31,7 @@ test('isValidCapsuleFile identifies valid capsule files', async (t) => {
     
     // Check each capsule file
     for (let i = 0; i < capsuleSet.metadata.capsuleCount; i++) {
-      const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_${i.toString().padStart(3, '0')}.capsule`)
+      const capsuleFile = join(capsulesDir, `${capsuleSet.id}_${i.toString().padStart(3, '0')}.capsule`)
       const isValid = await isValidCapsuleFile(capsuleFile)
       t.true(isValid, `Capsule file ${i} should be valid`)
     }
@@ -112,15 +114,15 @@ test('getCapsuleFileInfo extracts correct header information', async (t) => {
     const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
     
     // Get info from first capsule
-    const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_000.capsule`)
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
     const info = await getCapsuleFileInfo(capsuleFile)
     
     t.truthy(info, 'Should return capsule info')
-    t.is(info.version, 1, 'Version should be 1')
+    t.is(info.version, 2, 'Version should be 2')
     t.is(info.capsuleIndex, 0, 'Index should be 0')
     t.is(info.capsuleSize, 256 * 1024, 'Size should be 256KB')
     t.true(info.isEncrypted, 'Should be encrypted')
-    t.true(info.isCompressed, 'Should be compressed')
+    t.false(info.isCompressed, 'Random data should be stored uncompressed')
     t.is(typeof info.checksum, 'string', 'Should have checksum string')
     t.true(info.checksum.length > 0, 'Checksum should not be empty')
     
@@ -160,12 +162,60 @@ test('getCapsuleFileInfo works with unencrypted files', async (t) => {
     // Create unencrypted capsules
     const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, false, null)
     
-    const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_000.capsule`)
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
     const info = await getCapsuleFileInfo(capsuleFile)
     
     t.truthy(info, 'Should return capsule info for unencrypted file')
     t.false(info.isEncrypted, 'Should not be marked as encrypted')
-    t.true(info.isCompressed, 'Should be marked as compressed')
+    t.false(info.isCompressed, 'Random data should not be marked as compressed')
+    
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('getCapsuleFileInfo reports the exact payload size', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  
+  try {
+    createTestFile(TEST_SIZES.SMALL, inputFile)
+    
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
+    
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
+    const info = await getCapsuleFileInfo(capsuleFile)
+    
+    t.true(info.dataSize > 0, 'Payload size should be recorded')
+    t.true(info.dataSize < info.capsuleSize, 'Payload size should exclude padding')
+    t.is(statSync(capsuleFile).size, 44 + info.capsuleSize, 'Body should fill the capsule exactly')
+    
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('reconstructFileFromCapsules rejects capsules whose body disagrees with the header', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  
+  try {
+    createTestFile(TEST_SIZES.SMALL, inputFile)
+    
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
+    
+    // Drop part of the padding: the payload is intact but the body is too short
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
+    truncateSync(capsuleFile, statSync(capsuleFile).size - 16)
+    
+    // Record the truncated file's hash so only the header/body check can catch it
+    capsuleSet.capsules[0].hash = createHash('sha256').update(readFileSync(capsuleFile)).digest('hex')
+    t.throws(
+      () => reconstructFileFromCapsules(capsuleSet, capsulesDir, join(tempDir, 'output.dat'), TEST_KEYS.BASIC),
+      { message: /Invalid format/ }
+    )
     
   } finally {
     cleanupTempDir(tempDir)
@@ -185,18 +235,18 @@ test('capsule headers maintain format consistency across multiple files', async
     
     // Verify all capsule files have consistent headers
     for (let i = 0; i < capsuleSet.metadata.capsuleCount; i++) {
-      const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_${i.toString().padStart(3, '0')}.capsule`)
+      const capsuleFile = join(capsulesDir, `${capsuleSet.id}_${i.toString().padStart(3, '0')}.capsule`)
       const info = await getCapsuleFileInfo(capsuleFile)
       
       // Get expected size for this specific capsule from the capsule set
       const expectedCapsuleSize = capsuleSet.capsules[i].size
       
       t.truthy(info, `Capsule ${i} should have valid header`)
-      t.is(info.version, 1, `Capsule ${i} should have correct version`)
+      t.is(info.version, 2, `Capsule ${i} should have correct version`)
       t.is(info.capsuleIndex, i, `Capsule ${i} should have correct index`)
       t.is(info.capsuleSize, expectedCapsuleSize, `Capsule ${i} should have correct size`)
       t.true(info.isEncrypted, `Capsule ${i} should be encrypted`)
-      t.true(info.isCompressed, `Capsule ${i} should be compressed`)
+      t.false(info.isCompressed, `Capsule ${i} of random data should be stored uncompressed`)
       t.is(typeof info.checksum, 'string', `Capsule ${i} should have checksum`)
     }
     
@@ -217,7 +267,7 @@ test('getCapsuleFileInfo handles different capsule sizes correctly', async (t) =
     const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, false, TEST_KEYS.BASIC)
     
     if (capsuleSet.metadata.capsuleCount > 0) {
-      const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_000.capsule`)
+      const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
       const info = await getCapsuleFileInfo(capsuleFile)
       
       t.truthy(info, 'Should return info for larger capsule')
@@ -245,7 +295,7 @@ test('isValidCapsuleFile performance with large number of files', async (t) => {
     // Validate all capsule files
     const validationPromises = []
     for (let i = 0; i < capsuleSet.metadata.capsuleCount; i++) {
-      const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_${i.toString().padStart(3, '0')}.capsule`)
+      const capsuleFile = join(capsulesDir, `${capsuleSet.id}_${i.toString().padStart(3, '0')}.capsule`)
       validationPromises.push(isValidCapsuleFile(capsuleFile))
     }
     
@@ -0,0 +1,184 @@
+import test from 'ava'
+import { join } from 'path'
+import { readFileSync, readdirSync } from 'fs'
+import { createDataCapsule, decodeCapsule, extractDataCapsule, extractRange } from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Compression Codec Tests
+
+const FLAGS_OFFSET = 24
+const FLAG_COMPRESSED = 0x02
+const CODEC_ID_OFFSET = 28 // reserved[0] in the capsule header
+const CODEC_IDS = { gzip: 0, zstd: 1, lz4: 2 }
+
+function capsuleFiles(dir) {
+  return readdirSync(dir)
+    .filter((file) => file.endsWith('.capsule'))
+    .sort()
+    .map((file) => join(dir, file))
+}
+
+for (const algorithm of ['zstd', 'lz4']) {
+  for (const postProcessPadding of [true, false]) {
+    test(`${algorithm} capsules round-trip (postProcessPadding = ${postProcessPadding})`, (t) => {
+      const tempDir = createTempDir()
+
+      try {
+        const testData = createTestData(TEST_SIZES.MULTI_MB)
+        const capsuleSet = createDataCapsule(testData, tempDir, postProcessPadding, TEST_KEYS.BASIC, {
+          compression: { algorithm }
+        })
+
+        t.is(capsuleSet.metadata.compressionInfo.algorithm, algorithm)
+        t.is(capsuleSet.metadata.compressionInfo.level, algorithm === 'zstd' ? 3 : 0)
+        for (const file of capsuleFiles(tempDir)) {
+          t.is(readFileSync(file)[CODEC_ID_OFFSET], CODEC_IDS[algorithm])
+        }
+
+        assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), testData)
+        assertBuffersEqual(
+          t,
+          extractRange(tempDir, 1024 * 1024 - 100, 200, TEST_KEYS.BASIC),
+          testData.subarray(1024 * 1024 - 100, 1024 * 1024 + 100)
+        )
+        assertBuffersEqual(
+          t,
+          decodeCapsule(capsuleFiles(tempDir)[0], TEST_KEYS.BASIC),
+          testData.subarray(0, 1024 * 1024)
+        )
+      } finally {
+        cleanupTempDir(tempDir)
+      }
+    })
+  }
+}
+
+test('gzip stays the default and keeps codec id 0', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const capsuleSet = createDataCapsule(testData, tempDir, true)
+
+    t.deepEqual(capsuleSet.metadata.compressionInfo, {
+      algorithm: 'gzip',
+      level: 6,
+      originalSize: TEST_SIZES.SMALL,
+      compressedCapsules: 0
+    })
+    t.is(readFileSync(capsuleFiles(tempDir)[0])[CODEC_ID_OFFSET], CODEC_IDS.gzip)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('pinned zstd levels are deterministic', (t) => {
+  const firstDir = createTempDir()
+  const secondDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.XLARGE)
+    const options = { compression: { algorithm: 'zstd', level: 19 } }
+    const first = createDataCapsule(testData, firstDir, true, TEST_KEYS.CONSENSUS, options)
+    const second = createDataCapsule(testData, secondDir, true, TEST_KEYS.CONSENSUS, options)
+
+    t.is(first.metadata.compressionInfo.level, 19)
+    t.deepEqual(
+      first.capsules.map((capsule) => capsule.hash),
+      second.capsules.map((capsule) => capsule.hash)
+    )
+    assertBuffersEqual(t, extractDataCapsule(firstDir, TEST_KEYS.CONSENSUS), testData)
+  } finally {
+    cleanupTempDir(firstDir)
+    cleanupTempDir(secondDir)
+  }
+})
+
+test('unsupported codecs and levels are rejected', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.TINY)
+    const create = (compression) => () => createDataCapsule(testData, tempDir, true, null, { compression })
+
+    t.throws(create({ algorithm: 'brotli' }), {
+      message: /Unsupported compression algorithm: brotli/,
+      code: 'CAPSULE_CONSENSUS_VIOLATION'
+    })
+    t.throws(create({ algorithm: 'zstd', level: 5 }), {
+      message: /Unsupported zstd compression level: 5/,
+      code: 'CAPSULE_CONSENSUS_VIOLATION'
+    })
+    t.throws(create({ algorithm: 'gzip', level: 9 }), { code: 'CAPSULE_CONSENSUS_VIOLATION' })
+    t.throws(create({ algorithm: 'lz4', level: 1 }), { code: 'CAPSULE_CONSENSUS_VIOLATION' })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+// Mixes compressible text with random bytes, one capsule chunk of each
+function mixedTestData() {
+  const text = Buffer.alloc(1024 * 1024, 'data capsules compress repetitive text well. ')
+  return Buffer.concat([text, createTestData(1024 * 1024), text.subarray(0, 1000)])
+}
+
+for (const algorithm of ['gzip', 'zstd', 'lz4']) {
+  for (const postProcessPadding of [true, false]) {
+    test(`${algorithm} stores chunks that do not compress raw (postProcessPadding = ${postProcessPadding})`, (t) => {
+      const tempDir = createTempDir()
+
+      try {
+        const testData = mixedTestData()
+        const capsuleSet = createDataCapsule(testData, tempDir, postProcessPadding, TEST_KEYS.BASIC, {
+          compression: { algorithm }
+        })
+
+        t.deepEqual(
+          capsuleSet.capsules.map((capsule) => capsule.compressed),
+          [true, false, true]
+        )
+        t.is(capsuleSet.metadata.compressionInfo.compressedCapsules, 2)
+        capsuleFiles(tempDir).forEach((file, index) => {
+          const flags = readFileSync(file).readUInt32LE(FLAGS_OFFSET)
+          t.is((flags & FLAG_COMPRESSED) !== 0, capsuleSet.capsules[index].compressed)
+        })
+
+        assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), testData)
+        assertBuffersEqual(
+          t,
+          extractRange(tempDir, 1024 * 1024 - 100, 1024 * 1024 + 200, TEST_KEYS.BASIC),
+          testData.subarray(1024 * 1024 - 100, 2 * 1024 * 1024 + 100)
+        )
+        assertBuffersEqual(
+          t,
+          decodeCapsule(capsuleFiles(tempDir)[1], TEST_KEYS.BASIC),
+          testData.subarray(1024 * 1024, 2 * 1024 * 1024)
+        )
+      } finally {
+        cleanupTempDir(tempDir)
+      }
+    })
+  }
+}
+
+test('unencrypted random data round-trips uncompressed', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.MEDIUM)
+    const capsuleSet = createDataCapsule(testData, tempDir, true)
+
+    t.false(capsuleSet.capsules[0].compressed)
+    t.is(capsuleSet.metadata.compressionInfo.compressedCapsules, 0, 'No capsule holds compressed data')
+    assertBuffersEqual(t, extractDataCapsule(tempDir), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -1,7 +1,11 @@
 import test from 'ava'
-import { join } from 'path'
+import { statSync, writeFileSync } from 'fs'
+import { join, dirname } from 'path'
+import { fileURLToPath } from 'url'
 import { 
   createDataCapsuleFromFile,
+  extractDataCapsule,
+  loadCapsuleSet,
   validateConsensusParameters
 } from '../index.js'
 import { 
@@ -14,6 +18,8 @@ import {
   TEST_KEYS
 } from './helpers/test-utils.mjs'
 
+const __dirname = dirname(fileURLToPath(import.meta.url))
+
 // Consensus Validation Tests
 
 test('validateConsensusParameters validates correct capsule sets', async (t) => {
@@ -111,11 +117,13 @@ test('postProcessPadding modes produce different but valid results', async (t) =
     // Create with postProcessPadding = true
     const capsuleSet2 = await createDataCapsuleFromFile(inputFile, outputDir2, true, TEST_KEYS.CONSENSUS)
     
-    // Should have same original checksum (since padding mode is now always the same)
+    // Should have same original checksum
     t.is(capsuleSet1.metadata.checksum, capsuleSet2.metadata.checksum, 'Original checksums should match')
     
-    // Since padding is now always post-process, capsule hashes should be identical
-    t.is(capsuleSet1.capsules[0].hash, capsuleSet2.capsules[0].hash, 'Capsule hashes should be identical since padding mode is consistent')
+    // Padding inside vs. outside the encryption envelope yields different capsules
+    t.not(capsuleSet1.capsules[0].hash, capsuleSet2.capsules[0].hash, 'Capsule hashes should differ between padding modes')
+    t.false(capsuleSet1.capsules[0].paddingPostProcess, 'postProcessPadding = false should pad inside the envelope')
+    t.true(capsuleSet2.capsules[0].paddingPostProcess, 'postProcessPadding = true should pad after encryption')
     
   } finally {
     cleanupTempDir(tempDir)
@@ -165,7 +173,7 @@ test('consensus validation is consistent across different file sizes', async (t)
       t.true(isValid, `Capsule set for size ${testSizes[i]} should be valid`)
       
       // Verify consensus-critical fields
-      t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V1', 'Should have correct consensus version')
+      t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V2', 'Should have correct consensus version')
       t.is(capsuleSet.metadata.chunkingAlgorithm, 'DIG_DETERMINISTIC_V1', 'Should have correct chunking algorithm')
     }
     
@@ -196,4 +204,63 @@ test('deterministic chunking follows consensus algorithm', async (t) => {
   } finally {
     cleanupTempDir(tempDir)
   }
-}) 
\ No newline at end of file
+}) 
+
+test('legacy DIG_CAPSULE_V1 sets pass consensus validation', async (t) => {
+  const capsuleSet = await loadCapsuleSet(join(__dirname, 'fixtures', 'legacy-v1'))
+
+  t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V1', 'Fixture should be a V1 set')
+  t.true(await validateConsensusParameters(capsuleSet), 'V1 sets should remain valid')
+})
+
+test('compressible encrypted data stays in its consensus bucket', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    // Full 256KB chunks of compressible data only fit their bucket when
+    // compression runs before encryption
+    const originalData = Buffer.alloc(3 * 256 * 1024, 'capsule ')
+    writeFileSync(inputFile, originalData)
+
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, outputDir, false, TEST_KEYS.CONSENSUS)
+
+    t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V2', 'Should use the compress-then-encrypt pipeline')
+    capsuleSet.capsules.forEach((capsule, index) => {
+      t.is(capsule.size, 256 * 1024, `Capsule ${index} should not be upgraded to a larger bucket`)
+    })
+
+    const extracted = await extractDataCapsule(outputDir, TEST_KEYS.CONSENSUS)
+    t.true(Buffer.compare(extracted, originalData) === 0, 'Extracted data should match original')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('both padding modes roundtrip with fixed-size capsule files', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+
+  try {
+    const originalData = createTestFile(TEST_SIZES.LARGE, inputFile)
+
+    for (const postProcessPadding of [false, true]) {
+      for (const key of [TEST_KEYS.CONSENSUS, null]) {
+        const outputDir = join(tempDir, `out-${postProcessPadding}-${key ? 'enc' : 'plain'}`)
+        const capsuleSet = await createDataCapsuleFromFile(inputFile, outputDir, postProcessPadding, key)
+
+        capsuleSet.capsules.forEach((capsule) => {
+          t.is(capsule.paddingPostProcess, postProcessPadding, 'Padding mode should be recorded per capsule')
+          const capsuleFile = join(outputDir, `${capsuleSet.id}_${String(capsule.index).padStart(3, '0')}.capsule`)
+          t.is(statSync(capsuleFile).size, capsule.size + 44, 'Capsule file should be header + fixed capsule size')
+        })
+
+        const extracted = await extractDataCapsule(outputDir, key)
+        t.true(Buffer.compare(extracted, originalData) === 0, `Roundtrip should succeed (postProcessPadding=${postProcessPadding}, key=${!!key})`)
+      }
+    }
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
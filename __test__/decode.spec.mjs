@@ -0,0 +1,137 @@
+import test from 'ava'
+import { join, dirname } from 'path'
+import { fileURLToPath } from 'url'
+import { readFileSync, readdirSync, renameSync, unlinkSync, writeFileSync } from 'fs'
+import { createDataCapsule, decodeCapsule, decodeCapsuleToFile, extractDataCapsule, loadCapsuleSet } from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+const __dirname = dirname(fileURLToPath(import.meta.url))
+const LEGACY_V1_DIR = join(__dirname, 'fixtures', 'legacy-v1')
+const LEGACY_V1_KEY = 'example-encryption-key-2024'
+
+// Single-Capsule Decode Tests
+
+const MB = 1024 * 1024
+
+function capsuleFile(dir, index) {
+  const suffix = `_${String(index).padStart(3, '0')}.capsule`
+  return join(dir, readdirSync(dir).find((file) => file.endsWith(suffix)))
+}
+
+test('decodeCapsule returns the plaintext of one capsule', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.MULTI_MB)
+    createDataCapsule(testData, tempDir, false, TEST_KEYS.BASIC)
+
+    for (const index of [0, 3]) {
+      const decoded = decodeCapsule(capsuleFile(tempDir, index), TEST_KEYS.BASIC)
+      assertBuffersEqual(t, decoded, testData.subarray(index * MB, (index + 1) * MB))
+    }
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('decodeCapsuleToFile writes the plaintext of one capsule', (t) => {
+  const tempDir = createTempDir()
+  const capsulesDir = join(tempDir, 'capsules')
+  const outputFile = join(tempDir, 'capsule.dat')
+
+  try {
+    const testData = createTestData(750 * 1024)
+    createDataCapsule(testData, capsulesDir, true, TEST_KEYS.BASIC)
+
+    decodeCapsuleToFile(capsuleFile(capsulesDir, 2), outputFile, TEST_KEYS.BASIC)
+    assertBuffersEqual(t, readFileSync(outputFile), testData.subarray(512 * 1024))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('decodeCapsule uses a provided capsule set when the metadata is elsewhere', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const capsuleSet = createDataCapsule(testData, tempDir, true, TEST_KEYS.BASIC)
+
+    const metadataFile = readdirSync(tempDir).find((file) => file.endsWith('_metadata.json'))
+    unlinkSync(join(tempDir, metadataFile))
+
+    t.throws(() => decodeCapsule(capsuleFile(tempDir, 0), TEST_KEYS.BASIC), {
+      message: /Capsule set metadata not found/
+    })
+
+    const decoded = decodeCapsule(capsuleFile(tempDir, 0), TEST_KEYS.BASIC, capsuleSet)
+    assertBuffersEqual(t, decoded, testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('unencrypted capsules decode without any metadata', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    createDataCapsule(testData, tempDir, true)
+
+    const source = capsuleFile(tempDir, 0)
+    const renamed = join(tempDir, 'standalone.capsule')
+    renameSync(source, renamed)
+
+    assertBuffersEqual(t, decodeCapsule(renamed), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('decodeCapsule rejects invalid and tampered capsules', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true, TEST_KEYS.BASIC)
+
+    const notCapsule = join(tempDir, 'not-a-capsule.capsule')
+    writeFileSync(notCapsule, Buffer.alloc(1024))
+    t.throws(() => decodeCapsule(notCapsule), { message: /Invalid format/ })
+
+    const capsulePath = capsuleFile(tempDir, 0)
+    const contents = readFileSync(capsulePath)
+    contents[contents.length - 1] ^= 0xff
+    writeFileSync(capsulePath, contents)
+    t.throws(() => decodeCapsule(capsulePath, TEST_KEYS.BASIC), { message: /Capsule hash mismatch at index 0/ })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('decodeCapsule requires the right key', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, false, TEST_KEYS.BASIC)
+
+    t.throws(() => decodeCapsule(capsuleFile(tempDir, 0), TEST_KEYS.STRONG), { message: /Decryption failed/ })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('decodeCapsule reads legacy version 1 capsules', async (t) => {
+  const capsuleSet = await loadCapsuleSet(LEGACY_V1_DIR)
+  const decoded = decodeCapsule(capsuleFile(LEGACY_V1_DIR, 0), LEGACY_V1_KEY)
+  const extracted = extractDataCapsule(LEGACY_V1_DIR, LEGACY_V1_KEY)
+
+  t.is(decoded.length, capsuleSet.metadata.originalSize)
+  assertBuffersEqual(t, decoded, extracted)
+})
@@ -0,0 +1,152 @@
+import test from 'ava'
+import { join } from 'path'
+import { createHash } from 'crypto'
+import { readdirSync, unlinkSync } from 'fs'
+import {
+  createDataCapsule,
+  decodeCapsule,
+  extractDataCapsule,
+  extractRange,
+  getCapsuleFileInfo,
+  listCapsuleSets,
+  trainCompressionDictionary
+} from '../index.js'
+import { createTempDir, cleanupTempDir, assertBuffersEqual, TEST_KEYS } from './helpers/test-utils.mjs'
+
+// Compression Dictionary Tests
+
+const DICTIONARY_SIZE = 8 * 1024
+
+// Small JSON documents sharing most of their structure
+function jsonDocument(index) {
+  return Buffer.from(
+    JSON.stringify({
+      id: `document-${index}`,
+      type: 'customer-record',
+      createdAt: new Date(Date.UTC(2024, 0, 1 + (index % 28))).toISOString(),
+      customer: {
+        name: `Customer ${index}`,
+        email: `customer${index}@example.com`,
+        address: { street: `${index} Main Street`, city: 'Springfield', country: 'US' }
+      },
+      tags: ['active', index % 2 ? 'premium' : 'standard', 'newsletter'],
+      balance: (index * 37) % 1000
+    })
+  )
+}
+
+function jsonDocuments(count) {
+  return Array.from({ length: count }, (_, index) => jsonDocument(index))
+}
+
+function capsuleFile(dir, capsuleSet) {
+  return join(dir, `${capsuleSet.id}_000.capsule`)
+}
+
+test('trained dictionaries are stored as content-addressed dictionary sets', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const dictionarySet = trainCompressionDictionary(jsonDocuments(500), tempDir, null, DICTIONARY_SIZE)
+
+    t.is(dictionarySet.metadata.kind, 'ZSTD_DICTIONARY')
+    t.true(dictionarySet.metadata.originalSize <= DICTIONARY_SIZE)
+
+    const dictionary = extractDataCapsule(tempDir, null, dictionarySet.id)
+    t.is(createHash('sha256').update(dictionary).digest('hex'), dictionarySet.id)
+    t.deepEqual(listCapsuleSets(tempDir), [dictionarySet])
+
+    // Training on the same samples stores the same dictionary again
+    t.deepEqual(trainCompressionDictionary(jsonDocuments(500), tempDir, null, DICTIONARY_SIZE), dictionarySet)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+for (const postProcessPadding of [true, false]) {
+  test(`sets compressed with a dictionary extract without naming it (postProcessPadding = ${postProcessPadding})`, (t) => {
+    const tempDir = createTempDir()
+    const plainDir = createTempDir()
+
+    try {
+      const dictionarySet = trainCompressionDictionary(jsonDocuments(500), tempDir, TEST_KEYS.BASIC, DICTIONARY_SIZE)
+      const document = jsonDocument(10_000)
+      const capsuleSet = createDataCapsule(document, tempDir, postProcessPadding, TEST_KEYS.BASIC, {
+        compression: { algorithm: 'zstd', dictionary: dictionarySet.id }
+      })
+      const plainSet = createDataCapsule(document, plainDir, postProcessPadding, TEST_KEYS.BASIC, {
+        compression: { algorithm: 'zstd' }
+      })
+
+      t.is(capsuleSet.metadata.compressionInfo.dictionary, dictionarySet.id)
+      t.true(capsuleSet.capsules[0].compressed)
+      if (postProcessPadding) {
+        // With inner padding the payload always fills the capsule
+        t.true(
+          getCapsuleFileInfo(capsuleFile(tempDir, capsuleSet)).dataSize <
+            getCapsuleFileInfo(capsuleFile(plainDir, plainSet)).dataSize,
+          'The dictionary should shrink the compressed document'
+        )
+      }
+
+      // The dictionary set is not a candidate when no set id is given
+      assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), document)
+      assertBuffersEqual(t, extractRange(tempDir, 10, 20, TEST_KEYS.BASIC), document.subarray(10, 30))
+      assertBuffersEqual(t, decodeCapsule(capsuleFile(tempDir, capsuleSet), TEST_KEYS.BASIC), document)
+    } finally {
+      cleanupTempDir(tempDir)
+      cleanupTempDir(plainDir)
+    }
+  })
+}
+
+test('extraction fails when the dictionary is missing', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const dictionarySet = trainCompressionDictionary(jsonDocuments(500), tempDir, null, DICTIONARY_SIZE)
+    createDataCapsule(jsonDocument(10_000), tempDir, true, null, {
+      compression: { algorithm: 'zstd', dictionary: dictionarySet.id }
+    })
+    const dictionaryMetadata = readdirSync(tempDir).find(
+      (file) => file.endsWith('_metadata.json') && dictionarySet.id.startsWith(file.split('_')[0])
+    )
+    unlinkSync(join(tempDir, dictionaryMetadata))
+
+    t.throws(() => extractDataCapsule(tempDir), {
+      message: new RegExp(`Compression dictionary ${dictionarySet.id} not found`),
+      code: 'CAPSULE_DICTIONARY_NOT_FOUND'
+    })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('dictionaries must exist and are only used with zstd', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const dataSet = createDataCapsule(jsonDocument(1), tempDir, true)
+    const document = jsonDocument(2)
+
+    t.throws(
+      () => createDataCapsule(document, tempDir, true, null, { compression: { algorithm: 'zstd', dictionary: 'ab'.repeat(32) } }),
+      { code: 'CAPSULE_DICTIONARY_NOT_FOUND' }
+    )
+    // Data sets are not dictionaries
+    t.throws(
+      () => createDataCapsule(document, tempDir, true, null, { compression: { algorithm: 'zstd', dictionary: dataSet.id } }),
+      { code: 'CAPSULE_DICTIONARY_NOT_FOUND' }
+    )
+    t.throws(
+      () => createDataCapsule(document, tempDir, true, null, { compression: { algorithm: 'gzip', dictionary: dataSet.id } }),
+      { message: /Compression dictionaries require zstd/, code: 'CAPSULE_INVALID_OPTION' }
+    )
+    t.throws(() => trainCompressionDictionary([Buffer.from('x')], tempDir), {
+      message: /Dictionary training failed/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -0,0 +1,219 @@
+import test from 'ava'
+import { join } from 'path'
+import {
+  chmodSync,
+  existsSync,
+  lstatSync,
+  mkdirSync,
+  readFileSync,
+  readlinkSync,
+  statSync,
+  symlinkSync,
+  utimesSync,
+  writeFileSync
+} from 'fs'
+import {
+  createDataCapsule,
+  createDataCapsuleFromDirectory,
+  extractArchiveEntry,
+  extractDataCapsuleToDirectory,
+  listArchiveEntries
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Directory Tree Tests
+
+const POSIX = process.platform !== 'win32'
+const MTIME = new Date(Date.UTC(2024, 5, 1, 12, 30, 15, 250))
+
+// A small site: nested files, an empty directory, an executable, a private
+// file and a symlink
+function createSiteTree(root) {
+  const files = {
+    'index.html': Buffer.from('<h1>home</h1>'),
+    'assets/app.js': Buffer.from('console.log("app")'),
+    'assets/img/logo.bin': createTestData(1536 * 1024),
+    'scripts/deploy.sh': Buffer.from('#!/bin/sh\necho deploy\n'),
+    'secrets.env': Buffer.from('TOKEN=1'),
+    'logs/access.log': Buffer.from('GET /'),
+    'node_modules/dep/index.js': Buffer.from('module.exports = 1')
+  }
+  for (const [name, data] of Object.entries(files)) {
+    mkdirSync(join(root, name, '..'), { recursive: true })
+    writeFileSync(join(root, name), data)
+  }
+  mkdirSync(join(root, 'uploads'))
+  utimesSync(join(root, 'index.html'), MTIME, MTIME)
+  if (POSIX) {
+    // The setuid bit is dropped; only permission bits are kept
+    chmodSync(join(root, 'scripts/deploy.sh'), 0o4755)
+    chmodSync(join(root, 'secrets.env'), 0o600)
+    symlinkSync('index.html', join(root, 'home.html'))
+  }
+  return files
+}
+
+for (const postProcessPadding of [true, false]) {
+  test(`directory trees round-trip with modes, times and symlinks (postProcessPadding = ${postProcessPadding})`, (t) => {
+    const inputDir = createTempDir()
+    const capsuleDir = createTempDir()
+    const outputDir = createTempDir()
+
+    try {
+      const files = createSiteTree(inputDir)
+      const capsuleSet = createDataCapsuleFromDirectory(inputDir, capsuleDir, postProcessPadding, TEST_KEYS.BASIC)
+      t.is(capsuleSet.metadata.kind, 'ARCHIVE')
+
+      const restoreDir = join(outputDir, 'site')
+      const restored = extractDataCapsuleToDirectory(capsuleDir, restoreDir, TEST_KEYS.BASIC)
+      t.deepEqual(restored, listArchiveEntries(capsuleDir, TEST_KEYS.BASIC))
+
+      for (const [name, data] of Object.entries(files)) {
+        assertBuffersEqual(t, readFileSync(join(restoreDir, name)), data)
+      }
+      t.true(statSync(join(restoreDir, 'uploads')).isDirectory(), 'Empty directories should be restored')
+      t.is(statSync(join(restoreDir, 'index.html')).mtime.getTime(), MTIME.getTime())
+
+      if (POSIX) {
+        t.is(statSync(join(restoreDir, 'scripts/deploy.sh')).mode & 0o7777, 0o755)
+        t.is(statSync(join(restoreDir, 'secrets.env')).mode & 0o777, 0o600)
+        t.true(lstatSync(join(restoreDir, 'home.html')).isSymbolicLink())
+        t.is(readlinkSync(join(restoreDir, 'home.html')), 'index.html')
+      }
+    } finally {
+      cleanupTempDir(inputDir)
+      cleanupTempDir(capsuleDir)
+      cleanupTempDir(outputDir)
+    }
+  })
+}
+
+test('the manifest records directories, symlinks and modification times', (t) => {
+  const inputDir = createTempDir()
+  const capsuleDir = createTempDir()
+
+  try {
+    createSiteTree(inputDir)
+    createDataCapsuleFromDirectory(inputDir, capsuleDir, true)
+    const entries = Object.fromEntries(listArchiveEntries(capsuleDir).map((entry) => [entry.name, entry]))
+
+    t.is(entries.assets.kind, 'directory')
+    t.is(entries.uploads.kind, 'directory')
+    t.is(entries['index.html'].kind, undefined)
+    t.is(entries['index.html'].mtime, MTIME.getTime())
+    if (POSIX) {
+      t.is(entries['home.html'].kind, 'symlink')
+      t.is(entries['home.html'].linkTarget, 'index.html')
+      t.is(entries['home.html'].size, 0)
+    }
+
+    // Entries of a directory set are read like any other archive entry
+    assertBuffersEqual(t, extractArchiveEntry(capsuleDir, 'assets/app.js'), Buffer.from('console.log("app")'))
+  } finally {
+    cleanupTempDir(inputDir)
+    cleanupTempDir(capsuleDir)
+  }
+})
+
+test('include and exclude globs pick the paths capsuled', (t) => {
+  const inputDir = createTempDir()
+  const capsuleDir = createTempDir()
+
+  try {
+    createSiteTree(inputDir)
+    createDataCapsuleFromDirectory(inputDir, join(capsuleDir, 'excluded'), true, null, null, {
+      exclude: ['node_modules', '**/*.log', '*.env']
+    })
+    const names = listArchiveEntries(join(capsuleDir, 'excluded')).map((entry) => entry.name)
+    t.false(names.some((name) => name.startsWith('node_modules')))
+    t.false(names.includes('logs/access.log'))
+    t.false(names.includes('secrets.env'))
+    t.true(names.includes('logs'))
+    t.true(names.includes('assets/img/logo.bin'))
+
+    // Included directories bring their contents; parents of included paths are kept
+    createDataCapsuleFromDirectory(inputDir, join(capsuleDir, 'included'), true, null, null, {
+      include: ['assets/img', '**/*.sh']
+    })
+    t.deepEqual(
+      listArchiveEntries(join(capsuleDir, 'included')).map((entry) => entry.name),
+      ['assets', 'assets/img', 'assets/img/logo.bin', 'scripts', 'scripts/deploy.sh']
+    )
+  } finally {
+    cleanupTempDir(inputDir)
+    cleanupTempDir(capsuleDir)
+  }
+})
+
+test('globs restore part of a tree', (t) => {
+  const inputDir = createTempDir()
+  const capsuleDir = createTempDir()
+  const outputDir = createTempDir()
+
+  try {
+    const files = createSiteTree(inputDir)
+    createDataCapsuleFromDirectory(inputDir, capsuleDir, true, TEST_KEYS.BASIC)
+
+    const restoreDir = join(outputDir, 'assets-only')
+    const restored = extractDataCapsuleToDirectory(capsuleDir, restoreDir, TEST_KEYS.BASIC, null, {
+      include: ['assets'],
+      exclude: ['**/*.bin']
+    })
+
+    t.deepEqual(
+      restored.map((entry) => entry.name),
+      ['assets', 'assets/app.js', 'assets/img']
+    )
+    assertBuffersEqual(t, readFileSync(join(restoreDir, 'assets/app.js')), files['assets/app.js'])
+    t.false(existsSync(join(restoreDir, 'assets/img/logo.bin')))
+    t.false(existsSync(join(restoreDir, 'index.html')))
+  } finally {
+    cleanupTempDir(inputDir)
+    cleanupTempDir(capsuleDir)
+    cleanupTempDir(outputDir)
+  }
+})
+
+test('invalid directories, globs and targets are rejected', (t) => {
+  const inputDir = createTempDir()
+  const capsuleDir = createTempDir()
+  const outputDir = createTempDir()
+
+  try {
+    createSiteTree(inputDir)
+
+    t.throws(() => createDataCapsuleFromDirectory(join(inputDir, 'index.html'), capsuleDir, true), {
+      message: /is not a directory/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+    t.throws(() => createDataCapsuleFromDirectory(inputDir, capsuleDir, true, null, null, { include: ['a[b'] }), {
+      message: /Invalid glob pattern a\[b/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+
+    createDataCapsuleFromDirectory(inputDir, capsuleDir, true)
+    writeFileSync(join(outputDir, 'existing.txt'), 'keep me')
+    t.throws(() => extractDataCapsuleToDirectory(capsuleDir, outputDir), {
+      message: /is not empty/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+    t.is(readFileSync(join(outputDir, 'existing.txt'), 'utf8'), 'keep me')
+
+    const dataDir = join(capsuleDir, 'plain')
+    createDataCapsule(Buffer.from('data'), dataDir, true)
+    t.throws(() => extractDataCapsuleToDirectory(dataDir, join(outputDir, 'plain')), {
+      message: /is not an archive/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+  } finally {
+    cleanupTempDir(inputDir)
+    cleanupTempDir(capsuleDir)
+    cleanupTempDir(outputDir)
+  }
+})
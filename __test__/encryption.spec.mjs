@@ -0,0 +1,399 @@
+import test from 'ava'
+import { join, dirname } from 'path'
+import { fileURLToPath } from 'url'
+import { randomBytes, createHash } from 'crypto'
+import { readFileSync, readdirSync, writeFileSync } from 'fs'
+import {
+  createDataCapsule,
+  createDataCapsuleFromFile,
+  extractDataCapsule,
+  extractDataCapsuleToFile,
+  loadCapsuleSet,
+  reconstructFileFromCapsules
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestFile,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+const __dirname = dirname(fileURLToPath(import.meta.url))
+const LEGACY_V1_DIR = join(__dirname, 'fixtures', 'legacy-v1')
+const LEGACY_V1_KEY = 'example-encryption-key-2024'
+
+// Encryption Scheme Tests
+
+test('encrypted sets record a per-set nonce prefix', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    createTestFile(TEST_SIZES.SMALL, inputFile)
+
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, outputDir, false, TEST_KEYS.BASIC)
+    const info = capsuleSet.metadata.encryptionInfo
+
+    t.truthy(info, 'Encrypted set should have encryption info')
+    t.is(info.nonceScheme, 'DIG_SET_PREFIX_V1', 'Should use the per-set nonce scheme')
+    t.is(info.noncePrefix.length, 16, 'Nonce prefix should be 8 bytes of hex')
+
+    const loaded = await loadCapsuleSet(outputDir)
+    t.is(loaded.metadata.encryptionInfo.noncePrefix, info.noncePrefix, 'Nonce prefix should be persisted in metadata')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('different inputs under the same key get different nonce prefixes', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const inputFile1 = join(tempDir, 'input1.dat')
+    const inputFile2 = join(tempDir, 'input2.dat')
+    createTestFile(TEST_SIZES.SMALL, inputFile1)
+    createTestFile(TEST_SIZES.SMALL, inputFile2)
+
+    const capsuleSet1 = await createDataCapsuleFromFile(inputFile1, join(tempDir, 'out1'), false, TEST_KEYS.BASIC)
+    const capsuleSet2 = await createDataCapsuleFromFile(inputFile2, join(tempDir, 'out2'), false, TEST_KEYS.BASIC)
+
+    t.not(
+      capsuleSet1.metadata.encryptionInfo.noncePrefix,
+      capsuleSet2.metadata.encryptionInfo.noncePrefix,
+      'Capsule 0 of different files must not share a nonce'
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('one input encoded two ways never reuses a keystream', (t) => {
+  const CAPSULE_HEADER_SIZE = 44
+  const tempDir = createTempDir()
+
+  try {
+    // Random data is stored uncompressed, so the plaintext under the
+    // ciphertext starts with the input in every encoding
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const keystreams = []
+    for (const postProcessPadding of [true, false]) {
+      for (const algorithm of ['gzip', 'zstd']) {
+        const outputDir = join(tempDir, `${postProcessPadding}-${algorithm}`)
+        const capsuleSet = createDataCapsule(testData, outputDir, postProcessPadding, TEST_KEYS.BASIC, {
+          compression: { algorithm }
+        })
+        t.false(capsuleSet.capsules[0].compressed)
+
+        const capsuleFile = readdirSync(outputDir).find((file) => file.endsWith('.capsule'))
+        const ciphertext = readFileSync(join(outputDir, capsuleFile)).subarray(CAPSULE_HEADER_SIZE)
+        const keystream = Buffer.alloc(1000)
+        for (let i = 0; i < keystream.length; i++) {
+          keystream[i] = ciphertext[i] ^ testData[i]
+        }
+        keystreams.push(keystream.toString('hex'))
+      }
+    }
+
+    t.is(new Set(keystreams).size, keystreams.length, 'Every encoding should use its own keystream')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('unencrypted sets have no encryption info', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+
+  try {
+    createTestFile(TEST_SIZES.TINY, inputFile)
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, join(tempDir, 'capsules'), false)
+    t.falsy(capsuleSet.metadata.encryptionInfo, 'No encryption info without a key')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('legacy DIG1-nonce capsule sets still extract', async (t) => {
+  const capsuleSet = await loadCapsuleSet(LEGACY_V1_DIR)
+  t.falsy(capsuleSet.metadata.encryptionInfo.nonceScheme, 'Legacy metadata predates nonce schemes')
+
+  const extracted = await extractDataCapsule(LEGACY_V1_DIR, LEGACY_V1_KEY)
+  t.is(extracted.length, capsuleSet.metadata.originalSize, 'Legacy set should extract to its original size')
+})
+
+test('roundtrip with per-set nonces preserves data', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    const originalData = createTestFile(TEST_SIZES.LARGE, inputFile)
+    await createDataCapsuleFromFile(inputFile, outputDir, false, TEST_KEYS.STRONG)
+
+    const extracted = await extractDataCapsule(outputDir, TEST_KEYS.STRONG)
+    assertBuffersEqual(t, extracted, originalData, 'Extracted data should match original')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('segmented encryption roundtrips in both padding modes', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    // Incompressible input spans several 64 KiB segments per capsule
+    const originalData = randomBytes(300 * 1024)
+
+    for (const postProcessPadding of [true, false]) {
+      const outputDir = join(tempDir, `capsules-${postProcessPadding}`)
+      await createDataCapsule(originalData, outputDir, postProcessPadding, TEST_KEYS.BASIC)
+
+      const extracted = await extractDataCapsule(outputDir, TEST_KEYS.BASIC)
+      assertBuffersEqual(t, extracted, originalData, `Roundtrip with postProcessPadding=${postProcessPadding}`)
+    }
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('tampered ciphertext segments fail authentication', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    const capsuleSet = await createDataCapsule(randomBytes(200 * 1024), outputDir, true, TEST_KEYS.BASIC)
+
+    // Flip a byte in the second segment of the payload
+    const capsuleFile = join(outputDir, `${capsuleSet.id}_000.capsule`)
+    const bytes = readFileSync(capsuleFile)
+    bytes[44 + 64 * 1024 + 10] ^= 0xff
+    writeFileSync(capsuleFile, bytes)
+
+    // Record the tampered file's hash so only the AEAD can catch it
+    capsuleSet.capsules[0].hash = createHash('sha256').update(bytes).digest('hex')
+    await t.throwsAsync(
+      async () => reconstructFileFromCapsules(capsuleSet, outputDir, join(tempDir, 'output.dat'), TEST_KEYS.BASIC),
+      { message: /Decryption failed/ }
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('new sets use real PBKDF2 with a per-set salt by default', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const inputFile1 = join(tempDir, 'input1.dat')
+    const inputFile2 = join(tempDir, 'input2.dat')
+    createTestFile(TEST_SIZES.TINY, inputFile1)
+    createTestFile(TEST_SIZES.TINY, inputFile2)
+
+    const capsuleSet1 = await createDataCapsuleFromFile(inputFile1, join(tempDir, 'out1'), false, TEST_KEYS.BASIC)
+    const capsuleSet2 = await createDataCapsuleFromFile(inputFile2, join(tempDir, 'out2'), false, TEST_KEYS.BASIC)
+    const info1 = capsuleSet1.metadata.encryptionInfo
+    const info2 = capsuleSet2.metadata.encryptionInfo
+
+    t.is(info1.keyDerivation, 'PBKDF2-HMAC-SHA256', 'Default KDF should be PBKDF2')
+    t.is(info1.iterations, 100000, 'Default PBKDF2 iteration count')
+    t.is(info1.salt.length, 32, 'Salt should be 16 bytes of hex')
+    t.not(info1.salt, info2.salt, 'Each set should get its own salt')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+for (const keyDerivation of [
+  { algorithm: 'PBKDF2-HMAC-SHA256', iterations: 2000 },
+  { algorithm: 'ARGON2ID', iterations: 1, memoryCost: 1024, parallelism: 1 },
+  { algorithm: 'SCRYPT', logN: 10, blockSize: 8, parallelism: 1 }
+]) {
+  test(`roundtrip with ${keyDerivation.algorithm} key derivation`, async (t) => {
+    const tempDir = createTempDir()
+    const inputFile = join(tempDir, 'input.dat')
+    const outputDir = join(tempDir, 'capsules')
+
+    try {
+      const originalData = createTestFile(TEST_SIZES.SMALL, inputFile)
+      const capsuleSet = await createDataCapsuleFromFile(inputFile, outputDir, false, TEST_KEYS.STRONG, { keyDerivation })
+      const info = capsuleSet.metadata.encryptionInfo
+
+      t.is(info.keyDerivation, keyDerivation.algorithm, 'Chosen KDF should be recorded')
+      for (const param of ['memoryCost', 'parallelism', 'logN', 'blockSize']) {
+        if (keyDerivation[param] !== undefined) {
+          t.is(info[param], keyDerivation[param], `${param} should be recorded`)
+        }
+      }
+
+      const extracted = await extractDataCapsule(outputDir, TEST_KEYS.STRONG)
+      assertBuffersEqual(t, extracted, originalData, 'Extracted data should match original')
+
+      await t.throwsAsync(
+        async () => await extractDataCapsule(outputDir, TEST_KEYS.BASIC),
+        undefined,
+        'Wrong key should fail'
+      )
+    } finally {
+      cleanupTempDir(tempDir)
+    }
+  })
+}
+
+test('unsupported key derivation is rejected', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+
+  try {
+    createTestFile(TEST_SIZES.TINY, inputFile)
+    await t.throwsAsync(
+      async () => await createDataCapsuleFromFile(inputFile, join(tempDir, 'capsules'), false, TEST_KEYS.BASIC, {
+        keyDerivation: { algorithm: 'MD5' }
+      }),
+      { message: /Unsupported key derivation/ }
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('scrypt parameters needing more than 1 GiB are rejected', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    const originalData = createTestData(TEST_SIZES.TINY)
+    // 128 * r * 2^logN * p = 128 * 32 * 2^22 = 16 GiB, although each parameter is within bounds
+    const hostile = { logN: 22, blockSize: 32, parallelism: 1 }
+    await t.throwsAsync(
+      async () => await createDataCapsule(originalData, join(tempDir, 'rejected'), false, TEST_KEYS.BASIC, {
+        keyDerivation: { algorithm: 'SCRYPT', ...hostile }
+      }),
+      { message: /Invalid key derivation parameters/ }
+    )
+
+    // Metadata edited to demand the same is refused before any key is derived
+    const capsuleSet = await createDataCapsule(originalData, outputDir, false, TEST_KEYS.BASIC, {
+      keyDerivation: { algorithm: 'SCRYPT', logN: 10, blockSize: 8, parallelism: 1 }
+    })
+    const metadataFile = join(outputDir, `${capsuleSet.id}_metadata.json`)
+    const metadata = JSON.parse(readFileSync(metadataFile, 'utf8'))
+    metadata.metadata.encryption_info.log_n = hostile.logN
+    metadata.metadata.encryption_info.block_size = hostile.blockSize
+    writeFileSync(metadataFile, JSON.stringify(metadata))
+
+    await t.throwsAsync(
+      async () => await extractDataCapsule(outputDir, TEST_KEYS.BASIC),
+      { message: /Invalid key derivation parameters/, code: 'CAPSULE_CONSENSUS_VIOLATION' }
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('Argon2id memory above 1 GiB is rejected', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    const originalData = createTestData(TEST_SIZES.TINY)
+    const hostileMemoryCost = 1024 * 1024 + 1 // 1 GiB + 1 KiB
+    await t.throwsAsync(
+      async () => await createDataCapsule(originalData, join(tempDir, 'rejected'), false, TEST_KEYS.BASIC, {
+        keyDerivation: { algorithm: 'ARGON2ID', iterations: 1, memoryCost: hostileMemoryCost, parallelism: 1 }
+      }),
+      { message: /Invalid key derivation parameters/ }
+    )
+
+    // Metadata edited to demand the same is refused before any key is derived
+    const capsuleSet = await createDataCapsule(originalData, outputDir, false, TEST_KEYS.BASIC, {
+      keyDerivation: { algorithm: 'ARGON2ID', iterations: 1, memoryCost: 64, parallelism: 1 }
+    })
+    const metadataFile = join(outputDir, `${capsuleSet.id}_metadata.json`)
+    const metadata = JSON.parse(readFileSync(metadataFile, 'utf8'))
+    metadata.metadata.encryption_info.memory_cost = hostileMemoryCost
+    writeFileSync(metadataFile, JSON.stringify(metadata))
+
+    await t.throwsAsync(
+      async () => await extractDataCapsule(outputDir, TEST_KEYS.BASIC),
+      { message: /Invalid key derivation parameters/, code: 'CAPSULE_CONSENSUS_VIOLATION' }
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('raw 32-byte keys bypass passphrase derivation', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+  const outputFile = join(tempDir, 'output.dat')
+
+  try {
+    const rawKey = randomBytes(32)
+    const originalData = createTestData(TEST_SIZES.SMALL)
+    const capsuleSet = await createDataCapsule(originalData, outputDir, false, rawKey)
+
+    t.is(capsuleSet.metadata.encryptionInfo.keyDerivation, 'RAW', 'Raw keys should be flagged in metadata')
+    t.true(capsuleSet.capsules.every(capsule => capsule.encrypted), 'Capsules should be encrypted')
+
+    await extractDataCapsuleToFile(outputDir, outputFile, rawKey)
+    const extracted = await extractDataCapsule(outputDir, rawKey)
+    assertBuffersEqual(t, extracted, originalData, 'Extracted data should match original')
+
+    await t.throwsAsync(
+      async () => await extractDataCapsule(outputDir, randomBytes(32)),
+      undefined,
+      'A different raw key should fail'
+    )
+    await t.throwsAsync(
+      async () => await extractDataCapsule(outputDir, rawKey.toString('hex')),
+      { message: /raw binary key/ },
+      'A passphrase should not open a raw-key set'
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('raw keys must be 32 bytes', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    await t.throwsAsync(
+      async () => await createDataCapsule(createTestData(TEST_SIZES.TINY), join(tempDir, 'capsules'), false, randomBytes(16)),
+      { message: /Invalid key.*32 bytes/ }
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('raw keys can be expanded per set with HKDF', async (t) => {
+  const tempDir = createTempDir()
+  const outputDir = join(tempDir, 'capsules')
+
+  try {
+    const rawKey = randomBytes(32)
+    const originalData = createTestData(TEST_SIZES.SMALL)
+    const capsuleSet = await createDataCapsule(originalData, outputDir, false, rawKey, {
+      keyDerivation: { algorithm: 'HKDF-SHA256' }
+    })
+
+    t.is(capsuleSet.metadata.encryptionInfo.keyDerivation, 'HKDF-SHA256', 'HKDF should be recorded')
+    const extracted = await extractDataCapsule(outputDir, rawKey)
+    assertBuffersEqual(t, extracted, originalData, 'Extracted data should match original')
+
+    await t.throwsAsync(
+      async () => await createDataCapsule(originalData, join(tempDir, 'passphrase'), false, TEST_KEYS.STRONG, {
+        keyDerivation: { algorithm: 'HKDF-SHA256' }
+      }),
+      { message: /HKDF-SHA256 requires a raw binary key/, code: 'CAPSULE_INVALID_OPTION' },
+      'Passphrases must go through a password-hashing KDF'
+    )
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -1,10 +1,14 @@
 import test from 'ava'
 import { join } from 'path'
+import { readFileSync, writeFileSync } from 'fs'
 import { 
   createDataCapsuleFromFile,
+  extractDataCapsule,
+  extractDataCapsuleAsync,
   extractDataCapsuleToFile,
   loadCapsuleSet,
-  reconstructFileFromCapsules
+  reconstructFileFromCapsules,
+  startExtractDataCapsuleToFile
 } from '../index.js'
 import { 
   createTempDir, 
@@ -121,7 +125,7 @@ test('extractDataCapsuleToFile fails with missing capsule files', async (t) => {
     // Remove one of the capsule files to simulate corruption/missing file
     if (capsuleSet.metadata.capsuleCount > 1) {
       const fs = await import('fs')
-      const capsuleToRemove = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_001.capsule`)
+      const capsuleToRemove = join(capsulesDir, `${capsuleSet.id}_001.capsule`)
       fs.unlinkSync(capsuleToRemove)
       
       // Try to extract - should fail due to missing capsule
@@ -176,7 +180,7 @@ test('loadCapsuleSet fails with corrupted metadata file', async (t) => {
     
     // Corrupt the metadata file
     const fs = await import('fs')
-    const metadataFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_metadata.json`)
+    const metadataFile = join(capsulesDir, `${capsuleSet.id}_metadata.json`)
     fs.writeFileSync(metadataFile, 'invalid json content')
     
     // Try to load - should fail due to corrupted metadata
@@ -235,7 +239,7 @@ test('extractDataCapsuleToFile handles corrupted capsule data gracefully', async
     
     // Corrupt a capsule file by overwriting it with random data
     const fs = await import('fs')
-    const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_000.capsule`)
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
     fs.writeFileSync(capsuleFile, Buffer.from('corrupted data'))
     
     // Try to extract - should fail gracefully
@@ -250,6 +254,110 @@ test('extractDataCapsuleToFile handles corrupted capsule data gracefully', async
   }
 })
 
+test('extraction names the capsule whose hash does not match', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  const outputFile = join(tempDir, 'output.dat')
+  
+  try {
+    createTestFile(TEST_SIZES.LARGE, inputFile)
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
+    t.true(capsuleSet.metadata.capsuleCount > 1, 'Should create multiple capsules')
+    
+    // Flip the last padding byte of capsule 1: decoding alone would never notice
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_001.capsule`)
+    const bytes = readFileSync(capsuleFile)
+    bytes[bytes.length - 1] ^= 0xff
+    writeFileSync(capsuleFile, bytes)
+    
+    await t.throwsAsync(
+      async () => extractDataCapsule(capsulesDir, TEST_KEYS.BASIC),
+      { message: /Capsule hash mismatch at index 1/, code: 'CAPSULE_HASH_MISMATCH' },
+      'Extraction should report the corrupted capsule'
+    )
+    await t.throwsAsync(
+      async () => reconstructFileFromCapsules(capsuleSet, capsulesDir, outputFile, TEST_KEYS.BASIC),
+      { message: /Capsule hash mismatch at index 1/ },
+      'Reconstruction should report the corrupted capsule'
+    )
+    
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('errors carry a stable code and their context', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  const outputFile = join(tempDir, 'output.dat')
+  
+  try {
+    const missingFile = join(tempDir, 'missing.dat')
+    const ioError = t.throws(
+      () => createDataCapsuleFromFile(missingFile, capsulesDir, false, null),
+      { code: 'CAPSULE_IO_ERROR' }
+    )
+    t.true(ioError.message.includes(missingFile), 'IO errors should name the file')
+    
+    createTestFile(TEST_SIZES.LARGE, inputFile)
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, false, TEST_KEYS.BASIC)
+    
+    t.throws(
+      () => extractDataCapsule(capsulesDir, TEST_KEYS.STRONG),
+      { message: /Decryption failed for capsule 0/, code: 'CAPSULE_DECRYPT_FAILED' }
+    )
+    
+    const wrongChecksum = 'ab'.repeat(32)
+    const checksumError = t.throws(
+      () => reconstructFileFromCapsules(
+        { ...capsuleSet, metadata: { ...capsuleSet.metadata, checksum: wrongChecksum } },
+        capsulesDir,
+        outputFile,
+        TEST_KEYS.BASIC
+      ),
+      { code: 'CAPSULE_CHECKSUM_MISMATCH' }
+    )
+    t.true(checksumError.message.includes(wrongChecksum), 'Should report the expected checksum')
+    t.true(checksumError.message.includes(capsuleSet.metadata.checksum), 'Should report the actual checksum')
+    
+    const metadataDir = join(tempDir, 'metadata')
+    const fs = await import('fs')
+    fs.mkdirSync(metadataDir)
+    writeFileSync(join(metadataDir, 'broken_metadata.json'), '{ not json')
+    t.throws(() => loadCapsuleSet(metadataDir), { code: 'CAPSULE_INVALID_METADATA' })
+    
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('async rejections and operation handles report the same codes', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  
+  try {
+    createTestFile(TEST_SIZES.SMALL, inputFile)
+    await createDataCapsuleFromFile(inputFile, capsulesDir, false, TEST_KEYS.BASIC)
+    
+    await t.throwsAsync(
+      extractDataCapsuleAsync(capsulesDir, TEST_KEYS.STRONG),
+      { message: /Decryption failed/, code: 'CAPSULE_DECRYPT_FAILED' }
+    )
+    
+    const handle = startExtractDataCapsuleToFile(capsulesDir, join(tempDir, 'output.dat'), TEST_KEYS.STRONG)
+    while (!handle.isCompleted() && !handle.hasError()) {
+      await new Promise((resolve) => setTimeout(resolve, 5))
+    }
+    t.is(handle.getErrorCode(), 'CAPSULE_DECRYPT_FAILED')
+    
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
 test('createDataCapsuleFromFile handles invalid encryption key gracefully', async (t) => {
   const tempDir = createTempDir()
   const inputFile = join(tempDir, 'input.dat')
@@ -1,5 +1,5 @@
 import test from 'ava'
-import { readFileSync } from 'fs'
+import { readFileSync, readdirSync } from 'fs'
 import { join } from 'path'
 import { 
   createDataCapsuleFromFile,
@@ -47,7 +47,7 @@ test('createDataCapsuleFromFile handles small file with encryption', async (t) =
     
     // Verify capsule properties
     t.true(capsuleSet.capsules[0].encrypted, 'Capsule should be marked as encrypted')
-    t.true(capsuleSet.capsules[0].compressed, 'Capsule should be marked as compressed')
+    t.false(capsuleSet.capsules[0].compressed, 'Random data should be stored uncompressed')
     
   } finally {
     cleanupTempDir(tempDir)
@@ -74,7 +74,7 @@ test('createDataCapsuleFromFile handles unencrypted files', async (t) => {
     
     // Verify capsule properties
     t.false(capsuleSet.capsules[0].encrypted, 'Capsule should not be marked as encrypted')
-    t.true(capsuleSet.capsules[0].compressed, 'Capsule should be marked as compressed')
+    t.false(capsuleSet.capsules[0].compressed, 'Random data should be stored uncompressed')
     
   } finally {
     cleanupTempDir(tempDir)
@@ -223,7 +223,7 @@ test('file operations handle multiple capsules correctly', async (t) => {
     capsuleSet.capsules.forEach((capsule, index) => {
       t.is(capsule.index, index, `Capsule ${index} should have correct index`)
       t.true(capsule.encrypted, `Capsule ${index} should be encrypted`)
-      t.true(capsule.compressed, `Capsule ${index} should be compressed`)
+      t.false(capsule.compressed, `Capsule ${index} of random data should be stored uncompressed`)
     })
     
     // Reconstruct and verify
@@ -236,6 +236,29 @@ test('file operations handle multiple capsules correctly', async (t) => {
   }
 })
 
+test('createDataCapsuleFromFile leaves only final capsule files behind', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  
+  try {
+    createTestFile(TEST_SIZES.LARGE, inputFile)
+    
+    const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
+    
+    // Capsules are written under temporary names and renamed once the set id is confirmed
+    const entries = readdirSync(capsulesDir).sort()
+    const expected = capsuleSet.capsules.map(
+      (capsule) => `${capsuleSet.id}_${capsule.index.toString().padStart(3, '0')}.capsule`
+    )
+    expected.push(`${capsuleSet.id}_metadata.json`)
+    t.deepEqual(entries, expected.sort(), 'No temporary files should remain')
+    
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
 test('file operations preserve file permissions and metadata', async (t) => {
   const tempDir = createTempDir()
   const inputFile = join(tempDir, 'input.dat')
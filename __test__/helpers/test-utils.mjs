@@ -83,7 +83,7 @@ export function assertValidCapsuleSet(t, capsuleSet, expectedSize = null) {
   t.is(capsuleSet.id.length, 64, 'ID should be 64-char SHA256 hex string')
   t.true(Array.isArray(capsuleSet.capsules), 'Capsules should be array')
   t.truthy(capsuleSet.metadata, 'Metadata should exist')
-  t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V1', 'Should have correct consensus version')
+  t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V2', 'Should have correct consensus version')
   t.is(capsuleSet.metadata.chunkingAlgorithm, 'DIG_DETERMINISTIC_V1', 'Should have correct chunking algorithm')
   
   if (expectedSize !== null) {
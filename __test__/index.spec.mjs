@@ -64,7 +64,7 @@ test('getCapsuleSizes returns correct fixed sizes', (t) => {
 
 test('getConsensusVersion returns valid version', (t) => {
   const version = getConsensusVersion()
-  t.is(version, 'DIG_CAPSULE_V1')
+  t.is(version, 'DIG_CAPSULE_V2')
 })
 
 test('calculateStorageOverhead returns correct values', (t) => {
@@ -94,7 +94,7 @@ test('createDataCapsuleFromFile handles small file with encryption', async (t) =
     t.is(capsuleSet.id.length, 64) // SHA256 hex string
     t.is(capsuleSet.metadata.originalSize, 150 * 1024)
     t.is(capsuleSet.metadata.capsuleCount, 1)
-    t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V1')
+    t.is(capsuleSet.metadata.consensusVersion, 'DIG_CAPSULE_V2')
     t.is(capsuleSet.metadata.chunkingAlgorithm, 'DIG_DETERMINISTIC_V1')
     
     // Verify encryption info
@@ -109,10 +109,10 @@ test('createDataCapsuleFromFile handles small file with encryption', async (t) =
     t.is(capsuleSet.metadata.compressionInfo.level, 6)
     
     // Verify capsule files exist
-    const metadataFile = join(outputDir, `${capsuleSet.id.substring(0, 16)}_metadata.json`)
+    const metadataFile = join(outputDir, `${capsuleSet.id}_metadata.json`)
     t.true(existsSync(metadataFile))
     
-    const capsuleFile = join(outputDir, `${capsuleSet.id.substring(0, 16)}_000.capsule`)
+    const capsuleFile = join(outputDir, `${capsuleSet.id}_000.capsule`)
     t.true(existsSync(capsuleFile))
     
   } finally {
@@ -279,7 +279,7 @@ test('isValidCapsuleFile identifies valid capsule files', async (t) => {
     
     // Check capsule files
     for (let i = 0; i < capsuleSet.metadata.capsuleCount; i++) {
-      const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_${i.toString().padStart(3, '0')}.capsule`)
+      const capsuleFile = join(capsulesDir, `${capsuleSet.id}_${i.toString().padStart(3, '0')}.capsule`)
       const isValid = await isValidCapsuleFile(capsuleFile)
       t.true(isValid, `Capsule file ${i} should be valid`)
     }
@@ -328,16 +328,16 @@ test('getCapsuleFileInfo extracts correct header information', async (t) => {
     const capsuleSet = await createDataCapsuleFromFile(inputFile, capsulesDir, true, encryptionKey) // postProcessPadding = true
     
     // Get info from first capsule
-    const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_000.capsule`)
+    const capsuleFile = join(capsulesDir, `${capsuleSet.id}_000.capsule`)
     const info = await getCapsuleFileInfo(capsuleFile)
     
     t.truthy(info, 'Should return capsule info')
     t.is(info.magic, '4449474341503031', 'Magic should be DIGCAP01 in hex') // "DIGCAP01" 
-    t.is(info.version, 1, 'Version should be 1')
+    t.is(info.version, 2, 'Version should be 2')
     t.is(info.capsuleIndex, 0, 'Index should be 0')
     t.is(info.capsuleSize, 256 * 1024, 'Size should be 256KB')
     t.true(info.isEncrypted, 'Should be encrypted')
-    t.true(info.isCompressed, 'Should be compressed')
+    t.false(info.isCompressed, 'Random data should be stored uncompressed')
     
   } finally {
     cleanupTempDir(tempDir)
@@ -378,7 +378,7 @@ test('capsule headers maintain format consistency', async (t) => {
     
     // Verify all capsule files have consistent headers
     for (let i = 0; i < capsuleSet.metadata.capsuleCount; i++) {
-      const capsuleFile = join(capsulesDir, `${capsuleSet.id.substring(0, 16)}_${i.toString().padStart(3, '0')}.capsule`)
+      const capsuleFile = join(capsulesDir, `${capsuleSet.id}_${i.toString().padStart(3, '0')}.capsule`)
       const info = await getCapsuleFileInfo(capsuleFile)
       
       // Get the expected capsule size for this specific capsule
@@ -386,11 +386,11 @@ test('capsule headers maintain format consistency', async (t) => {
       
       t.truthy(info, `Capsule ${i} should have valid header`)
       t.is(info.magic, '4449474341503031', `Capsule ${i} should have correct magic`)
-      t.is(info.version, 1, `Capsule ${i} should have correct version`)
+      t.is(info.version, 2, `Capsule ${i} should have correct version`)
       t.is(info.capsuleIndex, i, `Capsule ${i} should have correct index`)
       t.is(info.capsuleSize, expectedCapsuleSize, `Capsule ${i} should have correct size`)
       t.true(info.isEncrypted, `Capsule ${i} should be encrypted`)
-      t.true(info.isCompressed, `Capsule ${i} should be compressed`)
+      t.false(info.isCompressed, `Capsule ${i} of random data should be stored uncompressed`)
     }
     
   } finally {
@@ -491,11 +491,11 @@ test('postProcessPadding modes produce different but valid results', async (t) =
     // Create with postProcessPadding = true
     const capsuleSet2 = await createDataCapsuleFromFile(inputFile, outputDir2, true, encryptionKey)
     
-    // Should have same processing and checksums (since padding mode is now consistent)
+    // Should have same original checksums
     t.is(capsuleSet1.metadata.checksum, capsuleSet2.metadata.checksum)
     
-    // Since padding is now always post-process, capsule hashes should be identical
-    t.is(capsuleSet1.capsules[0].hash, capsuleSet2.capsules[0].hash)
+    // The padding modes place padding differently, so capsule hashes differ
+    t.not(capsuleSet1.capsules[0].hash, capsuleSet2.capsules[0].hash)
     
     // Both should reconstruct correctly
     await extractDataCapsuleToFile(outputDir1, reconstruct1, encryptionKey)
@@ -528,7 +528,7 @@ test('legacy createDataCapsule still works for small data', async (t) => {
     t.is(capsuleSet.metadata.originalSize, 100 * 1024)
     t.is(capsuleSet.metadata.capsuleCount, 1)
     t.true(capsuleSet.capsules[0].encrypted)
-    t.true(capsuleSet.capsules[0].compressed)
+    t.false(capsuleSet.capsules[0].compressed)
   } finally {
     cleanupTempDir(tempDir)
   }
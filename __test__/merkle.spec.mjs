@@ -0,0 +1,147 @@
+import test from 'ava'
+import {
+  createDataCapsule,
+  loadCapsuleSet,
+  validateConsensusParameters,
+  getCapsuleMerkleRoot,
+  createCapsuleMerkleProof,
+  verifyCapsuleMerkleProof
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Merkle Root and Inclusion Proof Tests
+
+test('capsule sets record a Merkle root over their capsule hashes', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(750 * 1024), tempDir, true, TEST_KEYS.BASIC)
+
+    t.regex(capsuleSet.metadata.merkleRoot, /^[0-9a-f]{64}$/, 'Root should be a hex SHA-256')
+    t.is(getCapsuleMerkleRoot(capsuleSet), capsuleSet.metadata.merkleRoot, 'Root should be recomputable')
+
+    const loaded = loadCapsuleSet(tempDir)
+    t.is(loaded.metadata.merkleRoot, capsuleSet.metadata.merkleRoot, 'Root should be saved with the metadata')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('inclusion proofs verify for every capsule', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    // 5 MB gives five capsules, exercising an unpaired node at each level
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.MULTI_MB), tempDir, true)
+    const root = capsuleSet.metadata.merkleRoot
+
+    for (const capsule of capsuleSet.capsules) {
+      const proof = createCapsuleMerkleProof(capsuleSet, capsule.index)
+      t.is(proof.capsuleIndex, capsule.index)
+      t.is(proof.capsuleHash, capsule.hash)
+      t.is(proof.leafCount, capsuleSet.capsules.length)
+      t.true(verifyCapsuleMerkleProof(root, capsuleSet.metadata.capsuleCount, proof), `Proof for capsule ${capsule.index} should verify`)
+    }
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('single capsule sets have a proof with no siblings', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true)
+    const proof = createCapsuleMerkleProof(capsuleSet, 0)
+
+    t.deepEqual(proof.siblings, [])
+    t.true(verifyCapsuleMerkleProof(capsuleSet.metadata.merkleRoot, 1, proof))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('tampered proofs do not verify', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(750 * 1024), tempDir, true)
+    const root = capsuleSet.metadata.merkleRoot
+    const count = capsuleSet.metadata.capsuleCount
+    const proof = createCapsuleMerkleProof(capsuleSet, 1)
+
+    const otherHash = capsuleSet.capsules[2].hash
+    t.false(verifyCapsuleMerkleProof(root, count, { ...proof, capsuleHash: otherHash }), 'Wrong capsule hash')
+    t.false(verifyCapsuleMerkleProof(root, count, { ...proof, capsuleIndex: 2 }), 'Wrong capsule index')
+    t.false(verifyCapsuleMerkleProof(root, count, { ...proof, leafCount: 2 }), 'Wrong leaf count')
+    t.false(verifyCapsuleMerkleProof(root, count, { ...proof, siblings: proof.siblings.slice(1) }), 'Missing sibling')
+    t.false(verifyCapsuleMerkleProof('0'.repeat(64), count, proof), 'Wrong root')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('proofs cannot move a capsule to another index with a smaller leaf count', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    // Three capsules: capsule 2 is unpaired and its only sibling is the node
+    // over capsules 0 and 1, which a two-leaf tree would pair it with at index 1
+    const capsuleSet = createDataCapsule(createTestData(750 * 1024), tempDir, true)
+    const root = capsuleSet.metadata.merkleRoot
+    const proof = createCapsuleMerkleProof(capsuleSet, 2)
+    const forged = { ...proof, capsuleIndex: 1, leafCount: 2 }
+
+    t.is(capsuleSet.metadata.capsuleCount, 3)
+    t.true(verifyCapsuleMerkleProof(root, 3, proof))
+    t.false(verifyCapsuleMerkleProof(root, 3, forged), 'Leaf count must match the trusted capsule count')
+    t.false(verifyCapsuleMerkleProof(root, 3, { ...proof, leafCount: 4 }))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('proof generation rejects out of range indices', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true)
+
+    t.throws(() => createCapsuleMerkleProof(capsuleSet, 1), { message: /Invalid format/ })
+    t.throws(() => verifyCapsuleMerkleProof('not-hex', 1, createCapsuleMerkleProof(capsuleSet, 0)), {
+      message: /Invalid format/
+    })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('consensus validation rejects a mismatched Merkle root', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(750 * 1024), tempDir, true)
+    t.true(validateConsensusParameters(capsuleSet))
+
+    const reordered = {
+      ...capsuleSet,
+      capsules: capsuleSet.capsules.map((capsule, index) => ({
+        ...capsule,
+        hash: capsuleSet.capsules[(index + 1) % capsuleSet.capsules.length].hash
+      }))
+    }
+    t.throws(() => validateConsensusParameters(reordered), { message: /Merkle root mismatch/ })
+
+    // Sets saved before Merkle roots were recorded still validate
+    const legacy = { ...capsuleSet, metadata: { ...capsuleSet.metadata, merkleRoot: undefined } }
+    t.true(validateConsensusParameters(legacy))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
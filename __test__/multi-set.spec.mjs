@@ -0,0 +1,137 @@
+import test from 'ava'
+import { join } from 'path'
+import { readFileSync } from 'fs'
+import {
+  createDataCapsule,
+  extractDataCapsule,
+  extractDataCapsuleAsync,
+  extractDataCapsuleToFile,
+  extractRange,
+  listCapsuleSets,
+  loadCapsuleSet
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Multiple Capsule Sets per Directory Tests
+
+function createSets(dir) {
+  const first = createTestData(TEST_SIZES.SMALL)
+  const second = Buffer.from('a second capsule set in the same directory')
+  return [
+    { data: first, capsuleSet: createDataCapsule(first, dir, true, TEST_KEYS.BASIC) },
+    { data: second, capsuleSet: createDataCapsule(second, dir, true) }
+  ]
+}
+
+test('listCapsuleSets returns every set in the directory ordered by id', (t) => {
+  const tempDir = createTempDir()
+  const emptyDir = createTempDir()
+
+  try {
+    const sets = createSets(tempDir)
+    const listed = listCapsuleSets(tempDir)
+
+    t.deepEqual(
+      listed.map((capsuleSet) => capsuleSet.id),
+      sets.map(({ capsuleSet }) => capsuleSet.id).sort()
+    )
+    t.deepEqual(listCapsuleSets(emptyDir), [])
+  } finally {
+    cleanupTempDir(tempDir)
+    cleanupTempDir(emptyDir)
+  }
+})
+
+test('extraction without a set id is rejected when several sets are present', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    createSets(tempDir)
+
+    const expected = { message: /Ambiguous capsule set: 2 sets match/, code: 'CAPSULE_AMBIGUOUS_SET' }
+    t.throws(() => extractDataCapsule(tempDir, TEST_KEYS.BASIC), expected)
+    t.throws(() => loadCapsuleSet(tempDir), expected)
+    await t.throwsAsync(() => extractDataCapsuleAsync(tempDir, TEST_KEYS.BASIC), expected)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('a set id or unique id prefix selects the set to read', async (t) => {
+  const tempDir = createTempDir()
+  const outputFile = join(tempDir, 'output.dat')
+
+  try {
+    const [first, second] = createSets(tempDir)
+
+    assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC, first.capsuleSet.id), first.data)
+    assertBuffersEqual(t, extractDataCapsule(tempDir, null, second.capsuleSet.id.slice(0, 8)), second.data)
+    assertBuffersEqual(
+      t,
+      await extractDataCapsuleAsync(tempDir, null, second.capsuleSet.id.toUpperCase()),
+      second.data
+    )
+
+    extractDataCapsuleToFile(tempDir, outputFile, TEST_KEYS.BASIC, first.capsuleSet.id)
+    assertBuffersEqual(t, readFileSync(outputFile), first.data)
+
+    assertBuffersEqual(t, extractRange(tempDir, 2, 6, null, second.capsuleSet.id), second.data.subarray(2, 8))
+    t.is(loadCapsuleSet(tempDir, first.capsuleSet.id).id, first.capsuleSet.id)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('an unknown set id is rejected', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const [first] = createSets(tempDir)
+    const unknownId = first.capsuleSet.id.startsWith('0') ? 'f' : '0'
+
+    t.throws(() => extractDataCapsule(tempDir, null, unknownId), {
+      message: new RegExp(`Capsule set ${unknownId} not found`),
+      code: 'CAPSULE_SET_NOT_FOUND'
+    })
+
+    // A metadata file path names its set, so a different id cannot match
+    const metadataFile = join(tempDir, `${first.capsuleSet.id}_metadata.json`)
+    t.throws(() => loadCapsuleSet(metadataFile, unknownId), { code: 'CAPSULE_SET_NOT_FOUND' })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('a directory without capsule set metadata is rejected', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const expected = { message: /Capsule set metadata not found/, code: 'CAPSULE_METADATA_NOT_FOUND' }
+    t.throws(() => loadCapsuleSet(tempDir), expected)
+    t.throws(() => extractDataCapsule(tempDir, TEST_KEYS.BASIC), expected)
+    await t.throwsAsync(() => extractDataCapsuleAsync(tempDir, TEST_KEYS.BASIC), expected)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('a directory with a single set needs no set id', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const capsuleSet = createDataCapsule(testData, tempDir, true)
+
+    assertBuffersEqual(t, extractDataCapsule(tempDir), testData)
+    assertBuffersEqual(t, extractDataCapsule(tempDir, null, capsuleSet.id), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -0,0 +1,188 @@
+import test from 'ava'
+import { join, dirname } from 'path'
+import { fileURLToPath } from 'url'
+import { existsSync, readdirSync, readFileSync, writeFileSync } from 'fs'
+import { createDataCapsule, decodeCapsule, extractDataCapsule, loadCapsuleSet } from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Capsule File Naming and Collision Tests
+
+const __dirname = dirname(fileURLToPath(import.meta.url))
+const LEGACY_V1_DIR = join(__dirname, 'fixtures', 'legacy-v1')
+const LEGACY_V1_KEY = 'example-encryption-key-2024'
+
+function expectedCapsulePath(dir, capsuleSet, capsule) {
+  const index = String(capsule.index).padStart(3, '0')
+  switch (capsuleSet.metadata.namingScheme) {
+    case 'SET_ID':
+      return join(dir, `${capsuleSet.id}_${index}.capsule`)
+    case 'CAPSULE_HASH':
+      return join(dir, `${capsule.hash}.capsule`)
+    case 'SET_DIRECTORY':
+      return join(dir, capsuleSet.id, `${index}.capsule`)
+  }
+}
+
+test('new sets are named by the full set id by default', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true)
+
+    t.is(capsuleSet.metadata.namingScheme, 'SET_ID')
+    t.deepEqual(readdirSync(tempDir).sort(), [`${capsuleSet.id}_000.capsule`, `${capsuleSet.id}_metadata.json`])
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('the 16 digit prefix naming is only read for legacy sets', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    t.throws(() => createDataCapsule(testData, tempDir, true, null, { namingScheme: 'SET_ID_PREFIX' }), {
+      message: /Naming scheme SET_ID_PREFIX is only supported for existing sets/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+
+    // Sets without a recorded scheme still load from their prefixed files
+    const legacySet = loadCapsuleSet(LEGACY_V1_DIR)
+    t.true(existsSync(join(LEGACY_V1_DIR, `${legacySet.id.substring(0, 16)}_000.capsule`)))
+    t.is(extractDataCapsule(LEGACY_V1_DIR, LEGACY_V1_KEY).length, legacySet.metadata.originalSize)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+for (const namingScheme of ['SET_ID', 'CAPSULE_HASH', 'SET_DIRECTORY']) {
+  test(`${namingScheme} naming round-trips and records the scheme`, (t) => {
+    const tempDir = createTempDir()
+
+    try {
+      const testData = createTestData(TEST_SIZES.MULTI_MB)
+      const capsuleSet = createDataCapsule(testData, tempDir, true, TEST_KEYS.BASIC, { namingScheme })
+
+      t.is(capsuleSet.metadata.namingScheme, namingScheme)
+      t.true(existsSync(join(tempDir, `${capsuleSet.id}_metadata.json`)))
+      for (const capsule of capsuleSet.capsules) {
+        t.true(existsSync(expectedCapsulePath(tempDir, capsuleSet, capsule)))
+      }
+
+      t.deepEqual(loadCapsuleSet(tempDir), capsuleSet)
+      assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), testData)
+
+      // Single capsules find their metadata from the file name alone
+      const lastCapsule = capsuleSet.capsules[capsuleSet.capsules.length - 1]
+      const decoded = decodeCapsule(expectedCapsulePath(tempDir, capsuleSet, lastCapsule), TEST_KEYS.BASIC)
+      assertBuffersEqual(t, decoded, testData.subarray(lastCapsule.index * 1024 * 1024))
+    } finally {
+      cleanupTempDir(tempDir)
+    }
+  })
+}
+
+test('re-creating an identical set is not a conflict', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const first = createDataCapsule(testData, tempDir, true)
+    const second = createDataCapsule(testData, tempDir, true)
+
+    t.deepEqual(second, first)
+    assertBuffersEqual(t, extractDataCapsule(tempDir), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('FAIL rejects a new set when existing files have different contents', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const existing = createDataCapsule(testData, tempDir, true, TEST_KEYS.BASIC)
+    const filesBefore = readdirSync(tempDir).sort()
+
+    // Same data, different key: same set id, different capsule contents
+    t.throws(() => createDataCapsule(testData, tempDir, true, TEST_KEYS.STRONG, { onConflict: 'fail' }), {
+      message: /File conflict: .* already exists with different contents/,
+      code: 'CAPSULE_FILE_CONFLICT'
+    })
+
+    // Nothing was replaced and no temporary files were left behind
+    t.deepEqual(readdirSync(tempDir).sort(), filesBefore)
+    t.deepEqual(loadCapsuleSet(tempDir), existing)
+    assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('SKIP keeps the existing set by default and OVERWRITE replaces it', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const existing = createDataCapsule(testData, tempDir, true, TEST_KEYS.BASIC)
+
+    t.deepEqual(createDataCapsule(testData, tempDir, true, TEST_KEYS.STRONG), existing)
+    const skipped = createDataCapsule(testData, tempDir, true, TEST_KEYS.STRONG, { onConflict: 'SKIP' })
+    t.deepEqual(skipped, existing)
+    assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), testData)
+
+    const replaced = createDataCapsule(testData, tempDir, true, TEST_KEYS.STRONG, { onConflict: 'OVERWRITE' })
+    t.notDeepEqual(replaced.capsules, existing.capsules)
+    assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.STRONG), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('SKIP replaces an existing set whose capsules are damaged', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    const existing = createDataCapsule(testData, tempDir, true, TEST_KEYS.BASIC)
+    const damagedFile = join(tempDir, `${existing.id}_000.capsule`)
+    const damaged = readFileSync(damagedFile)
+    damaged[damaged.length - 1] ^= 0xff
+    writeFileSync(damagedFile, damaged)
+
+    const replaced = createDataCapsule(testData, tempDir, true, TEST_KEYS.STRONG)
+
+    t.notDeepEqual(replaced.capsules, existing.capsules)
+    t.deepEqual(loadCapsuleSet(tempDir), replaced)
+    assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.STRONG), testData)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('unknown naming schemes and conflict policies are rejected', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+
+    t.throws(() => createDataCapsule(testData, tempDir, true, null, { namingScheme: 'RANDOM' }), {
+      message: /Unsupported naming scheme: RANDOM/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+    t.throws(() => createDataCapsule(testData, tempDir, true, null, { onConflict: 'MERGE' }), {
+      message: /Unsupported conflict policy: MERGE/,
+      code: 'CAPSULE_INVALID_OPTION'
+    })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -0,0 +1,178 @@
+import test from 'ava'
+import { readFileSync, readdirSync, existsSync } from 'fs'
+import { join } from 'path'
+import {
+  loadCapsuleSet,
+  startCreateDataCapsule,
+  startCreateDataCapsuleFromFile,
+  startExtractDataCapsule,
+  startExtractDataCapsuleToFile,
+  startReconstructFileFromCapsules
+} from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestFile,
+  createTestData,
+  assertValidCapsuleSet,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Progress Reporting and Cancellation Tests
+
+async function waitForHandle(handle) {
+  while (!handle.isCompleted()) {
+    await new Promise((resolve) => setTimeout(resolve, 5))
+  }
+  return handle
+}
+
+test('creation handle reports progress and resolves to a capsule set', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+
+  try {
+    createTestFile(TEST_SIZES.XLARGE, inputFile)
+
+    const handle = startCreateDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
+    t.false(handle.isCancelled(), 'New handle should not be cancelled')
+
+    await waitForHandle(handle)
+    t.true(handle.isCompleted(), 'Creation should complete')
+    t.false(handle.hasError(), 'Creation should not fail')
+    t.is(handle.getError(), null, 'No error message on success')
+
+    const capsuleSet = handle.getResult()
+    assertValidCapsuleSet(t, capsuleSet)
+
+    const progress = handle.getProgress()
+    t.is(progress.totalBytes, TEST_SIZES.XLARGE, 'Total bytes should be the input size')
+    t.is(progress.bytesHashed, TEST_SIZES.XLARGE, 'The whole input should be hashed for the set id')
+    t.is(progress.bytesProcessed, TEST_SIZES.XLARGE, 'All bytes should be processed')
+    t.is(progress.totalCapsules, capsuleSet.metadata.capsuleCount, 'Total capsules should match the set')
+    t.is(progress.capsulesCompleted, capsuleSet.metadata.capsuleCount, 'All capsules should be completed')
+    t.true(progress.elapsedMs > 0, 'Elapsed time should be tracked')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extraction handle reports progress and writes the output file', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  const outputFile = join(tempDir, 'output.dat')
+
+  try {
+    const originalData = createTestFile(TEST_SIZES.LARGE, inputFile)
+    const creation = await waitForHandle(startCreateDataCapsuleFromFile(inputFile, capsulesDir, false, TEST_KEYS.BASIC))
+    const capsuleSet = creation.getResult()
+
+    const handle = await waitForHandle(startExtractDataCapsuleToFile(capsulesDir, outputFile, TEST_KEYS.BASIC))
+    t.true(handle.isCompleted(), 'Extraction should complete')
+    t.is(handle.getResult(), null, 'Extraction has no capsule set result')
+
+    const progress = handle.getProgress()
+    t.is(progress.capsulesCompleted, capsuleSet.metadata.capsuleCount, 'All capsules should be decoded')
+    t.is(progress.bytesProcessed, TEST_SIZES.LARGE, 'All plaintext bytes should be reported')
+    assertBuffersEqual(t, readFileSync(outputFile), originalData, 'Extracted data should match')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('cancelled creation fails and leaves no capsule files behind', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+
+  try {
+    createTestFile(TEST_SIZES.XLARGE, inputFile)
+
+    const handle = startCreateDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.BASIC)
+    handle.cancel()
+    t.true(handle.isCancelled(), 'Handle should report cancellation')
+
+    await waitForHandle(handle)
+    t.true(handle.isCompleted(), 'Cancelled operation should still finish')
+    t.true(handle.hasError(), 'Cancelled operation should report an error')
+    t.is(handle.getErrorCode(), 'CAPSULE_CANCELLED', 'Error code should mark the cancellation')
+    t.regex(handle.getError(), /Operation cancelled/, 'Error should mention cancellation')
+    t.is(handle.getResult(), null, 'Cancelled operation has no result')
+
+    const leftovers = existsSync(capsulesDir) ? readdirSync(capsulesDir) : []
+    t.deepEqual(leftovers, [], 'No capsule or temporary files should remain')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('handle reports errors from the background operation', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const handle = await waitForHandle(
+      startExtractDataCapsuleToFile(join(tempDir, 'missing'), join(tempDir, 'out.dat'), TEST_KEYS.BASIC)
+    )
+    t.true(handle.isCompleted(), 'Failed operation should finish')
+    t.true(handle.hasError(), 'Missing capsule set should fail')
+    t.is(typeof handle.getError(), 'string', 'Error message should be available')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('buffer handles create and extract capsule sets', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const originalData = createTestData(TEST_SIZES.LARGE)
+    const creation = await waitForHandle(startCreateDataCapsule(originalData, tempDir, true, TEST_KEYS.BASIC))
+    t.false(creation.hasError(), 'Creation should not fail')
+    const capsuleSet = creation.getResult()
+    assertValidCapsuleSet(t, capsuleSet)
+    t.is(creation.getData(), null, 'Creation has no extracted data')
+    t.is(creation.getProgress().capsulesCompleted, capsuleSet.metadata.capsuleCount, 'All capsules should be completed')
+
+    const extraction = await waitForHandle(startExtractDataCapsule(tempDir, TEST_KEYS.BASIC))
+    t.false(extraction.hasError(), 'Extraction should not fail')
+    t.is(extraction.getResult(), null, 'Extraction has no capsule set result')
+    assertBuffersEqual(t, extraction.getData(), originalData, 'Extracted data should match')
+    t.is(extraction.getData(), null, 'Extracted data is only handed out once')
+    t.false(extraction.hasError(), 'Taking the data is not an error')
+    t.is(extraction.getProgress().bytesProcessed, TEST_SIZES.LARGE, 'All plaintext bytes should be reported')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('reconstruction handle writes the output file', async (t) => {
+  const tempDir = createTempDir()
+  const inputFile = join(tempDir, 'input.dat')
+  const capsulesDir = join(tempDir, 'capsules')
+  const outputFile = join(tempDir, 'output.dat')
+
+  try {
+    const originalData = createTestFile(TEST_SIZES.LARGE, inputFile)
+    await waitForHandle(startCreateDataCapsuleFromFile(inputFile, capsulesDir, true, TEST_KEYS.STRONG))
+    const capsuleSet = loadCapsuleSet(capsulesDir)
+
+    const handle = await waitForHandle(
+      startReconstructFileFromCapsules(capsuleSet, capsulesDir, outputFile, TEST_KEYS.STRONG)
+    )
+    t.false(handle.hasError(), 'Reconstruction should not fail')
+    t.is(handle.getProgress().capsulesCompleted, capsuleSet.metadata.capsuleCount, 'All capsules should be decoded')
+    assertBuffersEqual(t, readFileSync(outputFile), originalData, 'Reconstructed data should match')
+
+    const failed = await waitForHandle(
+      startReconstructFileFromCapsules(capsuleSet, capsulesDir, join(tempDir, 'wrong.dat'), 'wrong-key')
+    )
+    t.true(failed.isCompleted(), 'Failed reconstruction should finish')
+    t.true(failed.hasError(), 'Wrong key should fail')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -0,0 +1,124 @@
+import test from 'ava'
+import { readdirSync, unlinkSync } from 'fs'
+import { join } from 'path'
+import { createDataCapsule, extractRange, extractRangeAsync } from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Byte-Range Extraction Tests
+
+const MB = 1024 * 1024
+
+test('extractRange returns the requested bytes within one capsule', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.MULTI_MB)
+    createDataCapsule(testData, tempDir, false, TEST_KEYS.BASIC)
+
+    const range = extractRange(tempDir, 1000, 5000, TEST_KEYS.BASIC)
+    assertBuffersEqual(t, range, testData.subarray(1000, 6000))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extractRange spans capsule boundaries', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.MULTI_MB)
+    createDataCapsule(testData, tempDir, true, TEST_KEYS.BASIC)
+
+    const start = MB - 100
+    const range = extractRange(tempDir, start, 2 * MB, TEST_KEYS.BASIC)
+    assertBuffersEqual(t, range, testData.subarray(start, start + 2 * MB))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extractRange only reads the capsules covering the range', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.MULTI_MB)
+    createDataCapsule(testData, tempDir, true)
+
+    // Remove every capsule except the third one
+    for (const file of readdirSync(tempDir)) {
+      if (file.endsWith('.capsule') && !file.endsWith('_002.capsule')) {
+        unlinkSync(join(tempDir, file))
+      }
+    }
+
+    const range = extractRange(tempDir, 2 * MB + 10, 1024)
+    assertBuffersEqual(t, range, testData.subarray(2 * MB + 10, 2 * MB + 10 + 1024))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extractRange cuts ranges short at the end of the data', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(750 * 1024)
+    createDataCapsule(testData, tempDir, true)
+
+    const range = extractRange(tempDir, testData.length - 10, 100)
+    assertBuffersEqual(t, range, testData.subarray(testData.length - 10))
+
+    t.is(extractRange(tempDir, testData.length, 100).length, 0, 'Range at the end should be empty')
+    t.is(extractRange(tempDir, 0, 0).length, 0, 'Zero-length range should be empty')
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extractRange rejects invalid ranges', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.SMALL)
+    createDataCapsule(testData, tempDir, true)
+
+    t.throws(() => extractRange(tempDir, testData.length + 1, 10), { message: /Byte range out of bounds/ })
+    t.throws(() => extractRange(tempDir, -1, 10), { message: /Byte range out of bounds/ })
+    t.throws(() => extractRange(tempDir, 0, 1.5), { message: /Byte range out of bounds/ })
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extractRange requires the decryption key for encrypted sets', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true, TEST_KEYS.BASIC)
+
+    t.throws(() => extractRange(tempDir, 0, 100, TEST_KEYS.STRONG))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('extractRangeAsync resolves to the same bytes', async (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const testData = createTestData(TEST_SIZES.MULTI_MB)
+    createDataCapsule(testData, tempDir, false, TEST_KEYS.BASIC)
+
+    const range = await extractRangeAsync(tempDir, 3 * MB - 5, 10, TEST_KEYS.BASIC)
+    assertBuffersEqual(t, range, testData.subarray(3 * MB - 5, 3 * MB + 5))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -0,0 +1,150 @@
+import test from 'ava'
+import { join } from 'path'
+import { existsSync, readdirSync, unlinkSync, utimesSync, writeFileSync } from 'fs'
+import { createDataCapsule, extractDataCapsule, recoverCapsuleDirectory } from '../index.js'
+import {
+  createTempDir,
+  cleanupTempDir,
+  createTestData,
+  assertBuffersEqual,
+  TEST_SIZES,
+  TEST_KEYS
+} from './helpers/test-utils.mjs'
+
+// Crash Recovery Tests
+
+// Leave a set as a crash before its metadata was written would
+function removeMetadata(dir, capsuleSet) {
+  const metadataFile = readdirSync(dir).find(
+    (file) => file.endsWith('_metadata.json') && capsuleSet.id.startsWith(file.split('_')[0])
+  )
+  unlinkSync(join(dir, metadataFile))
+}
+
+// Leave a temporary file as a write that crashed two hours ago would
+function writeAbandonedFile(path) {
+  writeFileSync(path, 'partial')
+  const twoHoursAgo = new Date(Date.now() - 2 * 60 * 60 * 1000)
+  utimesSync(path, twoHoursAgo, twoHoursAgo)
+}
+
+test('recovery removes capsules without metadata and temporary files', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const completeData = createTestData(TEST_SIZES.SMALL)
+    createDataCapsule(completeData, tempDir, true, TEST_KEYS.BASIC)
+    const partialSet = createDataCapsule(createTestData(TEST_SIZES.MULTI_MB), tempDir, true, null, {
+      namingScheme: 'SET_ID'
+    })
+    removeMetadata(tempDir, partialSet)
+
+    const tempFiles = ['.0123456789abcdef_000.4242.tmp', '.0123456789abcdef_metadata.4242.tmp']
+    const unrelatedFiles = ['notes.txt', 'backup.capsule', '.editor.tmp']
+    for (const file of tempFiles) {
+      writeAbandonedFile(join(tempDir, file))
+    }
+    for (const file of unrelatedFiles) {
+      writeFileSync(join(tempDir, file), 'partial')
+    }
+
+    const report = recoverCapsuleDirectory(tempDir)
+
+    const expectedRemoved = [
+      ...partialSet.capsules.map((capsule) => join(tempDir, `${partialSet.id}_${String(capsule.index).padStart(3, '0')}.capsule`)),
+      ...tempFiles.map((file) => join(tempDir, file))
+    ].sort()
+    t.deepEqual(report.removedFiles, expectedRemoved)
+    for (const file of unrelatedFiles) {
+      t.true(existsSync(join(tempDir, file)), `${file} should be kept`)
+    }
+
+    assertBuffersEqual(t, extractDataCapsule(tempDir, TEST_KEYS.BASIC), completeData)
+    t.deepEqual(recoverCapsuleDirectory(tempDir).removedFiles, [])
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('recovery removes the directory of a SET_DIRECTORY set without metadata', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.MEDIUM), tempDir, true, null, {
+      namingScheme: 'SET_DIRECTORY'
+    })
+    unlinkSync(join(tempDir, `${capsuleSet.id}_metadata.json`))
+
+    const report = recoverCapsuleDirectory(tempDir)
+
+    t.is(report.removedFiles.length, capsuleSet.capsules.length)
+    t.deepEqual(readdirSync(tempDir), [])
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('recovery leaves the directory untouched when metadata cannot be read', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true)
+    const metadataFile = join(tempDir, `${capsuleSet.id}_metadata.json`)
+    writeFileSync(metadataFile, '{ truncated')
+    const filesBefore = readdirSync(tempDir).sort()
+
+    t.throws(() => recoverCapsuleDirectory(tempDir), { code: 'CAPSULE_INVALID_METADATA' })
+    t.deepEqual(readdirSync(tempDir).sort(), filesBefore)
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('completed sets leave no temporary files behind', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    createDataCapsule(createTestData(TEST_SIZES.MULTI_MB), tempDir, false, TEST_KEYS.BASIC)
+
+    t.false(readdirSync(tempDir).some((file) => file.endsWith('.tmp')))
+    t.deepEqual(recoverCapsuleDirectory(tempDir).removedFiles, [])
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('recovery keeps recent temporary files and files that are not capsules', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const keptFiles = ['.0123456789abcdef_000.4242-0.tmp', `${'ab'.repeat(32)}_000.capsule`]
+    writeFileSync(join(tempDir, keptFiles[0]), 'partial')
+    writeFileSync(join(tempDir, keptFiles[1]), Buffer.alloc(4096))
+
+    t.deepEqual(recoverCapsuleDirectory(tempDir).removedFiles, [])
+    t.true(existsSync(join(tempDir, keptFiles[0])))
+    t.true(existsSync(join(tempDir, keptFiles[1])))
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
+
+test('a dry run reports what recovery would remove without removing it', (t) => {
+  const tempDir = createTempDir()
+
+  try {
+    const capsuleSet = createDataCapsule(createTestData(TEST_SIZES.SMALL), tempDir, true)
+    removeMetadata(tempDir, capsuleSet)
+    writeAbandonedFile(join(tempDir, '.0123456789abcdef_000.4242-0.tmp'))
+    const filesBefore = readdirSync(tempDir).sort()
+
+    const planned = recoverCapsuleDirectory(tempDir, true)
+
+    t.is(planned.removedFiles.length, capsuleSet.capsules.length + 1)
+    t.deepEqual(readdirSync(tempDir).sort(), filesBefore)
+    t.deepEqual(recoverCapsuleDirectory(tempDir).removedFiles, planned.removedFiles)
+    t.deepEqual(readdirSync(tempDir), [])
+  } finally {
+    cleanupTempDir(tempDir)
+  }
+})
@@ -3,61 +3,22 @@
 import { spawn } from 'child_process'
 import { join, dirname } from 'path'
 import { fileURLToPath } from 'url'
-import { existsSync } from 'fs'
+import { readdirSync } from 'fs'
 
 const __filename = fileURLToPath(import.meta.url)
 const __dirname = dirname(__filename)
 
-// Define test suites in order of execution
-const testSuites = [
-  {
-    name: 'Basic Functionality',
-    file: 'basic.spec.mjs',
-    description: 'Core constants and utility functions'
-  },
-  {
-    name: 'File Operations',
-    file: 'file-operations.spec.mjs',
-    description: 'File-based capsule creation and extraction'
-  },
-  {
-    name: 'Consensus Validation',
-    file: 'consensus.spec.mjs',
-    description: 'Consensus rules and deterministic processing'
-  },
-  {
-    name: 'Capsule Validation',
-    file: 'capsule-validation.spec.mjs',
-    description: 'Capsule file format validation'
-  },
-  {
-    name: 'Error Handling',
-    file: 'error-handling.spec.mjs',
-    description: 'Error conditions and recovery'
-  },
-  {
-    name: 'Edge Cases',
-    file: 'edge-cases.spec.mjs',
-    description: 'Boundary conditions and special scenarios'
-  },
-  {
-    name: 'Performance',
-    file: 'performance.spec.mjs',
-    description: 'Large files and streaming performance'
-  }
-]
+// Every spec file next to this runner, in alphabetical order
+const testSuites = readdirSync(__dirname)
+  .filter((file) => file.endsWith('.spec.mjs'))
+  .sort()
+  .map((file) => ({ name: file.replace(/\.spec\.mjs$/, ''), file }))
 
 async function runTestSuite(testSuite) {
   const testFile = join(__dirname, testSuite.file)
-  
-  if (!existsSync(testFile)) {
-    console.log(`⚠️  Test suite ${testSuite.name} not found: ${testSuite.file}`)
-    return { name: testSuite.name, status: 'skipped', reason: 'file not found' }
-  }
 
   return new Promise((resolve) => {
     console.log(`\n🧪 Running ${testSuite.name}`)
-    console.log(`   ${testSuite.description}`)
     console.log(`   File: ${testSuite.file}`)
     
     const startTime = Date.now()
@@ -1,5 +1,7 @@
 extern crate napi_build;
 
 fn main() {
-    napi_build::setup();
+    if std::env::var_os("CARGO_FEATURE_NAPI").is_some() {
+        napi_build::setup();
+    }
 }
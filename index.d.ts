@@ -9,17 +9,45 @@ export interface Capsule {
   hash: string
   encrypted: boolean
   compressed: boolean
+  paddingPostProcess?: boolean
 }
 export interface EncryptionInfo {
   algorithm: string
   keyDerivation: string
   iterations: number
   salt: string
+  memoryCost?: number
+  parallelism?: number
+  logN?: number
+  blockSize?: number
+  nonceScheme?: string
+  noncePrefix?: string
+}
+export interface KeyDerivationOptions {
+  algorithm: string
+  iterations?: number
+  memoryCost?: number
+  parallelism?: number
+  logN?: number
+  blockSize?: number
+}
+export interface CompressionOptions {
+  algorithm: string
+  level?: number
+  dictionary?: string
+}
+export interface CreateCapsuleOptions {
+  keyDerivation?: KeyDerivationOptions
+  compression?: CompressionOptions
+  namingScheme?: string
+  onConflict?: string
 }
 export interface CompressionInfo {
   algorithm: string
   level: number
   originalSize: number
+  dictionary?: string
+  compressedCapsules?: number
 }
 export interface CapsuleMetadata {
   originalSize: number
@@ -30,24 +58,81 @@ export interface CapsuleMetadata {
   consensusVersion: string
   encryptionInfo?: EncryptionInfo
   compressionInfo?: CompressionInfo
+  merkleRoot?: string
+  namingScheme?: string
+  kind?: string
 }
 export interface CapsuleSet {
   id: string
   capsules: Array<Capsule>
   metadata: CapsuleMetadata
 }
-export declare function createDataCapsule(bufferData: Buffer, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | undefined | null): CapsuleSet
-export declare function extractDataCapsule(capsuleSetPath: string, decryptionKey?: string | undefined | null): Buffer
-export declare function createDataCapsuleFromFile(inputFilePath: string, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | undefined | null): CapsuleSet
-export declare function extractDataCapsuleToFile(capsuleSetPath: string, outputFilePath: string, decryptionKey?: string | undefined | null): void
-export declare function loadCapsuleSet(path: string): CapsuleSet
-export declare function reconstructFileFromCapsules(capsuleSet: CapsuleSet, capsulesDir: string, outputFilePath: string, decryptionKey?: string | undefined | null): void
+export interface ArchiveEntry {
+  name: string
+  size: number
+  mode: number
+  hash: string
+  offset: number
+  kind?: string
+  linkTarget?: string
+  mtime?: number
+}
+export interface DirectoryFilter {
+  include?: Array<string>
+  exclude?: Array<string>
+}
+export interface ArchiveEntryInput {
+  name: string
+  data?: Buffer
+  path?: string
+  mode?: number
+}
+export interface MerkleProof {
+  capsuleIndex: number
+  capsuleHash: string
+  leafCount: number
+  siblings: Array<string>
+}
+export declare function createDataCapsule(bufferData: Buffer, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): CapsuleSet
+export declare function extractDataCapsule(capsuleSetPath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Buffer
+export declare function createDataCapsuleFromFile(inputFilePath: string, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): CapsuleSet
+export declare function extractDataCapsuleToFile(capsuleSetPath: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): void
+export declare function createArchiveCapsule(entries: Array<ArchiveEntryInput>, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): CapsuleSet
+export declare function listArchiveEntries(capsuleSetPath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Array<ArchiveEntry>
+export declare function extractArchiveEntry(capsuleSetPath: string, name: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Buffer
+export declare function createDataCapsuleFromDirectory(inputDirectory: string, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null, filter?: DirectoryFilter | undefined | null): CapsuleSet
+export declare function extractDataCapsuleToDirectory(capsuleSetPath: string, outputDirectory: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null, filter?: DirectoryFilter | undefined | null): Array<ArchiveEntry>
+export declare function trainCompressionDictionary(samples: Array<Buffer>, outputDirectory: string, encryptionKey?: string | Buffer | undefined | null, maxSize?: number | undefined | null): CapsuleSet
+export declare function loadCapsuleSet(path: string, setId?: string | undefined | null): CapsuleSet
+export declare function listCapsuleSets(directory: string): Array<CapsuleSet>
+export declare function recoverCapsuleDirectory(directory: string, dryRun?: boolean | undefined | null): RecoveryReport
+export declare function reconstructFileFromCapsules(capsuleSet: CapsuleSet, capsulesDir: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null): void
+export declare function extractRange(capsuleSetPath: string, offset: number, length: number, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Buffer
+export declare function decodeCapsule(filePath: string, decryptionKey?: string | Buffer | undefined | null, capsuleSet?: CapsuleSet | undefined | null): Buffer
+export declare function decodeCapsuleToFile(filePath: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null, capsuleSet?: CapsuleSet | undefined | null): void
+export declare function createDataCapsuleAsync(bufferData: Buffer, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): Promise<CapsuleSet>
+export declare function createDataCapsuleFromFileAsync(inputFilePath: string, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): Promise<CapsuleSet>
+export declare function extractDataCapsuleAsync(capsuleSetPath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Promise<Buffer>
+export declare function extractDataCapsuleToFileAsync(capsuleSetPath: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Promise<void>
+export declare function reconstructFileFromCapsulesAsync(capsuleSet: CapsuleSet, capsulesDir: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null): Promise<void>
+export declare function extractRangeAsync(capsuleSetPath: string, offset: number, length: number, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): Promise<Buffer>
+export declare function startCreateDataCapsule(bufferData: Buffer, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): CapsuleOperationHandle
+export declare function startCreateDataCapsuleFromFile(inputFilePath: string, outputDirectory: string, postProcessPadding: boolean, encryptionKey?: string | Buffer | undefined | null, options?: CreateCapsuleOptions | undefined | null): CapsuleOperationHandle
+export declare function startExtractDataCapsule(capsuleSetPath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): CapsuleOperationHandle
+export declare function startExtractDataCapsuleToFile(capsuleSetPath: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null, setId?: string | undefined | null): CapsuleOperationHandle
+export declare function startReconstructFileFromCapsules(capsuleSet: CapsuleSet, capsulesDir: string, outputFilePath: string, decryptionKey?: string | Buffer | undefined | null): CapsuleOperationHandle
 export declare function isValidCapsuleFile(filePath: string): boolean
 export declare function getCapsuleFileInfo(filePath: string): CapsuleFileInfo | null
 export declare function calculateStorageOverhead(originalSize: number, capsuleCount: number): number
 export declare function getCapsuleSizes(): Array<number>
 export declare function getConsensusVersion(): string
 export declare function validateConsensusParameters(capsuleSet: CapsuleSet): boolean
+export declare function getCapsuleMerkleRoot(capsuleSet: CapsuleSet): string
+export declare function createCapsuleMerkleProof(capsuleSet: CapsuleSet, capsuleIndex: number): MerkleProof
+export declare function verifyCapsuleMerkleProof(merkleRoot: string, capsuleCount: number, proof: MerkleProof): boolean
+export interface RecoveryReport {
+  removedFiles: Array<string>
+}
 export interface CapsuleFileInfo {
   magic: string
   version: number
@@ -58,3 +143,22 @@ export interface CapsuleFileInfo {
   isCompressed: boolean
   checksum: string
 }
+export interface CapsuleProgress {
+  bytesHashed: number
+  bytesProcessed: number
+  totalBytes: number
+  capsulesCompleted: number
+  totalCapsules: number
+  elapsedMs: number
+}
+export declare class CapsuleOperationHandle {
+  cancel(): void
+  isCancelled(): boolean
+  isCompleted(): boolean
+  hasError(): boolean
+  getError(): string | null
+  getErrorCode(): string | null
+  getResult(): CapsuleSet | null
+  getData(): Buffer | null
+  getProgress(): CapsuleProgress
+}
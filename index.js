@@ -310,17 +310,43 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { createDataCapsule, extractDataCapsule, createDataCapsuleFromFile, extractDataCapsuleToFile, loadCapsuleSet, reconstructFileFromCapsules, isValidCapsuleFile, getCapsuleFileInfo, calculateStorageOverhead, getCapsuleSizes, getConsensusVersion, validateConsensusParameters } = nativeBinding
+const { CapsuleOperationHandle, createDataCapsule, extractDataCapsule, createDataCapsuleFromFile, extractDataCapsuleToFile, createArchiveCapsule, listArchiveEntries, extractArchiveEntry, createDataCapsuleFromDirectory, extractDataCapsuleToDirectory, trainCompressionDictionary, loadCapsuleSet, listCapsuleSets, recoverCapsuleDirectory, reconstructFileFromCapsules, extractRange, decodeCapsule, decodeCapsuleToFile, createDataCapsuleAsync, createDataCapsuleFromFileAsync, extractDataCapsuleAsync, extractDataCapsuleToFileAsync, reconstructFileFromCapsulesAsync, extractRangeAsync, startCreateDataCapsule, startCreateDataCapsuleFromFile, startExtractDataCapsule, startExtractDataCapsuleToFile, startReconstructFileFromCapsules, isValidCapsuleFile, getCapsuleFileInfo, calculateStorageOverhead, getCapsuleSizes, getConsensusVersion, validateConsensusParameters, getCapsuleMerkleRoot, createCapsuleMerkleProof, verifyCapsuleMerkleProof } = nativeBinding
 
+module.exports.CapsuleOperationHandle = CapsuleOperationHandle
 module.exports.createDataCapsule = createDataCapsule
 module.exports.extractDataCapsule = extractDataCapsule
 module.exports.createDataCapsuleFromFile = createDataCapsuleFromFile
 module.exports.extractDataCapsuleToFile = extractDataCapsuleToFile
+module.exports.createArchiveCapsule = createArchiveCapsule
+module.exports.listArchiveEntries = listArchiveEntries
+module.exports.extractArchiveEntry = extractArchiveEntry
+module.exports.createDataCapsuleFromDirectory = createDataCapsuleFromDirectory
+module.exports.extractDataCapsuleToDirectory = extractDataCapsuleToDirectory
+module.exports.trainCompressionDictionary = trainCompressionDictionary
 module.exports.loadCapsuleSet = loadCapsuleSet
+module.exports.listCapsuleSets = listCapsuleSets
+module.exports.recoverCapsuleDirectory = recoverCapsuleDirectory
 module.exports.reconstructFileFromCapsules = reconstructFileFromCapsules
+module.exports.extractRange = extractRange
+module.exports.decodeCapsule = decodeCapsule
+module.exports.decodeCapsuleToFile = decodeCapsuleToFile
+module.exports.createDataCapsuleAsync = createDataCapsuleAsync
+module.exports.createDataCapsuleFromFileAsync = createDataCapsuleFromFileAsync
+module.exports.extractDataCapsuleAsync = extractDataCapsuleAsync
+module.exports.extractDataCapsuleToFileAsync = extractDataCapsuleToFileAsync
+module.exports.reconstructFileFromCapsulesAsync = reconstructFileFromCapsulesAsync
+module.exports.extractRangeAsync = extractRangeAsync
+module.exports.startCreateDataCapsule = startCreateDataCapsule
+module.exports.startCreateDataCapsuleFromFile = startCreateDataCapsuleFromFile
+module.exports.startExtractDataCapsule = startExtractDataCapsule
+module.exports.startExtractDataCapsuleToFile = startExtractDataCapsuleToFile
+module.exports.startReconstructFileFromCapsules = startReconstructFileFromCapsules
 module.exports.isValidCapsuleFile = isValidCapsuleFile
 module.exports.getCapsuleFileInfo = getCapsuleFileInfo
 module.exports.calculateStorageOverhead = calculateStorageOverhead
 module.exports.getCapsuleSizes = getCapsuleSizes
 module.exports.getConsensusVersion = getConsensusVersion
 module.exports.validateConsensusParameters = validateConsensusParameters
+module.exports.getCapsuleMerkleRoot = getCapsuleMerkleRoot
+module.exports.createCapsuleMerkleProof = createCapsuleMerkleProof
+module.exports.verifyCapsuleMerkleProof = verifyCapsuleMerkleProof
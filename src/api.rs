@@ -0,0 +1,496 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::archive::{
+    create_archive_capsule_internal, extract_archive_entry_internal, list_archive_entries_internal,
+    ArchiveEntry, ArchiveInput, ARCHIVE_KIND,
+};
+use crate::dictionary::{train_compression_dictionary_internal, DICTIONARY_KIND};
+use crate::directory::{
+    create_directory_capsule_internal, extract_directory_internal, DirectoryFilter,
+};
+use crate::progress::ProgressTracker;
+use crate::{
+    capsule_path, create_data_capsule_from_file_internal, create_data_capsule_internal,
+    decode_capsule_internal, decode_capsule_set, extract_range_internal,
+    list_capsule_sets_internal, load_capsule_set_from_path, merkle, merkle_leaves,
+    read_capsule_header, reconstruct_file_from_capsules_internal,
+    recover_capsule_directory_internal, validate_capsule_file_internal, verify_capsule_hash,
+    Capsule, CapsuleError, CapsuleFileInfo, CapsuleResult, CapsuleSet, CompressionOptions,
+    ConflictPolicy, CreateCapsuleOptions, EncryptionKey, KeyDerivationOptions, MerkleProof,
+    NamingScheme, RecoveryReport, CAPSULE_SIZES, FLAG_COMPRESSED, FLAG_ENCRYPTED,
+    SUPPORTED_CONSENSUS_VERSIONS,
+};
+
+// Creates capsule sets. Defaults match the Node API: padding after
+// encryption, no encryption, and the default key derivation for the key type.
+#[derive(Clone)]
+pub struct CapsuleWriter {
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: CreateCapsuleOptions,
+}
+
+impl Default for CapsuleWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CapsuleWriter {
+    pub fn new() -> Self {
+        CapsuleWriter {
+            post_process_padding: true,
+            encryption_key: None,
+            options: CreateCapsuleOptions::default(),
+        }
+    }
+
+    pub fn with_post_process_padding(mut self, post_process_padding: bool) -> Self {
+        self.post_process_padding = post_process_padding;
+        self
+    }
+
+    pub fn with_encryption_key(mut self, encryption_key: impl Into<EncryptionKey>) -> Self {
+        self.encryption_key = Some(encryption_key.into());
+        self
+    }
+
+    pub fn with_key_derivation(mut self, key_derivation: KeyDerivationOptions) -> Self {
+        self.options.key_derivation = Some(key_derivation);
+        self
+    }
+
+    pub fn with_compression(mut self, compression: CompressionOptions) -> Self {
+        self.options.compression = Some(compression);
+        self
+    }
+
+    // Compress with the dictionary set `dictionary_id`, which must be stored in
+    // the output directory. Switches to zstd unless a codec was chosen.
+    pub fn with_dictionary(mut self, dictionary_id: &str) -> Self {
+        let compression = self
+            .options
+            .compression
+            .get_or_insert_with(|| CompressionOptions {
+                algorithm: "zstd".to_string(),
+                level: None,
+                dictionary: None,
+            });
+        compression.dictionary = Some(dictionary_id.to_string());
+        self
+    }
+
+    pub fn with_naming_scheme(mut self, naming_scheme: NamingScheme) -> Self {
+        self.options.naming_scheme = Some(naming_scheme.as_str().to_string());
+        self
+    }
+
+    // What to do when files of the new set already exist with different contents
+    pub fn with_conflict_policy(mut self, conflict_policy: ConflictPolicy) -> Self {
+        self.options.on_conflict = Some(conflict_policy.as_str().to_string());
+        self
+    }
+
+    // Capsule `data` into `output_directory`, returning the saved capsule set
+    pub fn write(
+        &self,
+        data: &[u8],
+        output_directory: impl AsRef<Path>,
+    ) -> CapsuleResult<CapsuleSet> {
+        create_data_capsule_internal(
+            data,
+            output_directory.as_ref(),
+            self.post_process_padding,
+            self.encryption_key.clone(),
+            Some(self.options.clone()),
+            &ProgressTracker::default(),
+        )
+    }
+
+    // Capsule a file without loading it into memory
+    pub fn write_file(
+        &self,
+        input_path: impl AsRef<Path>,
+        output_directory: impl AsRef<Path>,
+    ) -> CapsuleResult<CapsuleSet> {
+        create_data_capsule_from_file_internal(
+            input_path.as_ref(),
+            output_directory.as_ref(),
+            self.post_process_padding,
+            self.encryption_key.clone(),
+            Some(self.options.clone()),
+            &ProgressTracker::default(),
+        )
+    }
+
+    // Pack several small files or buffers into one archive set, so they share
+    // capsules instead of each filling a 256 KB capsule of its own
+    pub fn write_archive(
+        &self,
+        inputs: &[ArchiveInput],
+        output_directory: impl AsRef<Path>,
+    ) -> CapsuleResult<CapsuleSet> {
+        create_archive_capsule_internal(
+            inputs,
+            output_directory.as_ref(),
+            self.post_process_padding,
+            self.encryption_key.clone(),
+            Some(self.options.clone()),
+        )
+    }
+
+    // Capsule the tree under `input_directory` as an archive set recording
+    // permissions, modification times and symlinks; `filter` picks the paths
+    pub fn write_directory(
+        &self,
+        input_directory: impl AsRef<Path>,
+        output_directory: impl AsRef<Path>,
+        filter: &DirectoryFilter,
+    ) -> CapsuleResult<CapsuleSet> {
+        create_directory_capsule_internal(
+            input_directory.as_ref(),
+            output_directory.as_ref(),
+            self.post_process_padding,
+            self.encryption_key.clone(),
+            Some(self.options.clone()),
+            filter,
+        )
+    }
+
+    // Train a zstd dictionary of at most `max_size` bytes (about 110 KiB by
+    // default) from `samples` and store it as a dictionary set in
+    // `output_directory`, encrypted with this writer's key. Its id is what
+    // `with_dictionary` takes.
+    pub fn write_dictionary<S: AsRef<[u8]>>(
+        &self,
+        samples: &[S],
+        max_size: Option<usize>,
+        output_directory: impl AsRef<Path>,
+    ) -> CapsuleResult<CapsuleSet> {
+        train_compression_dictionary_internal(
+            samples,
+            max_size,
+            output_directory.as_ref(),
+            self.post_process_padding,
+            self.encryption_key.clone(),
+            Some(self.options.clone()),
+        )
+    }
+}
+
+// Reads a capsule set stored in a directory: the whole data, a byte range or
+// single capsules. Every capsule is checked against its recorded hash first.
+#[derive(Clone)]
+pub struct CapsuleReader {
+    capsule_set: CapsuleSet,
+    capsules_dir: PathBuf,
+    decryption_key: Option<EncryptionKey>,
+}
+
+impl CapsuleReader {
+    // `path` is either the capsule directory or the set's metadata file. Fails
+    // with `AmbiguousCapsuleSet` when the directory holds more than one set.
+    pub fn open(path: impl AsRef<Path>) -> CapsuleResult<Self> {
+        let (capsule_set, capsules_dir) = load_capsule_set_from_path(path.as_ref(), None)?;
+        Ok(Self::new(capsule_set, capsules_dir))
+    }
+
+    // Open the set whose id is, or starts with, `set_id`
+    pub fn open_by_id(path: impl AsRef<Path>, set_id: &str) -> CapsuleResult<Self> {
+        let (capsule_set, capsules_dir) = load_capsule_set_from_path(path.as_ref(), Some(set_id))?;
+        Ok(Self::new(capsule_set, capsules_dir))
+    }
+
+    // Read a set whose metadata is already known, with capsules in `capsules_dir`
+    pub fn new(capsule_set: CapsuleSet, capsules_dir: impl Into<PathBuf>) -> Self {
+        CapsuleReader {
+            capsule_set,
+            capsules_dir: capsules_dir.into(),
+            decryption_key: None,
+        }
+    }
+
+    pub fn with_decryption_key(mut self, decryption_key: impl Into<EncryptionKey>) -> Self {
+        self.decryption_key = Some(decryption_key.into());
+        self
+    }
+
+    pub fn capsule_set(&self) -> &CapsuleSet {
+        &self.capsule_set
+    }
+
+    pub fn capsules_dir(&self) -> &Path {
+        &self.capsules_dir
+    }
+
+    // Decode the whole set into `writer`, checking it against the set checksum.
+    // On a checksum mismatch the bad data has already been written.
+    pub fn extract_to<W: Write>(&self, writer: W) -> CapsuleResult<()> {
+        decode_capsule_set(
+            &self.capsule_set,
+            &self.capsules_dir,
+            self.decryption_key.clone(),
+            writer,
+            &ProgressTracker::default(),
+        )
+    }
+
+    pub fn extract(&self) -> CapsuleResult<Vec<u8>> {
+        let mut data = Vec::new();
+        self.extract_to(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn extract_to_file(&self, output_path: impl AsRef<Path>) -> CapsuleResult<()> {
+        reconstruct_file_from_capsules_internal(
+            &self.capsule_set,
+            &self.capsules_dir,
+            output_path.as_ref(),
+            self.decryption_key.clone(),
+            &ProgressTracker::default(),
+        )
+    }
+
+    // Read `length` bytes starting at `offset`, decoding only the capsules
+    // covering them; ranges running past the end are cut short
+    pub fn read_range(&self, offset: u64, length: u64) -> CapsuleResult<Vec<u8>> {
+        extract_range_internal(
+            &self.capsule_set,
+            &self.capsules_dir,
+            offset,
+            length,
+            self.decryption_key.clone(),
+        )
+    }
+
+    // Decode the capsule at `index` on its own
+    pub fn decode_capsule(&self, index: u32) -> CapsuleResult<Vec<u8>> {
+        let capsule_path = self.capsule_path(index)?;
+        let mut data = Vec::new();
+        decode_capsule_internal(
+            &capsule_path,
+            self.decryption_key.clone(),
+            Some(&self.capsule_set),
+            &mut data,
+        )?;
+        Ok(data)
+    }
+
+    pub fn decode_capsule_to_file(
+        &self,
+        index: u32,
+        output_path: impl AsRef<Path>,
+    ) -> CapsuleResult<()> {
+        let output_path = output_path.as_ref();
+        let output_file = File::create(output_path).map_err(CapsuleError::at_path(output_path))?;
+        let mut writer = BufWriter::new(output_file);
+        decode_capsule_internal(
+            &self.capsule_path(index)?,
+            self.decryption_key.clone(),
+            Some(&self.capsule_set),
+            &mut writer,
+        )?;
+        writer.flush().map_err(CapsuleError::at_path(output_path))?;
+        Ok(())
+    }
+
+    // The files stored in an archive set, read from its manifest
+    pub fn list_entries(&self) -> CapsuleResult<Vec<ArchiveEntry>> {
+        list_archive_entries_internal(
+            &self.capsule_set,
+            &self.capsules_dir,
+            self.decryption_key.clone(),
+        )
+    }
+
+    // Decode one file of an archive set, checked against its recorded hash.
+    // Only the capsules holding the manifest and the entry are read.
+    pub fn extract_entry(&self, name: &str) -> CapsuleResult<Vec<u8>> {
+        extract_archive_entry_internal(
+            &self.capsule_set,
+            &self.capsules_dir,
+            name,
+            self.decryption_key.clone(),
+        )
+    }
+
+    // Restore the tree stored in an archive set into `output_directory`, which
+    // must be empty or missing. Returns the entries restored.
+    pub fn extract_to_directory(
+        &self,
+        output_directory: impl AsRef<Path>,
+        filter: &DirectoryFilter,
+    ) -> CapsuleResult<Vec<ArchiveEntry>> {
+        extract_directory_internal(
+            &self.capsule_set,
+            &self.capsules_dir,
+            output_directory.as_ref(),
+            self.decryption_key.clone(),
+            filter,
+        )
+    }
+
+    // Check every capsule file against its recorded hash and header without
+    // decoding anything, so sets can be verified without the key
+    pub fn verify_capsules(&self) -> CapsuleResult<()> {
+        for index in 0..self.capsule_set.metadata.capsule_count {
+            let capsule_path = self.capsule_path(index)?;
+            let mut capsule_file =
+                File::open(&capsule_path).map_err(CapsuleError::at_path(&capsule_path))?;
+
+            let capsule = self.capsule(index)?;
+            verify_capsule_hash(&mut capsule_file, capsule)?;
+            read_capsule_header(&mut capsule_file, index)?;
+        }
+        Ok(())
+    }
+
+    pub fn capsule_path(&self, index: u32) -> CapsuleResult<PathBuf> {
+        capsule_path(&self.capsule_set, &self.capsules_dir, self.capsule(index)?)
+    }
+
+    fn capsule(&self, index: u32) -> CapsuleResult<&Capsule> {
+        self.capsule_set
+            .capsules
+            .iter()
+            .find(|capsule| capsule.index == index)
+            .ok_or(CapsuleError::InvalidFormat)
+    }
+}
+
+// Every capsule set stored in `dir`, ordered by id
+pub fn list_capsule_sets(dir: impl AsRef<Path>) -> CapsuleResult<Vec<CapsuleSet>> {
+    list_capsule_sets_internal(dir.as_ref())
+}
+
+// Clean up after interrupted writes: remove temporary files untouched for an
+// hour and capsules without metadata from `dir`. Must not run while sets are
+// being written there. With `dry_run`, nothing is removed and the report lists
+// what would be.
+pub fn recover_capsule_directory(
+    dir: impl AsRef<Path>,
+    dry_run: bool,
+) -> CapsuleResult<RecoveryReport> {
+    recover_capsule_directory_internal(dir.as_ref(), dry_run)
+}
+
+impl CapsuleSet {
+    // Load the metadata of a set from its directory or metadata file
+    pub fn load(path: impl AsRef<Path>) -> CapsuleResult<Self> {
+        let (capsule_set, _) = load_capsule_set_from_path(path.as_ref(), None)?;
+        Ok(capsule_set)
+    }
+
+    pub fn load_by_id(path: impl AsRef<Path>, set_id: &str) -> CapsuleResult<Self> {
+        let (capsule_set, _) = load_capsule_set_from_path(path.as_ref(), Some(set_id))?;
+        Ok(capsule_set)
+    }
+
+    // Whether the set holds a compression dictionary rather than data
+    pub fn is_dictionary(&self) -> bool {
+        self.metadata.kind.as_deref() == Some(DICTIONARY_KIND)
+    }
+
+    // Whether the set holds an archive of several files
+    pub fn is_archive(&self) -> bool {
+        self.metadata.kind.as_deref() == Some(ARCHIVE_KIND)
+    }
+
+    // Check the consensus-critical parameters recorded in the set
+    pub fn validate_consensus(&self) -> CapsuleResult<()> {
+        if !SUPPORTED_CONSENSUS_VERSIONS.contains(&self.metadata.consensus_version.as_str()) {
+            return Err(CapsuleError::ConsensusViolation(
+                "Invalid consensus version".to_string(),
+            ));
+        }
+
+        if self.metadata.chunking_algorithm != "DIG_DETERMINISTIC_V1" {
+            return Err(CapsuleError::ConsensusViolation(
+                "Invalid chunking algorithm".to_string(),
+            ));
+        }
+
+        // Validate capsule sizes are from allowed set
+        for capsule in &self.capsules {
+            if !CAPSULE_SIZES.contains(&(capsule.size as usize)) {
+                return Err(CapsuleError::ConsensusViolation(
+                    "Invalid capsule size".to_string(),
+                ));
+            }
+        }
+
+        // Sets created before Merkle roots were recorded carry none
+        if let Some(merkle_root) = &self.metadata.merkle_root {
+            if self.merkle_root()? != merkle_root.to_lowercase() {
+                return Err(CapsuleError::ConsensusViolation(
+                    "Merkle root mismatch".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Recompute the Merkle root over the capsule hashes
+    pub fn merkle_root(&self) -> CapsuleResult<String> {
+        let leaves = merkle_leaves(&self.capsules)?;
+        Ok(hex::encode(merkle::root(&leaves)?))
+    }
+
+    pub fn merkle_proof(&self, capsule_index: u32) -> CapsuleResult<MerkleProof> {
+        let leaves = merkle_leaves(&self.capsules)?;
+        let siblings = merkle::proof(&leaves, capsule_index as usize)?;
+
+        Ok(MerkleProof {
+            capsule_index,
+            capsule_hash: hex::encode(leaves[capsule_index as usize]),
+            leaf_count: leaves.len() as u32,
+            siblings: siblings.iter().map(hex::encode).collect(),
+        })
+    }
+}
+
+impl MerkleProof {
+    // Check the proof against a trusted root and capsule count without needing
+    // the rest of the set. The root does not commit to the leaf count, so the
+    // count recorded in the proof is only accepted when it matches.
+    pub fn verify(&self, merkle_root: &str, capsule_count: u32) -> CapsuleResult<bool> {
+        if self.leaf_count != capsule_count {
+            return Ok(false);
+        }
+        let expected_root = merkle::parse_hash(merkle_root)?;
+        let capsule_hash = merkle::parse_hash(&self.capsule_hash)?;
+        let siblings = self
+            .siblings
+            .iter()
+            .map(|sibling| merkle::parse_hash(sibling))
+            .collect::<CapsuleResult<Vec<_>>>()?;
+
+        Ok(merkle::verify(
+            &capsule_hash,
+            self.capsule_index as usize,
+            self.leaf_count as usize,
+            &siblings,
+            &expected_root,
+        ))
+    }
+}
+
+impl CapsuleFileInfo {
+    // Read and validate the header of a capsule file
+    pub fn read(file_path: impl AsRef<Path>) -> CapsuleResult<Self> {
+        let header = validate_capsule_file_internal(file_path.as_ref())?;
+        Ok(CapsuleFileInfo {
+            magic: hex::encode(header.magic),
+            version: header.version,
+            capsule_index: header.capsule_index,
+            capsule_size: header.capsule_size,
+            data_size: header.data_size,
+            is_encrypted: header.flags & FLAG_ENCRYPTED != 0,
+            is_compressed: header.flags & FLAG_COMPRESSED != 0,
+            checksum: format!("{:08x}", header.header_checksum),
+        })
+    }
+}
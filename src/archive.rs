@@ -0,0 +1,351 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::progress::ProgressTracker;
+use crate::{
+    create_capsule_set_from_file, extract_range_internal, CapsuleError, CapsuleResult, CapsuleSet,
+    ChecksumWriter, CreateCapsuleOptions, EncryptionKey,
+};
+
+// CapsuleMetadata.kind of sets holding an archive of several files
+pub const ARCHIVE_KIND: &str = "ARCHIVE";
+
+// Archive stream layout: magic, manifest length (u32 LE), JSON manifest, then
+// the entry contents back to back in manifest order
+const ARCHIVE_MAGIC: [u8; 8] = *b"DIGARCH1";
+const ARCHIVE_HEADER_SIZE: usize = 12;
+// Enough for the manifest of most archives, so listing decodes one range
+const MANIFEST_READ_AHEAD: u64 = 64 * 1024;
+const DEFAULT_MODE: u32 = 0o644;
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+const SYMLINK_MODE: u32 = 0o777;
+
+// ArchiveEntry.kind of entries that are not regular files
+pub const ENTRY_KIND_DIRECTORY: &str = "directory";
+pub const ENTRY_KIND_SYMLINK: &str = "symlink";
+
+// One file or buffer to pack into an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveInput {
+    pub name: String,
+    pub source: ArchiveSource,
+    pub mode: Option<u32>, // Defaults to the file's permissions, 0644 for buffers
+    pub mtime: Option<f64>, // Milliseconds since the Unix epoch
+}
+
+#[derive(Debug, Clone)]
+pub enum ArchiveSource {
+    Data(Vec<u8>),
+    File(PathBuf),
+    Directory,
+    Symlink(String), // Link target, stored as is
+}
+
+impl ArchiveInput {
+    pub fn from_data(name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        ArchiveInput {
+            name: name.into(),
+            source: ArchiveSource::Data(data.into()),
+            mode: None,
+            mtime: None,
+        }
+    }
+
+    pub fn from_file(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        ArchiveInput {
+            name: name.into(),
+            source: ArchiveSource::File(path.into()),
+            mode: None,
+            mtime: None,
+        }
+    }
+
+    // An empty directory entry, so directories without files are kept too
+    pub fn directory(name: impl Into<String>) -> Self {
+        ArchiveInput {
+            name: name.into(),
+            source: ArchiveSource::Directory,
+            mode: None,
+            mtime: None,
+        }
+    }
+
+    pub fn symlink(name: impl Into<String>, target: impl Into<String>) -> Self {
+        ArchiveInput {
+            name: name.into(),
+            source: ArchiveSource::Symlink(target.into()),
+            mode: None,
+            mtime: None,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn with_mtime(mut self, mtime: f64) -> Self {
+        self.mtime = Some(mtime);
+        self
+    }
+
+    fn open(&self) -> CapsuleResult<Box<dyn Read + '_>> {
+        Ok(match &self.source {
+            ArchiveSource::Data(data) => Box::new(data.as_slice()),
+            ArchiveSource::File(path) => {
+                Box::new(File::open(path).map_err(CapsuleError::at_path(path))?)
+            }
+            ArchiveSource::Directory | ArchiveSource::Symlink(_) => Box::new(std::io::empty()),
+        })
+    }
+
+    fn default_mode(&self) -> CapsuleResult<u32> {
+        match &self.source {
+            ArchiveSource::Data(_) => Ok(DEFAULT_MODE),
+            ArchiveSource::File(path) => {
+                let metadata = std::fs::metadata(path).map_err(CapsuleError::at_path(path))?;
+                Ok(metadata_mode(&metadata).unwrap_or(DEFAULT_MODE))
+            }
+            ArchiveSource::Directory => Ok(DEFAULT_DIRECTORY_MODE),
+            ArchiveSource::Symlink(_) => Ok(SYMLINK_MODE),
+        }
+    }
+
+    fn kind(&self) -> Option<&'static str> {
+        match &self.source {
+            ArchiveSource::Data(_) | ArchiveSource::File(_) => None,
+            ArchiveSource::Directory => Some(ENTRY_KIND_DIRECTORY),
+            ArchiveSource::Symlink(_) => Some(ENTRY_KIND_SYMLINK),
+        }
+    }
+}
+
+// Read/write/execute bits of a file or directory, on platforms that have them
+#[cfg(unix)]
+pub(crate) fn metadata_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn metadata_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+// A file stored in an archive, as recorded in its manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: f64,
+    pub mode: u32,
+    pub hash: String, // SHA-256 of the entry contents
+    pub offset: f64,  // Position of the contents after the manifest
+    // "directory" or "symlink"; regular files have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    // Milliseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<f64>,
+}
+
+impl ArchiveEntry {
+    pub fn is_directory(&self) -> bool {
+        self.kind.as_deref() == Some(ENTRY_KIND_DIRECTORY)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind.as_deref() == Some(ENTRY_KIND_SYMLINK)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveManifest {
+    entries: Vec<ArchiveEntry>,
+}
+
+// Entry names are relative `/`-separated paths without `.` or `..` segments,
+// so archives can be unpacked without escaping the target directory
+pub(crate) fn check_entry_name(name: &str) -> CapsuleResult<()> {
+    let valid = !name.contains(['\\', '\0'])
+        && name
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    if !valid {
+        return Err(CapsuleError::InvalidOption(format!(
+            "Invalid archive entry name: {:?}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+// Pack `inputs` into one stream and capsule it as an archive set. Entries are
+// ordered by name, so the same files always give the same set id.
+pub(crate) fn create_archive_capsule_internal(
+    inputs: &[ArchiveInput],
+    output_directory: &Path,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+) -> CapsuleResult<CapsuleSet> {
+    let mut inputs: Vec<&ArchiveInput> = inputs.iter().collect();
+    inputs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // First pass: sizes and hashes for the manifest
+    let mut entries = Vec::with_capacity(inputs.len());
+    let mut offset = 0u64;
+    for (position, input) in inputs.iter().enumerate() {
+        check_entry_name(&input.name)?;
+        if position > 0 && inputs[position - 1].name == input.name {
+            return Err(CapsuleError::InvalidOption(format!(
+                "Duplicate archive entry name: {}",
+                input.name
+            )));
+        }
+
+        let mut hasher = Sha256::default();
+        let size = std::io::copy(&mut input.open()?, &mut hasher)?;
+        entries.push(ArchiveEntry {
+            name: input.name.clone(),
+            size: size as f64,
+            mode: input.mode.map_or_else(|| input.default_mode(), Ok)?,
+            hash: hex::encode(hasher.finalize()),
+            offset: offset as f64,
+            kind: input.kind().map(str::to_string),
+            link_target: match &input.source {
+                ArchiveSource::Symlink(target) => Some(target.clone()),
+                _ => None,
+            },
+            mtime: input.mtime,
+        });
+        offset += size;
+    }
+    let manifest = ArchiveManifest { entries };
+    let manifest_json = serde_json::to_vec(&manifest)?;
+    let manifest_len = u32::try_from(manifest_json.len())
+        .map_err(|_| CapsuleError::InvalidOption("Archive manifest too large".to_string()))?;
+
+    // Second pass: write the stream, checking files did not change in between
+    let mut temp_file = tempfile::NamedTempFile::new()?;
+    {
+        let mut writer = BufWriter::new(temp_file.as_file_mut());
+        writer.write_all(&ARCHIVE_MAGIC)?;
+        writer.write_all(&manifest_len.to_le_bytes())?;
+        writer.write_all(&manifest_json)?;
+        for (input, entry) in inputs.iter().zip(&manifest.entries) {
+            let mut checksum_writer = ChecksumWriter::new(&mut writer);
+            std::io::copy(&mut input.open()?, &mut checksum_writer)?;
+            let hash = hex::encode(checksum_writer.finalize());
+            if hash != entry.hash {
+                return Err(CapsuleError::ChecksumMismatch {
+                    expected: entry.hash.clone(),
+                    actual: hash,
+                });
+            }
+        }
+        writer.flush()?;
+    }
+
+    create_capsule_set_from_file(
+        temp_file.path(),
+        output_directory,
+        post_process_padding,
+        encryption_key,
+        options,
+        Some(ARCHIVE_KIND),
+        &ProgressTracker::default(),
+    )
+}
+
+// Read an archive's manifest, decoding only the capsules it is stored in.
+// Returns the entries and where their contents start in the archive stream.
+pub(crate) fn read_manifest(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<(Vec<ArchiveEntry>, u64)> {
+    if !capsule_set.is_archive() {
+        return Err(CapsuleError::InvalidOption(format!(
+            "Capsule set {} is not an archive",
+            capsule_set.id
+        )));
+    }
+
+    let mut head = extract_range_internal(
+        capsule_set,
+        capsules_dir,
+        0,
+        MANIFEST_READ_AHEAD,
+        decryption_key.clone(),
+    )?;
+    if head.len() < ARCHIVE_HEADER_SIZE || head[..8] != ARCHIVE_MAGIC {
+        return Err(CapsuleError::InvalidFormat);
+    }
+    let manifest_len = u32::from_le_bytes(head[8..12].try_into().unwrap()) as u64;
+    let data_start = ARCHIVE_HEADER_SIZE as u64 + manifest_len;
+    if data_start > capsule_set.metadata.original_size as u64 {
+        return Err(CapsuleError::InvalidFormat);
+    }
+    if (head.len() as u64) < data_start {
+        let rest = extract_range_internal(
+            capsule_set,
+            capsules_dir,
+            head.len() as u64,
+            data_start - head.len() as u64,
+            decryption_key,
+        )?;
+        head.extend_from_slice(&rest);
+    }
+
+    let manifest: ArchiveManifest =
+        serde_json::from_slice(&head[ARCHIVE_HEADER_SIZE..data_start as usize])
+            .map_err(|_| CapsuleError::InvalidFormat)?;
+    Ok((manifest.entries, data_start))
+}
+
+pub(crate) fn list_archive_entries_internal(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<Vec<ArchiveEntry>> {
+    Ok(read_manifest(capsule_set, capsules_dir, decryption_key)?.0)
+}
+
+// Decode one entry of an archive and check it against its recorded hash
+pub(crate) fn extract_archive_entry_internal(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    name: &str,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<Vec<u8>> {
+    let (entries, data_start) = read_manifest(capsule_set, capsules_dir, decryption_key.clone())?;
+    let entry = entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| CapsuleError::EntryNotFound {
+            name: name.to_string(),
+        })?;
+
+    let data = extract_range_internal(
+        capsule_set,
+        capsules_dir,
+        data_start + entry.offset as u64,
+        entry.size as u64,
+        decryption_key,
+    )?;
+    let hash = hex::encode(Sha256::digest(&data));
+    if data.len() as u64 != entry.size as u64 || hash != entry.hash {
+        return Err(CapsuleError::ChecksumMismatch {
+            expected: entry.hash.clone(),
+            actual: hash,
+        });
+    }
+    Ok(data)
+}
@@ -0,0 +1,1019 @@
+use napi::bindgen_prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::archive::{
+    create_archive_capsule_internal, extract_archive_entry_internal, list_archive_entries_internal,
+};
+use crate::dictionary::train_compression_dictionary_internal;
+use crate::directory::{create_directory_capsule_internal, extract_directory_internal};
+use crate::progress::ProgressTracker;
+use crate::{
+    create_data_capsule_from_file_internal, create_data_capsule_internal, decode_capsule_internal,
+    decode_capsule_set, extract_range_internal, load_capsule_set_from_path,
+    reconstruct_file_from_capsules_internal, ArchiveEntry, ArchiveInput, CapsuleError,
+    CapsuleFileInfo, CapsuleResult, CapsuleSet, CreateCapsuleOptions, DirectoryFilter,
+    EncryptionKey, ErrorCode, MerkleProof, RecoveryReport, CAPSULE_SIZES, CONSENSUS_VERSION,
+    MIN_PADDING_PERCENT,
+};
+
+// NAPI Error conversion
+impl From<CapsuleError> for napi::Error<ErrorCode> {
+    fn from(err: CapsuleError) -> Self {
+        napi::Error::new(err.code(), err.to_string())
+    }
+}
+
+// Async tasks can only reject with a plain napi::Error, so build the JS error
+// (with its code) on the main thread and reject with that
+fn reject_with_code(env: Env, err: CapsuleError) -> napi::Error {
+    let js_error = JsError::from(napi::Error::from(err)).into_unknown(env);
+    napi::Error::from(js_error)
+}
+
+#[napi]
+pub fn create_data_capsule(
+    buffer_data: Buffer,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> Result<CapsuleSet, ErrorCode> {
+    create_data_capsule_internal(
+        &buffer_data,
+        Path::new(&output_directory),
+        post_process_padding,
+        encryption_key.map(EncryptionKey::from),
+        options,
+        &ProgressTracker::default(),
+    )
+    .map_err(Into::into)
+}
+
+#[napi]
+pub fn extract_data_capsule(
+    capsule_set_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<Buffer, ErrorCode> {
+    extract_data_capsule_internal(
+        Path::new(&capsule_set_path),
+        set_id.as_deref(),
+        decryption_key.map(EncryptionKey::from),
+        &ProgressTracker::default(),
+    )
+    .map(Buffer::from)
+    .map_err(Into::into)
+}
+
+// Internal helper function
+fn extract_data_capsule_internal(
+    capsule_set_path: &Path,
+    set_id: Option<&str>,
+    decryption_key: Option<EncryptionKey>,
+    progress: &ProgressTracker,
+) -> CapsuleResult<Vec<u8>> {
+    let (capsule_set, input_dir) = load_capsule_set_from_path(capsule_set_path, set_id)?;
+
+    let mut extracted_data = Vec::new();
+    decode_capsule_set(
+        &capsule_set,
+        &input_dir,
+        decryption_key,
+        &mut extracted_data,
+        progress,
+    )?;
+    Ok(extracted_data)
+}
+
+// Internal helper function
+fn extract_data_capsule_to_file_internal(
+    capsule_set_path: &Path,
+    set_id: Option<&str>,
+    output_file_path: &Path,
+    decryption_key: Option<EncryptionKey>,
+    progress: &ProgressTracker,
+) -> CapsuleResult<()> {
+    let (capsule_set, input_dir) = load_capsule_set_from_path(capsule_set_path, set_id)?;
+    reconstruct_file_from_capsules_internal(
+        &capsule_set,
+        &input_dir,
+        output_file_path,
+        decryption_key,
+        progress,
+    )
+}
+
+// Utility function for working directly with files (more efficient for large files)
+#[napi]
+pub fn create_data_capsule_from_file(
+    input_file_path: String,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> Result<CapsuleSet, ErrorCode> {
+    create_data_capsule_from_file_internal(
+        Path::new(&input_file_path),
+        Path::new(&output_directory),
+        post_process_padding,
+        encryption_key.map(EncryptionKey::from),
+        options,
+        &ProgressTracker::default(),
+    )
+    .map_err(Into::into)
+}
+
+// Utility function for extracting directly to file (more efficient for large files)
+#[napi]
+pub fn extract_data_capsule_to_file(
+    capsule_set_path: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<(), ErrorCode> {
+    extract_data_capsule_to_file_internal(
+        Path::new(&capsule_set_path),
+        set_id.as_deref(),
+        Path::new(&output_file_path),
+        decryption_key.map(EncryptionKey::from),
+        &ProgressTracker::default(),
+    )
+    .map_err(Into::into)
+}
+
+// Train a zstd dictionary from sample inputs and store it as a dictionary set
+// in the output directory; sets created there use it by passing its id as
+// `compression.dictionary`
+#[napi]
+pub fn train_compression_dictionary(
+    samples: Vec<Buffer>,
+    output_directory: String,
+    encryption_key: Option<Either<String, Buffer>>,
+    max_size: Option<u32>,
+) -> Result<CapsuleSet, ErrorCode> {
+    train_compression_dictionary_internal(
+        &samples,
+        max_size.map(|max_size| max_size as usize),
+        Path::new(&output_directory),
+        true,
+        encryption_key.map(EncryptionKey::from),
+        None,
+    )
+    .map_err(Into::into)
+}
+
+// A file or buffer to pack into an archive: either its `data` or the `path`
+// to read it from
+#[napi(object)]
+pub struct ArchiveEntryInput {
+    pub name: String,
+    pub data: Option<Buffer>,
+    pub path: Option<String>,
+    pub mode: Option<u32>,
+}
+
+impl TryFrom<ArchiveEntryInput> for ArchiveInput {
+    type Error = CapsuleError;
+
+    fn try_from(entry: ArchiveEntryInput) -> CapsuleResult<Self> {
+        let input = match (entry.data, entry.path) {
+            (Some(data), None) => ArchiveInput::from_data(entry.name, data.to_vec()),
+            (None, Some(path)) => ArchiveInput::from_file(entry.name, path),
+            _ => {
+                return Err(CapsuleError::InvalidOption(format!(
+                    "Archive entry {} needs either data or a path",
+                    entry.name
+                )))
+            }
+        };
+        Ok(match entry.mode {
+            Some(mode) => input.with_mode(mode),
+            None => input,
+        })
+    }
+}
+
+// Pack several files or buffers into a single capsule set
+#[napi]
+pub fn create_archive_capsule(
+    entries: Vec<ArchiveEntryInput>,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> Result<CapsuleSet, ErrorCode> {
+    let inputs = entries
+        .into_iter()
+        .map(ArchiveInput::try_from)
+        .collect::<CapsuleResult<Vec<_>>>()?;
+    create_archive_capsule_internal(
+        &inputs,
+        Path::new(&output_directory),
+        post_process_padding,
+        encryption_key.map(EncryptionKey::from),
+        options,
+    )
+    .map_err(Into::into)
+}
+
+#[napi]
+pub fn list_archive_entries(
+    capsule_set_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<Vec<ArchiveEntry>, ErrorCode> {
+    let (capsule_set, input_dir) =
+        load_capsule_set_from_path(Path::new(&capsule_set_path), set_id.as_deref())?;
+    list_archive_entries_internal(
+        &capsule_set,
+        &input_dir,
+        decryption_key.map(EncryptionKey::from),
+    )
+    .map_err(Into::into)
+}
+
+// Decode one file of an archive without extracting the rest
+#[napi]
+pub fn extract_archive_entry(
+    capsule_set_path: String,
+    name: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<Buffer, ErrorCode> {
+    let (capsule_set, input_dir) =
+        load_capsule_set_from_path(Path::new(&capsule_set_path), set_id.as_deref())?;
+    extract_archive_entry_internal(
+        &capsule_set,
+        &input_dir,
+        &name,
+        decryption_key.map(EncryptionKey::from),
+    )
+    .map(Buffer::from)
+    .map_err(Into::into)
+}
+
+// Capsule a directory tree as an archive set whose manifest records each
+// path's permissions, modification time and symlink target
+#[napi]
+pub fn create_data_capsule_from_directory(
+    input_directory: String,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+    filter: Option<DirectoryFilter>,
+) -> Result<CapsuleSet, ErrorCode> {
+    create_directory_capsule_internal(
+        Path::new(&input_directory),
+        Path::new(&output_directory),
+        post_process_padding,
+        encryption_key.map(EncryptionKey::from),
+        options,
+        &filter.unwrap_or_default(),
+    )
+    .map_err(Into::into)
+}
+
+// Restore the tree of an archive set into an empty or missing directory
+#[napi]
+pub fn extract_data_capsule_to_directory(
+    capsule_set_path: String,
+    output_directory: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+    filter: Option<DirectoryFilter>,
+) -> Result<Vec<ArchiveEntry>, ErrorCode> {
+    let (capsule_set, input_dir) =
+        load_capsule_set_from_path(Path::new(&capsule_set_path), set_id.as_deref())?;
+    extract_directory_internal(
+        &capsule_set,
+        &input_dir,
+        Path::new(&output_directory),
+        decryption_key.map(EncryptionKey::from),
+        &filter.unwrap_or_default(),
+    )
+    .map_err(Into::into)
+}
+
+#[napi]
+pub fn load_capsule_set(path: String, set_id: Option<String>) -> Result<CapsuleSet, ErrorCode> {
+    match set_id {
+        Some(set_id) => CapsuleSet::load_by_id(path, &set_id),
+        None => CapsuleSet::load(path),
+    }
+    .map_err(Into::into)
+}
+
+#[napi]
+pub fn list_capsule_sets(directory: String) -> Result<Vec<CapsuleSet>, ErrorCode> {
+    crate::list_capsule_sets(directory).map_err(Into::into)
+}
+
+#[napi]
+pub fn recover_capsule_directory(
+    directory: String,
+    dry_run: Option<bool>,
+) -> Result<RecoveryReport, ErrorCode> {
+    crate::recover_capsule_directory(directory, dry_run.unwrap_or(false)).map_err(Into::into)
+}
+
+#[napi]
+pub fn reconstruct_file_from_capsules(
+    capsule_set: CapsuleSet,
+    capsules_dir: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+) -> Result<(), ErrorCode> {
+    reconstruct_file_from_capsules_internal(
+        &capsule_set,
+        Path::new(&capsules_dir),
+        Path::new(&output_file_path),
+        decryption_key.map(EncryptionKey::from),
+        &ProgressTracker::default(),
+    )
+    .map_err(Into::into)
+}
+
+// Read `length` bytes of the original data starting at `offset`, decoding only
+// the capsules that overlap the range. Ranges running past the end are cut short.
+#[napi]
+pub fn extract_range(
+    capsule_set_path: String,
+    offset: f64,
+    length: f64,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<Buffer, ErrorCode> {
+    extract_range_at_path(
+        &capsule_set_path,
+        set_id.as_deref(),
+        offset,
+        length,
+        decryption_key.map(EncryptionKey::from),
+    )
+    .map(Buffer::from)
+    .map_err(Into::into)
+}
+
+fn extract_range_at_path(
+    capsule_set_path: &str,
+    set_id: Option<&str>,
+    offset: f64,
+    length: f64,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<Vec<u8>> {
+    let (capsule_set, capsules_dir) =
+        load_capsule_set_from_path(Path::new(capsule_set_path), set_id)?;
+    extract_range_internal(
+        &capsule_set,
+        &capsules_dir,
+        byte_position(offset)?,
+        byte_position(length)?,
+        decryption_key,
+    )
+}
+
+// JS numbers used as byte positions must be non-negative integers
+fn byte_position(value: f64) -> CapsuleResult<u64> {
+    if value >= 0.0 && value.fract() == 0.0 {
+        Ok(value as u64)
+    } else {
+        Err(CapsuleError::RangeOutOfBounds)
+    }
+}
+
+// Decode a single capsule file without the rest of its set
+#[napi]
+pub fn decode_capsule(
+    file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    capsule_set: Option<CapsuleSet>,
+) -> Result<Buffer, ErrorCode> {
+    let mut output = Vec::new();
+    decode_capsule_internal(
+        Path::new(&file_path),
+        decryption_key.map(EncryptionKey::from),
+        capsule_set.as_ref(),
+        &mut output,
+    )?;
+    Ok(Buffer::from(output))
+}
+
+#[napi]
+pub fn decode_capsule_to_file(
+    file_path: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    capsule_set: Option<CapsuleSet>,
+) -> Result<(), ErrorCode> {
+    let output_path = Path::new(&output_file_path);
+    let output_file = File::create(output_path).map_err(CapsuleError::at_path(output_path))?;
+    let mut writer = BufWriter::new(output_file);
+    decode_capsule_internal(
+        Path::new(&file_path),
+        decryption_key.map(EncryptionKey::from),
+        capsule_set.as_ref(),
+        &mut writer,
+    )?;
+    writer.flush().map_err(CapsuleError::at_path(output_path))?;
+    Ok(())
+}
+
+// Async variants: the same operations run on the libuv thread pool and resolve
+// a Promise, keeping the Node event loop free. JS-owned values (Buffer) stay in
+// the task and are only borrowed on the worker thread. Errors are carried to
+// `resolve` so the rejection is built with its `code` on the main thread.
+
+pub struct CreateDataCapsuleTask {
+    buffer_data: Buffer,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+}
+
+impl Task for CreateDataCapsuleTask {
+    type Output = CapsuleResult<CapsuleSet>;
+    type JsValue = CapsuleSet;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(create_data_capsule_internal(
+            &self.buffer_data,
+            Path::new(&self.output_directory),
+            self.post_process_padding,
+            self.encryption_key.take(),
+            self.options.take(),
+            &ProgressTracker::default(),
+        ))
+    }
+
+    fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        output.map_err(|err| reject_with_code(env, err))
+    }
+}
+
+#[napi]
+pub fn create_data_capsule_async(
+    buffer_data: Buffer,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> AsyncTask<CreateDataCapsuleTask> {
+    AsyncTask::new(CreateDataCapsuleTask {
+        buffer_data,
+        output_directory,
+        post_process_padding,
+        encryption_key: encryption_key.map(EncryptionKey::from),
+        options,
+    })
+}
+
+pub struct CreateDataCapsuleFromFileTask {
+    input_file_path: String,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+}
+
+impl Task for CreateDataCapsuleFromFileTask {
+    type Output = CapsuleResult<CapsuleSet>;
+    type JsValue = CapsuleSet;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(create_data_capsule_from_file_internal(
+            Path::new(&self.input_file_path),
+            Path::new(&self.output_directory),
+            self.post_process_padding,
+            self.encryption_key.take(),
+            self.options.take(),
+            &ProgressTracker::default(),
+        ))
+    }
+
+    fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        output.map_err(|err| reject_with_code(env, err))
+    }
+}
+
+#[napi]
+pub fn create_data_capsule_from_file_async(
+    input_file_path: String,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> AsyncTask<CreateDataCapsuleFromFileTask> {
+    AsyncTask::new(CreateDataCapsuleFromFileTask {
+        input_file_path,
+        output_directory,
+        post_process_padding,
+        encryption_key: encryption_key.map(EncryptionKey::from),
+        options,
+    })
+}
+
+pub struct ExtractDataCapsuleTask {
+    capsule_set_path: String,
+    set_id: Option<String>,
+    decryption_key: Option<EncryptionKey>,
+}
+
+impl Task for ExtractDataCapsuleTask {
+    type Output = CapsuleResult<Vec<u8>>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(extract_data_capsule_internal(
+            Path::new(&self.capsule_set_path),
+            self.set_id.as_deref(),
+            self.decryption_key.take(),
+            &ProgressTracker::default(),
+        ))
+    }
+
+    fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        output
+            .map(Buffer::from)
+            .map_err(|err| reject_with_code(env, err))
+    }
+}
+
+#[napi]
+pub fn extract_data_capsule_async(
+    capsule_set_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> AsyncTask<ExtractDataCapsuleTask> {
+    AsyncTask::new(ExtractDataCapsuleTask {
+        capsule_set_path,
+        set_id,
+        decryption_key: decryption_key.map(EncryptionKey::from),
+    })
+}
+
+pub struct ExtractDataCapsuleToFileTask {
+    capsule_set_path: String,
+    set_id: Option<String>,
+    output_file_path: String,
+    decryption_key: Option<EncryptionKey>,
+}
+
+impl Task for ExtractDataCapsuleToFileTask {
+    type Output = CapsuleResult<()>;
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(extract_data_capsule_to_file_internal(
+            Path::new(&self.capsule_set_path),
+            self.set_id.as_deref(),
+            Path::new(&self.output_file_path),
+            self.decryption_key.take(),
+            &ProgressTracker::default(),
+        ))
+    }
+
+    fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        output.map_err(|err| reject_with_code(env, err))
+    }
+}
+
+#[napi]
+pub fn extract_data_capsule_to_file_async(
+    capsule_set_path: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> AsyncTask<ExtractDataCapsuleToFileTask> {
+    AsyncTask::new(ExtractDataCapsuleToFileTask {
+        capsule_set_path,
+        set_id,
+        output_file_path,
+        decryption_key: decryption_key.map(EncryptionKey::from),
+    })
+}
+
+pub struct ReconstructFileFromCapsulesTask {
+    capsule_set: CapsuleSet,
+    capsules_dir: String,
+    output_file_path: String,
+    decryption_key: Option<EncryptionKey>,
+}
+
+impl Task for ReconstructFileFromCapsulesTask {
+    type Output = CapsuleResult<()>;
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(reconstruct_file_from_capsules_internal(
+            &self.capsule_set,
+            Path::new(&self.capsules_dir),
+            Path::new(&self.output_file_path),
+            self.decryption_key.take(),
+            &ProgressTracker::default(),
+        ))
+    }
+
+    fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        output.map_err(|err| reject_with_code(env, err))
+    }
+}
+
+#[napi]
+pub fn reconstruct_file_from_capsules_async(
+    capsule_set: CapsuleSet,
+    capsules_dir: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+) -> AsyncTask<ReconstructFileFromCapsulesTask> {
+    AsyncTask::new(ReconstructFileFromCapsulesTask {
+        capsule_set,
+        capsules_dir,
+        output_file_path,
+        decryption_key: decryption_key.map(EncryptionKey::from),
+    })
+}
+
+pub struct ExtractRangeTask {
+    capsule_set_path: String,
+    set_id: Option<String>,
+    offset: f64,
+    length: f64,
+    decryption_key: Option<EncryptionKey>,
+}
+
+impl Task for ExtractRangeTask {
+    type Output = CapsuleResult<Vec<u8>>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(extract_range_at_path(
+            &self.capsule_set_path,
+            self.set_id.as_deref(),
+            self.offset,
+            self.length,
+            self.decryption_key.take(),
+        ))
+    }
+
+    fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        output
+            .map(Buffer::from)
+            .map_err(|err| reject_with_code(env, err))
+    }
+}
+
+#[napi]
+pub fn extract_range_async(
+    capsule_set_path: String,
+    offset: f64,
+    length: f64,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> AsyncTask<ExtractRangeTask> {
+    AsyncTask::new(ExtractRangeTask {
+        capsule_set_path,
+        set_id,
+        offset,
+        length,
+        decryption_key: decryption_key.map(EncryptionKey::from),
+    })
+}
+
+// Handle-based variants: start the operation on the libuv thread pool and
+// return a handle for progress reporting and cancellation
+
+#[napi]
+pub fn start_create_data_capsule(
+    env: Env,
+    buffer_data: Buffer,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> Result<CapsuleOperationHandle> {
+    let encryption_key = encryption_key.map(EncryptionKey::from);
+    CapsuleOperationHandle::spawn(env, move |progress| {
+        create_data_capsule_internal(
+            &buffer_data,
+            Path::new(&output_directory),
+            post_process_padding,
+            encryption_key,
+            options,
+            progress,
+        )
+        .map(|capsule_set| OperationOutput::CapsuleSet(Box::new(capsule_set)))
+    })
+}
+
+#[napi]
+pub fn start_create_data_capsule_from_file(
+    env: Env,
+    input_file_path: String,
+    output_directory: String,
+    post_process_padding: bool,
+    encryption_key: Option<Either<String, Buffer>>,
+    options: Option<CreateCapsuleOptions>,
+) -> Result<CapsuleOperationHandle> {
+    let encryption_key = encryption_key.map(EncryptionKey::from);
+    CapsuleOperationHandle::spawn(env, move |progress| {
+        create_data_capsule_from_file_internal(
+            Path::new(&input_file_path),
+            Path::new(&output_directory),
+            post_process_padding,
+            encryption_key,
+            options,
+            progress,
+        )
+        .map(|capsule_set| OperationOutput::CapsuleSet(Box::new(capsule_set)))
+    })
+}
+
+#[napi]
+pub fn start_extract_data_capsule(
+    env: Env,
+    capsule_set_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<CapsuleOperationHandle> {
+    let decryption_key = decryption_key.map(EncryptionKey::from);
+    CapsuleOperationHandle::spawn(env, move |progress| {
+        extract_data_capsule_internal(
+            Path::new(&capsule_set_path),
+            set_id.as_deref(),
+            decryption_key,
+            progress,
+        )
+        .map(OperationOutput::Data)
+    })
+}
+
+#[napi]
+pub fn start_extract_data_capsule_to_file(
+    env: Env,
+    capsule_set_path: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+    set_id: Option<String>,
+) -> Result<CapsuleOperationHandle> {
+    let decryption_key = decryption_key.map(EncryptionKey::from);
+    CapsuleOperationHandle::spawn(env, move |progress| {
+        extract_data_capsule_to_file_internal(
+            Path::new(&capsule_set_path),
+            set_id.as_deref(),
+            Path::new(&output_file_path),
+            decryption_key,
+            progress,
+        )
+        .map(|_| OperationOutput::Empty)
+    })
+}
+
+#[napi]
+pub fn start_reconstruct_file_from_capsules(
+    env: Env,
+    capsule_set: CapsuleSet,
+    capsules_dir: String,
+    output_file_path: String,
+    decryption_key: Option<Either<String, Buffer>>,
+) -> Result<CapsuleOperationHandle> {
+    let decryption_key = decryption_key.map(EncryptionKey::from);
+    CapsuleOperationHandle::spawn(env, move |progress| {
+        reconstruct_file_from_capsules_internal(
+            &capsule_set,
+            Path::new(&capsules_dir),
+            Path::new(&output_file_path),
+            decryption_key,
+            progress,
+        )
+        .map(|_| OperationOutput::Empty)
+    })
+}
+
+#[napi]
+pub fn is_valid_capsule_file(file_path: String) -> Result<bool> {
+    Ok(CapsuleFileInfo::read(file_path).is_ok())
+}
+
+#[napi]
+pub fn get_capsule_file_info(file_path: String) -> Result<Option<CapsuleFileInfo>> {
+    Ok(CapsuleFileInfo::read(file_path).ok())
+}
+
+#[napi]
+pub fn calculate_storage_overhead(original_size: i64, capsule_count: u32) -> f64 {
+    if original_size == 0 {
+        return 0.0;
+    }
+
+    let min_padding_per_capsule = (CAPSULE_SIZES[0] as f64 * MIN_PADDING_PERCENT) as i64;
+    let total_min_padding = min_padding_per_capsule * capsule_count as i64;
+
+    (total_min_padding as f64 / original_size as f64) * 100.0
+}
+
+#[napi]
+pub fn get_capsule_sizes() -> Vec<u32> {
+    CAPSULE_SIZES.iter().map(|&size| size as u32).collect()
+}
+
+#[napi]
+pub fn get_consensus_version() -> String {
+    CONSENSUS_VERSION.to_string()
+}
+
+#[napi]
+pub fn validate_consensus_parameters(capsule_set: CapsuleSet) -> Result<bool, ErrorCode> {
+    capsule_set.validate_consensus()?;
+    Ok(true)
+}
+
+#[napi]
+pub fn get_capsule_merkle_root(capsule_set: CapsuleSet) -> Result<String, ErrorCode> {
+    capsule_set.merkle_root().map_err(Into::into)
+}
+
+#[napi]
+pub fn create_capsule_merkle_proof(
+    capsule_set: CapsuleSet,
+    capsule_index: u32,
+) -> Result<MerkleProof, ErrorCode> {
+    capsule_set.merkle_proof(capsule_index).map_err(Into::into)
+}
+
+// Check a proof against a trusted root and capsule count (both from the set
+// metadata) without needing the rest of the set
+#[napi]
+pub fn verify_capsule_merkle_proof(
+    merkle_root: String,
+    capsule_count: u32,
+    proof: MerkleProof,
+) -> Result<bool, ErrorCode> {
+    proof
+        .verify(&merkle_root, capsule_count)
+        .map_err(Into::into)
+}
+
+#[napi(object)]
+pub struct CapsuleProgress {
+    // Input hashed for the set id; creations hash all of it before writing capsules
+    pub bytes_hashed: f64,
+    pub bytes_processed: f64,
+    pub total_bytes: f64,
+    pub capsules_completed: u32,
+    pub total_capsules: u32,
+    pub elapsed_ms: f64,
+}
+
+// What a finished operation produced
+enum OperationOutput {
+    CapsuleSet(Box<CapsuleSet>),
+    Data(Vec<u8>),
+    Empty,
+}
+
+type Operation = Box<dyn FnOnce(&ProgressTracker) -> CapsuleResult<OperationOutput> + Send>;
+
+struct OperationState {
+    tracker: ProgressTracker,
+    started_at: Instant,
+    completed: AtomicBool,
+    outcome: Mutex<Option<CapsuleResult<OperationOutput>>>,
+}
+
+// Runs a handle's operation on the libuv thread pool. The outcome goes to the
+// handle rather than a Promise, so `resolve` has nothing to hand back.
+struct OperationTask {
+    state: Arc<OperationState>,
+    operation: Option<Operation>,
+}
+
+impl Task for OperationTask {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        if let Some(operation) = self.operation.take() {
+            let outcome = operation(&self.state.tracker);
+            *self.state.outcome.lock().unwrap() = Some(outcome);
+            self.state.completed.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+        Ok(())
+    }
+}
+
+// Handle for a capsule operation running in the background
+#[napi]
+pub struct CapsuleOperationHandle {
+    state: Arc<OperationState>,
+}
+
+impl CapsuleOperationHandle {
+    // Queue `operation` on the libuv thread pool, recording its outcome in the handle
+    fn spawn<F>(env: Env, operation: F) -> Result<Self>
+    where
+        F: FnOnce(&ProgressTracker) -> CapsuleResult<OperationOutput> + Send + 'static,
+    {
+        let state = Arc::new(OperationState {
+            tracker: ProgressTracker::default(),
+            started_at: Instant::now(),
+            completed: AtomicBool::new(false),
+            outcome: Mutex::new(None),
+        });
+
+        env.spawn(OperationTask {
+            state: Arc::clone(&state),
+            operation: Some(Box::new(operation)),
+        })?;
+
+        Ok(CapsuleOperationHandle { state })
+    }
+}
+
+#[napi]
+impl CapsuleOperationHandle {
+    #[napi]
+    pub fn cancel(&self) {
+        self.state.tracker.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[napi]
+    pub fn is_cancelled(&self) -> bool {
+        self.state.tracker.cancelled.load(Ordering::Relaxed)
+    }
+
+    // True once the operation has finished, whether it succeeded, failed or
+    // was cancelled; `hasError()` tells them apart
+    #[napi]
+    pub fn is_completed(&self) -> bool {
+        self.state.completed.load(Ordering::Acquire)
+    }
+
+    #[napi]
+    pub fn has_error(&self) -> bool {
+        matches!(*self.state.outcome.lock().unwrap(), Some(Err(_)))
+    }
+
+    #[napi]
+    pub fn get_error(&self) -> Option<String> {
+        match &*self.state.outcome.lock().unwrap() {
+            Some(Err(err)) => Some(err.to_string()),
+            _ => None,
+        }
+    }
+
+    // Same `code` a synchronous call would have thrown with
+    #[napi]
+    pub fn get_error_code(&self) -> Option<String> {
+        match &*self.state.outcome.lock().unwrap() {
+            Some(Err(err)) => Some(err.code().as_ref().to_string()),
+            _ => None,
+        }
+    }
+
+    // Capsule set for completed creations; extractions complete without one
+    #[napi]
+    pub fn get_result(&self) -> Option<CapsuleSet> {
+        match &*self.state.outcome.lock().unwrap() {
+            Some(Ok(OperationOutput::CapsuleSet(capsule_set))) => Some((**capsule_set).clone()),
+            _ => None,
+        }
+    }
+
+    // Extracted data for completed `startExtractDataCapsule` calls. The data is
+    // handed over rather than copied, so only the first call returns it.
+    #[napi]
+    pub fn get_data(&self) -> Option<Buffer> {
+        let mut outcome = self.state.outcome.lock().unwrap();
+        if !matches!(*outcome, Some(Ok(OperationOutput::Data(_)))) {
+            return None;
+        }
+        match outcome.replace(Ok(OperationOutput::Empty)) {
+            Some(Ok(OperationOutput::Data(data))) => Some(data.into()),
+            _ => None,
+        }
+    }
+
+    #[napi]
+    pub fn get_progress(&self) -> CapsuleProgress {
+        let tracker = &self.state.tracker;
+        CapsuleProgress {
+            bytes_hashed: tracker.bytes_hashed.load(Ordering::Relaxed) as f64,
+            bytes_processed: tracker.bytes_processed.load(Ordering::Relaxed) as f64,
+            total_bytes: tracker.total_bytes.load(Ordering::Relaxed) as f64,
+            capsules_completed: tracker.capsules_completed.load(Ordering::Relaxed),
+            total_capsules: tracker.total_capsules.load(Ordering::Relaxed),
+            elapsed_ms: self.state.started_at.elapsed().as_secs_f64() * 1000.0,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{CapsuleError, CapsuleResult, CompressionOptions};
+
+// Codec names recorded in CompressionInfo.algorithm
+pub const CODEC_GZIP: &str = "gzip";
+pub const CODEC_ZSTD: &str = "zstd";
+pub const CODEC_LZ4: &str = "lz4";
+
+// Codec ids stored in CapsuleHeader.reserved[0]; capsules written before
+// codecs were selectable carry zero there and are gzip
+const CODEC_ID_GZIP: u8 = 0;
+const CODEC_ID_ZSTD: u8 = 1;
+const CODEC_ID_LZ4: u8 = 2;
+
+// NETWORK CONSENSUS CRITICAL: capsule contents depend on the compression
+// level, so only these levels are accepted
+const GZIP_LEVEL: u32 = 6;
+const ZSTD_LEVELS: [u32; 4] = [1, 3, 9, 19];
+const DEFAULT_ZSTD_LEVEL: u32 = 3;
+const LZ4_LEVEL: u32 = 0; // The LZ4 frame format has no levels
+
+// Dictionary size trained when none is given, zstd's own default
+pub const DEFAULT_DICTIONARY_SIZE: usize = 112_640;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+impl Codec {
+    pub fn from_name(name: &str) -> CapsuleResult<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            CODEC_GZIP => Ok(Codec::Gzip),
+            CODEC_ZSTD | "zstandard" => Ok(Codec::Zstd),
+            CODEC_LZ4 => Ok(Codec::Lz4),
+            _ => Err(CapsuleError::ConsensusViolation(format!(
+                "Unsupported compression algorithm: {}",
+                name
+            ))),
+        }
+    }
+
+    pub fn from_id(id: u8) -> CapsuleResult<Self> {
+        match id {
+            CODEC_ID_GZIP => Ok(Codec::Gzip),
+            CODEC_ID_ZSTD => Ok(Codec::Zstd),
+            CODEC_ID_LZ4 => Ok(Codec::Lz4),
+            _ => Err(CapsuleError::ConsensusViolation(format!(
+                "Unsupported compression codec id: {}",
+                id
+            ))),
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        match self {
+            Codec::Gzip => CODEC_ID_GZIP,
+            Codec::Zstd => CODEC_ID_ZSTD,
+            Codec::Lz4 => CODEC_ID_LZ4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Gzip => CODEC_GZIP,
+            Codec::Zstd => CODEC_ZSTD,
+            Codec::Lz4 => CODEC_LZ4,
+        }
+    }
+
+    // The pinned level to use, rejecting levels outside the consensus set
+    fn level(&self, requested: Option<u32>) -> CapsuleResult<u32> {
+        let (default, allowed): (u32, &[u32]) = match self {
+            Codec::Gzip => (GZIP_LEVEL, &[GZIP_LEVEL]),
+            Codec::Zstd => (DEFAULT_ZSTD_LEVEL, &ZSTD_LEVELS),
+            Codec::Lz4 => (LZ4_LEVEL, &[LZ4_LEVEL]),
+        };
+        let level = requested.unwrap_or(default);
+        if !allowed.contains(&level) {
+            return Err(CapsuleError::ConsensusViolation(format!(
+                "Unsupported {} compression level: {}",
+                self.name(),
+                level
+            )));
+        }
+        Ok(level)
+    }
+}
+
+// Codec and level used for the capsules of a new set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionSettings {
+    pub codec: Codec,
+    pub level: u32,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        CompressionSettings {
+            codec: Codec::Gzip,
+            level: GZIP_LEVEL,
+        }
+    }
+}
+
+impl CompressionSettings {
+    pub fn from_options(options: &CompressionOptions) -> CapsuleResult<Self> {
+        let codec = Codec::from_name(&options.algorithm)?;
+        if options.dictionary.is_some() && codec != Codec::Zstd {
+            return Err(CapsuleError::InvalidOption(format!(
+                "Compression dictionaries require zstd, not {}",
+                codec.name()
+            )));
+        }
+        Ok(CompressionSettings {
+            codec,
+            level: codec.level(options.level)?,
+        })
+    }
+
+    // `dictionary` is only used by zstd; other codecs never get one
+    pub fn compress<R: Read, W: Write>(
+        &self,
+        dictionary: Option<&[u8]>,
+        reader: R,
+        writer: W,
+    ) -> CapsuleResult<u64> {
+        let mut reader = BufReader::new(reader);
+        match self.codec {
+            Codec::Gzip => {
+                let mut encoder = GzEncoder::new(writer, Compression::new(self.level));
+                let bytes_read = std::io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
+                Ok(bytes_read)
+            }
+            Codec::Zstd => {
+                let mut encoder = zstd::stream::Encoder::with_dictionary(
+                    writer,
+                    self.level as i32,
+                    dictionary.unwrap_or_default(),
+                )?;
+                let bytes_read = std::io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
+                Ok(bytes_read)
+            }
+            Codec::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+                let bytes_read = std::io::copy(&mut reader, &mut encoder)?;
+                encoder
+                    .finish()
+                    .map_err(|_| CapsuleError::CompressionFailed)?;
+                Ok(bytes_read)
+            }
+        }
+    }
+}
+
+pub fn decompress<R: Read, W: Write>(
+    codec: Codec,
+    dictionary: Option<&[u8]>,
+    reader: R,
+    writer: W,
+) -> CapsuleResult<u64> {
+    let mut writer = BufWriter::new(writer);
+    let bytes_written = match codec {
+        Codec::Gzip => std::io::copy(&mut GzDecoder::new(reader), &mut writer)?,
+        Codec::Zstd => std::io::copy(
+            &mut zstd::stream::Decoder::with_dictionary(
+                BufReader::new(reader),
+                dictionary.unwrap_or_default(),
+            )?,
+            &mut writer,
+        )?,
+        Codec::Lz4 => std::io::copy(&mut lz4_flex::frame::FrameDecoder::new(reader), &mut writer)?,
+    };
+    writer.flush()?;
+    Ok(bytes_written)
+}
+
+// Train a zstd dictionary of at most `max_size` bytes from sample inputs
+pub fn train_dictionary<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> CapsuleResult<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+        .map_err(|err| CapsuleError::InvalidOption(format!("Dictionary training failed: {}", err)))
+}
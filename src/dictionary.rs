@@ -0,0 +1,116 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::compression::{self, DEFAULT_DICTIONARY_SIZE};
+use crate::progress::ProgressTracker;
+use crate::{
+    create_capsule_set_from_file, decode_capsule_set, find_capsule_set, CapsuleError,
+    CapsuleResult, CapsuleSet, CreateCapsuleOptions, EncryptionKey,
+};
+
+// CapsuleMetadata.kind of sets holding a zstd compression dictionary.
+// Dictionaries are stored as ordinary capsule sets, so a dictionary's id is
+// the SHA-256 of its contents and its capsules are checked on decoding.
+pub const DICTIONARY_KIND: &str = "ZSTD_DICTIONARY";
+
+// Train a dictionary from `samples` and store it in `output_directory`, where
+// sets created with it must be stored too
+pub(crate) fn train_compression_dictionary_internal<S: AsRef<[u8]>>(
+    samples: &[S],
+    max_size: Option<usize>,
+    output_directory: &Path,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+) -> CapsuleResult<CapsuleSet> {
+    let dictionary =
+        compression::train_dictionary(samples, max_size.unwrap_or(DEFAULT_DICTIONARY_SIZE))?;
+
+    let mut temp_file = tempfile::NamedTempFile::new()?;
+    temp_file.write_all(&dictionary)?;
+
+    // The dictionary itself is compressed with the default codec
+    let options = CreateCapsuleOptions {
+        compression: None,
+        ..options.unwrap_or_default()
+    };
+    create_capsule_set_from_file(
+        temp_file.path(),
+        output_directory,
+        post_process_padding,
+        encryption_key,
+        Some(options),
+        Some(DICTIONARY_KIND),
+        &ProgressTracker::default(),
+    )
+}
+
+// The dictionary set `dictionary_id` stored in `dir`
+pub(crate) fn find(dir: &Path, dictionary_id: &str) -> CapsuleResult<CapsuleSet> {
+    let not_found = || CapsuleError::DictionaryNotFound {
+        hash: dictionary_id.to_string(),
+    };
+    if !dir.is_dir() {
+        return Err(not_found());
+    }
+    match find_capsule_set(dir, Some(dictionary_id)) {
+        Ok(capsule_set) if capsule_set.is_dictionary() => Ok(capsule_set),
+        Ok(_) | Err(CapsuleError::CapsuleSetNotFound { .. }) => Err(not_found()),
+        Err(err) => Err(err),
+    }
+}
+
+// Decode a dictionary set with the key of the sets compressed with it
+pub(crate) fn decode(
+    dictionary_set: &CapsuleSet,
+    dir: &Path,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<Vec<u8>> {
+    // Dictionaries are never compressed with a dictionary, which also keeps
+    // a set from referring to itself
+    if dictionary_set
+        .metadata
+        .compression_info
+        .as_ref()
+        .is_some_and(|info| info.dictionary.is_some())
+    {
+        return Err(CapsuleError::ConsensusViolation(
+            "Compression dictionary refers to another dictionary".to_string(),
+        ));
+    }
+
+    let mut dictionary = Vec::new();
+    decode_capsule_set(
+        dictionary_set,
+        dir,
+        decryption_key,
+        &mut dictionary,
+        &ProgressTracker::default(),
+    )?;
+    Ok(dictionary)
+}
+
+// The dictionary a set recorded in its compression info, if any
+pub(crate) fn load_for_set(
+    capsule_set: &CapsuleSet,
+    dir: &Path,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<Option<Vec<u8>>> {
+    let Some(dictionary_id) = capsule_set
+        .metadata
+        .compression_info
+        .as_ref()
+        .and_then(|info| info.dictionary.as_deref())
+    else {
+        return Ok(None);
+    };
+
+    let dictionary_set = find(dir, dictionary_id)?;
+    if dictionary_set.id != dictionary_id.to_lowercase() {
+        // Only the full id identifies the dictionary contents
+        return Err(CapsuleError::DictionaryNotFound {
+            hash: dictionary_id.to_string(),
+        });
+    }
+    decode(&dictionary_set, dir, decryption_key).map(Some)
+}
@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::archive::{
+    check_entry_name, create_archive_capsule_internal, metadata_mode, read_manifest, ArchiveEntry,
+    ArchiveInput,
+};
+use crate::progress::ProgressTracker;
+use crate::{
+    decode_capsule_set, CapsuleError, CapsuleResult, CapsuleSet, ChecksumWriter,
+    CreateCapsuleOptions, EncryptionKey,
+};
+
+// Globs choosing which paths of a tree are capsuled or restored. Patterns
+// match whole `/`-separated relative paths: `*` stays within one directory,
+// `**` spans any number. A matching directory brings everything below it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct DirectoryFilter {
+    pub include: Option<Vec<String>>, // Only these paths (and their parents); all by default
+    pub exclude: Option<Vec<String>>, // Skipped even when included
+}
+
+impl DirectoryFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_include(mut self, pattern: impl Into<String>) -> Self {
+        self.include
+            .get_or_insert_with(Vec::new)
+            .push(pattern.into());
+        self
+    }
+
+    pub fn with_exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude
+            .get_or_insert_with(Vec::new)
+            .push(pattern.into());
+        self
+    }
+}
+
+fn glob_set(patterns: &[String]) -> CapsuleResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| {
+                CapsuleError::InvalidOption(format!("Invalid glob pattern {}: {}", pattern, err))
+            })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|err| CapsuleError::InvalidOption(err.to_string()))
+}
+
+// The directories above an entry name, outermost first
+fn parents(name: &str) -> impl Iterator<Item = &str> {
+    name.match_indices('/')
+        .map(move |(index, _)| &name[..index])
+}
+
+struct PathMatcher {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathMatcher {
+    fn new(filter: &DirectoryFilter) -> CapsuleResult<Self> {
+        Ok(PathMatcher {
+            include: filter.include.as_deref().map(glob_set).transpose()?,
+            exclude: glob_set(filter.exclude.as_deref().unwrap_or_default())?,
+        })
+    }
+
+    fn excludes(&self, name: &str) -> bool {
+        parents(name)
+            .chain([name])
+            .any(|path| self.exclude.is_match(path))
+    }
+
+    fn includes(&self, name: &str) -> bool {
+        match &self.include {
+            Some(include) => parents(name)
+                .chain([name])
+                .any(|path| include.is_match(path)),
+            None => true,
+        }
+    }
+
+    // The names to keep, with the parent directories of every kept entry
+    fn select<'a>(&self, names: impl Iterator<Item = &'a str>) -> HashSet<&'a str> {
+        let mut selected = HashSet::new();
+        for name in names {
+            if self.includes(name) && !self.excludes(name) {
+                selected.extend(parents(name));
+                selected.insert(name);
+            }
+        }
+        selected
+    }
+}
+
+// The `/`-separated name of `path` below `root`
+fn entry_name(root: &Path, path: &Path) -> CapsuleResult<String> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let segments = relative
+        .iter()
+        .map(|segment| segment.to_str())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            CapsuleError::InvalidOption(format!("Path is not valid UTF-8: {}", path.display()))
+        })?;
+    Ok(segments.join("/"))
+}
+
+fn walk_error(err: walkdir::Error) -> CapsuleError {
+    match err.path().map(Path::to_path_buf) {
+        Some(path) => CapsuleError::at_path(&path)(err.into()),
+        None => std::io::Error::from(err).into(),
+    }
+}
+
+fn modified_millis(metadata: &fs::Metadata) -> Option<f64> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as f64)
+}
+
+// Walk `input_directory` without following symlinks. Sockets, FIFOs and
+// devices are skipped.
+fn directory_inputs(
+    input_directory: &Path,
+    matcher: &PathMatcher,
+) -> CapsuleResult<Vec<ArchiveInput>> {
+    if !input_directory.is_dir() {
+        return Err(CapsuleError::InvalidOption(format!(
+            "{} is not a directory",
+            input_directory.display()
+        )));
+    }
+
+    let walker = WalkDir::new(input_directory)
+        .min_depth(1)
+        .into_iter()
+        // Excluded directories are not read at all
+        .filter_entry(|entry| {
+            entry_name(input_directory, entry.path()).map_or(true, |name| !matcher.excludes(&name))
+        });
+
+    let mut inputs = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(walk_error)?;
+        let name = entry_name(input_directory, entry.path())?;
+        let metadata = entry.metadata().map_err(walk_error)?;
+        let file_type = entry.file_type();
+
+        let input = if file_type.is_symlink() {
+            let target =
+                fs::read_link(entry.path()).map_err(CapsuleError::at_path(entry.path()))?;
+            let target = target.to_str().ok_or_else(|| {
+                CapsuleError::InvalidOption(format!(
+                    "Link target is not valid UTF-8: {}",
+                    entry.path().display()
+                ))
+            })?;
+            // Link times cannot be restored portably, so none is recorded
+            inputs.push(ArchiveInput::symlink(name, target));
+            continue;
+        } else if file_type.is_dir() {
+            ArchiveInput::directory(name)
+        } else if file_type.is_file() {
+            ArchiveInput::from_file(name, entry.path())
+        } else {
+            continue;
+        };
+
+        let input = match metadata_mode(&metadata) {
+            Some(mode) => input.with_mode(mode),
+            None => input,
+        };
+        inputs.push(match modified_millis(&metadata) {
+            Some(mtime) => input.with_mtime(mtime),
+            None => input,
+        });
+    }
+
+    let selected = matcher.select(inputs.iter().map(|input| input.name.as_str()));
+    let selected: HashSet<String> = selected.into_iter().map(str::to_string).collect();
+    inputs.retain(|input| selected.contains(&input.name));
+    Ok(inputs)
+}
+
+// Capsule a directory tree as an archive set. The manifest records each
+// path's permissions and modification time, and symlinks as links.
+pub(crate) fn create_directory_capsule_internal(
+    input_directory: &Path,
+    output_directory: &Path,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+    filter: &DirectoryFilter,
+) -> CapsuleResult<CapsuleSet> {
+    let inputs = directory_inputs(input_directory, &PathMatcher::new(filter)?)?;
+    create_archive_capsule_internal(
+        &inputs,
+        output_directory,
+        post_process_padding,
+        encryption_key,
+        options,
+    )
+}
+
+// Manifests come from the set, so check them like untrusted input: nothing
+// may be written outside the output directory or through a restored symlink
+fn check_entries(entries: &[ArchiveEntry]) -> CapsuleResult<()> {
+    let symlinks: HashSet<&str> = entries
+        .iter()
+        .filter(|entry| entry.is_symlink())
+        .map(|entry| entry.name.as_str())
+        .collect();
+    for entry in entries {
+        if check_entry_name(&entry.name).is_err()
+            || parents(&entry.name).any(|parent| symlinks.contains(parent))
+            || (entry.kind.is_some() && !entry.is_directory() && !entry.is_symlink())
+        {
+            return Err(CapsuleError::InvalidFormat);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restore_mode(path: &Path, mode: u32) -> CapsuleResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // Setuid, setgid and sticky bits recorded in a manifest are never restored
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+        .map_err(CapsuleError::at_path(path))
+}
+
+#[cfg(not(unix))]
+fn restore_mode(_path: &Path, _mode: u32) -> CapsuleResult<()> {
+    Ok(())
+}
+
+fn restore_mtime(file: &File, path: &Path, mtime: Option<f64>) -> CapsuleResult<()> {
+    match mtime {
+        Some(mtime) if mtime.is_finite() && mtime >= 0.0 => file
+            .set_modified(UNIX_EPOCH + Duration::from_millis(mtime as u64))
+            .map_err(CapsuleError::at_path(path)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn restore_symlink(target: &str, path: &Path) -> CapsuleResult<()> {
+    std::os::unix::fs::symlink(target, path).map_err(CapsuleError::at_path(path))
+}
+
+#[cfg(not(unix))]
+fn restore_symlink(_target: &str, path: &Path) -> CapsuleResult<()> {
+    Err(CapsuleError::InvalidOption(format!(
+        "Symbolic links cannot be restored on this platform: {}",
+        path.display()
+    )))
+}
+
+// Copy one file entry out of the decoded archive stream, checking its hash
+fn restore_file<R: Read + Seek>(
+    stream: &mut R,
+    data_start: u64,
+    entry: &ArchiveEntry,
+    path: &Path,
+) -> CapsuleResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(CapsuleError::at_path(parent))?;
+    }
+    let file = File::create_new(path).map_err(CapsuleError::at_path(path))?;
+
+    stream.seek(SeekFrom::Start(data_start + entry.offset as u64))?;
+    let mut writer = BufWriter::new(&file);
+    let mut checksum_writer = ChecksumWriter::new(&mut writer);
+    let size = std::io::copy(
+        &mut stream.by_ref().take(entry.size as u64),
+        &mut checksum_writer,
+    )
+    .map_err(CapsuleError::at_path(path))?;
+    let hash = hex::encode(checksum_writer.finalize());
+    writer.flush().map_err(CapsuleError::at_path(path))?;
+    drop(writer);
+    if size != entry.size as u64 || hash != entry.hash {
+        return Err(CapsuleError::ChecksumMismatch {
+            expected: entry.hash.clone(),
+            actual: hash,
+        });
+    }
+
+    restore_mtime(&file, path, entry.mtime)?;
+    restore_mode(path, entry.mode)
+}
+
+// Restore the tree stored in an archive set under `output_directory`, which
+// must be empty or missing. Returns the entries restored.
+pub(crate) fn extract_directory_internal(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    output_directory: &Path,
+    decryption_key: Option<EncryptionKey>,
+    filter: &DirectoryFilter,
+) -> CapsuleResult<Vec<ArchiveEntry>> {
+    let matcher = PathMatcher::new(filter)?;
+    let (entries, data_start) = read_manifest(capsule_set, capsules_dir, decryption_key.clone())?;
+    check_entries(&entries)?;
+    let selected = matcher.select(entries.iter().map(|entry| entry.name.as_str()));
+    let selected: HashSet<String> = selected.into_iter().map(str::to_string).collect();
+    let entries: Vec<ArchiveEntry> = entries
+        .into_iter()
+        .filter(|entry| selected.contains(&entry.name))
+        .collect();
+
+    fs::create_dir_all(output_directory).map_err(CapsuleError::at_path(output_directory))?;
+    let mut existing =
+        fs::read_dir(output_directory).map_err(CapsuleError::at_path(output_directory))?;
+    if existing.next().is_some() {
+        return Err(CapsuleError::InvalidOption(format!(
+            "Output directory {} is not empty",
+            output_directory.display()
+        )));
+    }
+
+    // Decode the archive stream once and copy the entries out of it
+    let mut stream = tempfile::tempfile()?;
+    {
+        let mut writer = BufWriter::new(&mut stream);
+        decode_capsule_set(
+            capsule_set,
+            capsules_dir,
+            decryption_key,
+            &mut writer,
+            &ProgressTracker::default(),
+        )?;
+        writer.flush()?;
+    }
+
+    for entry in &entries {
+        let path = output_directory.join(&entry.name);
+        if entry.is_directory() {
+            fs::create_dir_all(&path).map_err(CapsuleError::at_path(&path))?;
+        } else if !entry.is_symlink() {
+            restore_file(&mut stream, data_start, entry, &path)?;
+        }
+    }
+
+    // Symlinks last, so no file is ever written through one
+    for entry in entries.iter().filter(|entry| entry.is_symlink()) {
+        let path = output_directory.join(&entry.name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(CapsuleError::at_path(parent))?;
+        }
+        restore_symlink(entry.link_target.as_deref().unwrap_or_default(), &path)?;
+    }
+
+    // Directory times and modes after their contents, innermost first:
+    // filling a directory changes its mtime and a read-only mode would stop it
+    for entry in entries.iter().rev().filter(|entry| entry.is_directory()) {
+        let path = output_directory.join(&entry.name);
+        #[cfg(unix)]
+        restore_mtime(
+            &File::open(&path).map_err(CapsuleError::at_path(&path))?,
+            &path,
+            entry.mtime,
+        )?;
+        restore_mode(&path, entry.mode)?;
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,504 @@
+#[cfg(feature = "napi")]
+use napi::bindgen_prelude::{Buffer, Either};
+use sha2::{Digest, Sha256};
+
+use crate::{CapsuleError, CapsuleResult, EncryptionInfo, KeyDerivationOptions};
+
+// Key derivation identifiers recorded in EncryptionInfo.key_derivation
+pub const KDF_LEGACY_SHA256: &str = "SHA256-DIG-SALT-V1";
+pub const KDF_PBKDF2: &str = "PBKDF2-HMAC-SHA256";
+pub const KDF_ARGON2ID: &str = "ARGON2ID";
+pub const KDF_SCRYPT: &str = "SCRYPT";
+pub const KDF_HKDF: &str = "HKDF-SHA256";
+pub const KDF_RAW: &str = "RAW";
+
+// Salt written by DIG_CAPSULE_V1 sets, which advertised PBKDF2 but used a single SHA-256
+const LEGACY_SALT: &str = "DIG_CAPSULE_SALT_V1";
+const HKDF_INFO: &[u8] = b"DIG_CAPSULE_KEY_V1";
+const SET_SALT_SIZE: usize = 16;
+const RAW_KEY_SIZE: usize = 32;
+
+// Defaults used when the caller picks an algorithm without parameters
+const DEFAULT_PBKDF2_ITERATIONS: u32 = 100_000;
+const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+const DEFAULT_SCRYPT_LOG_N: u32 = 15;
+const DEFAULT_SCRYPT_BLOCK_SIZE: u32 = 8;
+const DEFAULT_SCRYPT_PARALLELISM: u32 = 1;
+
+// Upper bounds on the work and memory hostile metadata can demand from extraction:
+// at most 1 GiB for Argon2id and for scrypt (128 * r * N * p bytes)
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_PARALLELISM: u32 = 16;
+const MAX_SCRYPT_LOG_N: u32 = 22;
+const MAX_SCRYPT_BLOCK_SIZE: u32 = 32;
+const MAX_SCRYPT_MEMORY: u64 = 1024 * 1024 * 1024;
+
+// Key material supplied by the caller
+#[derive(Clone)]
+pub enum EncryptionKey {
+    Passphrase(String),
+    Raw(Vec<u8>),
+}
+
+impl EncryptionKey {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            EncryptionKey::Passphrase(passphrase) => passphrase.as_bytes(),
+            EncryptionKey::Raw(bytes) => bytes,
+        }
+    }
+
+    // Derivation used for a new set when the caller did not pick one
+    pub fn default_derivation(&self) -> KeyDerivation {
+        match self {
+            EncryptionKey::Passphrase(_) => KeyDerivation::default(),
+            EncryptionKey::Raw(_) => KeyDerivation::Raw,
+        }
+    }
+}
+
+impl From<String> for EncryptionKey {
+    fn from(passphrase: String) -> Self {
+        EncryptionKey::Passphrase(passphrase)
+    }
+}
+
+impl From<&str> for EncryptionKey {
+    fn from(passphrase: &str) -> Self {
+        EncryptionKey::Passphrase(passphrase.to_string())
+    }
+}
+
+impl From<Vec<u8>> for EncryptionKey {
+    fn from(bytes: Vec<u8>) -> Self {
+        EncryptionKey::Raw(bytes)
+    }
+}
+
+impl From<&[u8]> for EncryptionKey {
+    fn from(bytes: &[u8]) -> Self {
+        EncryptionKey::Raw(bytes.to_vec())
+    }
+}
+
+#[cfg(feature = "napi")]
+impl From<Either<String, Buffer>> for EncryptionKey {
+    fn from(key: Either<String, Buffer>) -> Self {
+        match key {
+            Either::A(passphrase) => EncryptionKey::Passphrase(passphrase),
+            Either::B(bytes) => EncryptionKey::Raw(bytes.to_vec()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyDerivation {
+    LegacySha256,
+    Pbkdf2 {
+        iterations: u32,
+    },
+    Argon2id {
+        iterations: u32,
+        memory_kib: u32,
+        parallelism: u32,
+    },
+    Scrypt {
+        log_n: u32,
+        block_size: u32,
+        parallelism: u32,
+    },
+    Hkdf,
+    Raw,
+}
+
+impl Default for KeyDerivation {
+    fn default() -> Self {
+        KeyDerivation::Pbkdf2 {
+            iterations: DEFAULT_PBKDF2_ITERATIONS,
+        }
+    }
+}
+
+impl KeyDerivation {
+    // Resolve caller-supplied options for a new capsule set, filling in defaults
+    pub fn from_options(options: &KeyDerivationOptions) -> CapsuleResult<Self> {
+        let kdf = match options.algorithm.to_ascii_uppercase().as_str() {
+            KDF_PBKDF2 | "PBKDF2" => KeyDerivation::Pbkdf2 {
+                iterations: options.iterations.unwrap_or(DEFAULT_PBKDF2_ITERATIONS),
+            },
+            KDF_ARGON2ID => KeyDerivation::Argon2id {
+                iterations: options.iterations.unwrap_or(DEFAULT_ARGON2_ITERATIONS),
+                memory_kib: options.memory_cost.unwrap_or(DEFAULT_ARGON2_MEMORY_KIB),
+                parallelism: options.parallelism.unwrap_or(DEFAULT_ARGON2_PARALLELISM),
+            },
+            KDF_SCRYPT => KeyDerivation::Scrypt {
+                log_n: options.log_n.unwrap_or(DEFAULT_SCRYPT_LOG_N),
+                block_size: options.block_size.unwrap_or(DEFAULT_SCRYPT_BLOCK_SIZE),
+                parallelism: options.parallelism.unwrap_or(DEFAULT_SCRYPT_PARALLELISM),
+            },
+            KDF_HKDF | "HKDF" => KeyDerivation::Hkdf,
+            KDF_RAW => KeyDerivation::Raw,
+            _ => {
+                return Err(CapsuleError::ConsensusViolation(format!(
+                    "Unsupported key derivation: {}",
+                    options.algorithm
+                )))
+            }
+        };
+
+        kdf.validate()?;
+        Ok(kdf)
+    }
+
+    // Recover the key derivation an existing capsule set was created with
+    pub fn from_encryption_info(info: &EncryptionInfo) -> CapsuleResult<Self> {
+        let kdf = match info.key_derivation.as_str() {
+            KDF_LEGACY_SHA256 => KeyDerivation::LegacySha256,
+            // DIG_CAPSULE_V1 sets claimed PBKDF2 while actually hashing once with a fixed salt
+            KDF_PBKDF2 if info.salt == LEGACY_SALT => KeyDerivation::LegacySha256,
+            KDF_PBKDF2 => KeyDerivation::Pbkdf2 {
+                iterations: info.iterations,
+            },
+            KDF_ARGON2ID => KeyDerivation::Argon2id {
+                iterations: info.iterations,
+                memory_kib: info.memory_cost.ok_or(CapsuleError::InvalidFormat)?,
+                parallelism: info.parallelism.ok_or(CapsuleError::InvalidFormat)?,
+            },
+            KDF_SCRYPT => KeyDerivation::Scrypt {
+                log_n: info.log_n.ok_or(CapsuleError::InvalidFormat)?,
+                block_size: info.block_size.ok_or(CapsuleError::InvalidFormat)?,
+                parallelism: info.parallelism.ok_or(CapsuleError::InvalidFormat)?,
+            },
+            KDF_HKDF => KeyDerivation::Hkdf,
+            KDF_RAW => KeyDerivation::Raw,
+            _ => {
+                return Err(CapsuleError::ConsensusViolation(
+                    "Unsupported key derivation".to_string(),
+                ))
+            }
+        };
+
+        kdf.validate()?;
+        Ok(kdf)
+    }
+
+    fn validate(&self) -> CapsuleResult<()> {
+        let valid = match *self {
+            KeyDerivation::LegacySha256 | KeyDerivation::Hkdf | KeyDerivation::Raw => true,
+            KeyDerivation::Pbkdf2 { iterations } => {
+                (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations)
+            }
+            KeyDerivation::Argon2id {
+                iterations,
+                memory_kib,
+                parallelism,
+            } => {
+                (1..=MAX_ARGON2_ITERATIONS).contains(&iterations)
+                    && (1..=MAX_PARALLELISM).contains(&parallelism)
+                    && memory_kib >= 8 * parallelism
+                    && memory_kib <= MAX_ARGON2_MEMORY_KIB
+            }
+            KeyDerivation::Scrypt {
+                log_n,
+                block_size,
+                parallelism,
+            } => {
+                (1..=MAX_SCRYPT_LOG_N).contains(&log_n)
+                    && (1..=MAX_SCRYPT_BLOCK_SIZE).contains(&block_size)
+                    && (1..=MAX_PARALLELISM).contains(&parallelism)
+                    && 128 * block_size as u64 * (1u64 << log_n) * parallelism as u64
+                        <= MAX_SCRYPT_MEMORY
+            }
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(CapsuleError::ConsensusViolation(
+                "Invalid key derivation parameters".to_string(),
+            ))
+        }
+    }
+
+    // Creation-time check that the caller's key suits the chosen derivation: HKDF
+    // only expands key material that is already uniformly random
+    pub fn check_key(&self, key: &EncryptionKey) -> CapsuleResult<()> {
+        if *self == KeyDerivation::Hkdf && matches!(key, EncryptionKey::Passphrase(_)) {
+            return Err(CapsuleError::InvalidOption(format!(
+                "{} requires a raw binary key",
+                KDF_HKDF
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyDerivation::LegacySha256 => KDF_LEGACY_SHA256,
+            KeyDerivation::Pbkdf2 { .. } => KDF_PBKDF2,
+            KeyDerivation::Argon2id { .. } => KDF_ARGON2ID,
+            KeyDerivation::Scrypt { .. } => KDF_SCRYPT,
+            KeyDerivation::Hkdf => KDF_HKDF,
+            KeyDerivation::Raw => KDF_RAW,
+        }
+    }
+
+    // Whether the derivation consumes the per-set salt recorded in metadata
+    pub fn uses_set_salt(&self) -> bool {
+        !matches!(self, KeyDerivation::LegacySha256 | KeyDerivation::Raw)
+    }
+
+    pub fn derive_key(&self, key: &EncryptionKey, salt: &[u8]) -> CapsuleResult<[u8; 32]> {
+        let secret = key.as_bytes();
+        let mut key_bytes = [0u8; 32];
+        match *self {
+            KeyDerivation::Raw => {
+                // Binary keys are used as-is; passphrases never bypass derivation
+                if !matches!(key, EncryptionKey::Raw(_)) {
+                    return Err(CapsuleError::InvalidKey(
+                        "capsule set requires a raw binary key".to_string(),
+                    ));
+                }
+                if secret.len() != RAW_KEY_SIZE {
+                    return Err(CapsuleError::InvalidKey(format!(
+                        "raw keys must be {} bytes",
+                        RAW_KEY_SIZE
+                    )));
+                }
+                key_bytes.copy_from_slice(secret);
+            }
+            KeyDerivation::LegacySha256 => {
+                let mut hasher = Sha256::default();
+                hasher.update(secret);
+                hasher.update(LEGACY_SALT.as_bytes());
+                key_bytes.copy_from_slice(&hasher.finalize());
+            }
+            KeyDerivation::Pbkdf2 { iterations } => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(secret, salt, iterations, &mut key_bytes);
+            }
+            KeyDerivation::Argon2id {
+                iterations,
+                memory_kib,
+                parallelism,
+            } => {
+                let params = argon2::Params::new(memory_kib, iterations, parallelism, Some(32))
+                    .map_err(|_| CapsuleError::EncryptionFailed)?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(secret, salt, &mut key_bytes)
+                    .map_err(|_| CapsuleError::EncryptionFailed)?;
+            }
+            KeyDerivation::Scrypt {
+                log_n,
+                block_size,
+                parallelism,
+            } => {
+                let params = scrypt::Params::new(log_n as u8, block_size, parallelism, 32)
+                    .map_err(|_| CapsuleError::EncryptionFailed)?;
+                scrypt::scrypt(secret, salt, &params, &mut key_bytes)
+                    .map_err(|_| CapsuleError::EncryptionFailed)?;
+            }
+            KeyDerivation::Hkdf => {
+                hkdf::Hkdf::<Sha256>::new(Some(salt), secret)
+                    .expand(HKDF_INFO, &mut key_bytes)
+                    .map_err(|_| CapsuleError::EncryptionFailed)?;
+            }
+        }
+        Ok(key_bytes)
+    }
+
+    // Describe this derivation (and the salt it was used with) for the set metadata
+    pub fn encryption_info(&self, salt: &[u8]) -> EncryptionInfo {
+        let mut info = EncryptionInfo {
+            algorithm: "AES-256-GCM".to_string(),
+            key_derivation: self.name().to_string(),
+            iterations: 0,
+            salt: match self {
+                KeyDerivation::LegacySha256 => LEGACY_SALT.to_string(),
+                KeyDerivation::Raw => String::new(),
+                _ => hex::encode(salt),
+            },
+            memory_cost: None,
+            parallelism: None,
+            log_n: None,
+            block_size: None,
+            nonce_scheme: None,
+            nonce_prefix: None,
+        };
+
+        match *self {
+            KeyDerivation::LegacySha256 => info.iterations = 1,
+            KeyDerivation::Pbkdf2 { iterations } => info.iterations = iterations,
+            KeyDerivation::Argon2id {
+                iterations,
+                memory_kib,
+                parallelism,
+            } => {
+                info.iterations = iterations;
+                info.memory_cost = Some(memory_kib);
+                info.parallelism = Some(parallelism);
+            }
+            KeyDerivation::Scrypt {
+                log_n,
+                block_size,
+                parallelism,
+            } => {
+                info.log_n = Some(log_n);
+                info.block_size = Some(block_size);
+                info.parallelism = Some(parallelism);
+            }
+            KeyDerivation::Hkdf | KeyDerivation::Raw => {}
+        }
+
+        info
+    }
+}
+
+// NETWORK CONSENSUS CRITICAL: Per-set KDF salt derived from the set's encoding
+// context (set id and encoding parameters), so every set and every encoding of
+// it gets its own salt while identical encodings still produce identical capsules
+pub fn derive_set_salt(context: &[u8]) -> [u8; SET_SALT_SIZE] {
+    let mut hasher = Sha256::default();
+    hasher.update(b"DIG_KDF_SALT_V1");
+    hasher.update(context);
+    let hash = hasher.finalize();
+    let mut salt = [0u8; SET_SALT_SIZE];
+    salt.copy_from_slice(&hash[..SET_SALT_SIZE]);
+    salt
+}
+
+// Salt bytes to feed the KDF of an existing set
+pub fn parse_set_salt(kdf: &KeyDerivation, info: &EncryptionInfo) -> CapsuleResult<Vec<u8>> {
+    if !kdf.uses_set_salt() {
+        return Ok(Vec::new());
+    }
+    hex::decode(&info.salt).map_err(|_| CapsuleError::InvalidFormat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(algorithm: &str) -> KeyDerivationOptions {
+        KeyDerivationOptions {
+            algorithm: algorithm.to_string(),
+            iterations: None,
+            memory_cost: None,
+            parallelism: None,
+            log_n: None,
+            block_size: None,
+        }
+    }
+
+    #[test]
+    fn options_fill_in_defaults() {
+        assert_eq!(
+            KeyDerivation::from_options(&options("pbkdf2")).unwrap(),
+            KeyDerivation::default()
+        );
+        assert_eq!(
+            KeyDerivation::from_options(&options(KDF_SCRYPT)).unwrap(),
+            KeyDerivation::Scrypt {
+                log_n: DEFAULT_SCRYPT_LOG_N,
+                block_size: DEFAULT_SCRYPT_BLOCK_SIZE,
+                parallelism: DEFAULT_SCRYPT_PARALLELISM,
+            }
+        );
+        assert!(KeyDerivation::from_options(&options("BCRYPT")).is_err());
+    }
+
+    #[test]
+    fn memory_hard_parameters_are_bounded() {
+        let argon2 = |memory_kib| KeyDerivation::Argon2id {
+            iterations: 1,
+            memory_kib,
+            parallelism: 1,
+        };
+        assert!(argon2(MAX_ARGON2_MEMORY_KIB).validate().is_ok());
+        assert!(argon2(MAX_ARGON2_MEMORY_KIB + 1).validate().is_err());
+        assert!(argon2(7).validate().is_err());
+
+        let scrypt = |log_n, block_size| KeyDerivation::Scrypt {
+            log_n,
+            block_size,
+            parallelism: 1,
+        };
+        // 128 * r * N bytes: 1 GiB at r = 8, N = 2^20
+        assert!(scrypt(20, 8).validate().is_ok());
+        assert!(scrypt(21, 8).validate().is_err());
+        assert!(KeyDerivation::Pbkdf2 { iterations: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn encryption_info_round_trips() {
+        let salt = derive_set_salt(b"context");
+        for kdf in [
+            KeyDerivation::default(),
+            KeyDerivation::Argon2id {
+                iterations: 2,
+                memory_kib: 64,
+                parallelism: 1,
+            },
+            KeyDerivation::Scrypt {
+                log_n: 10,
+                block_size: 8,
+                parallelism: 1,
+            },
+            KeyDerivation::Hkdf,
+            KeyDerivation::Raw,
+            KeyDerivation::LegacySha256,
+        ] {
+            let info = kdf.encryption_info(&salt);
+            assert_eq!(KeyDerivation::from_encryption_info(&info).unwrap(), kdf);
+            let expected_salt = if kdf.uses_set_salt() {
+                salt.to_vec()
+            } else {
+                Vec::new()
+            };
+            assert_eq!(parse_set_salt(&kdf, &info).unwrap(), expected_salt);
+        }
+    }
+
+    #[test]
+    fn version_one_pbkdf2_metadata_reads_as_legacy() {
+        let mut info = KeyDerivation::default().encryption_info(&[]);
+        info.salt = LEGACY_SALT.to_string();
+        assert_eq!(
+            KeyDerivation::from_encryption_info(&info).unwrap(),
+            KeyDerivation::LegacySha256
+        );
+    }
+
+    #[test]
+    fn keys_must_suit_the_derivation() {
+        let passphrase = EncryptionKey::from("passphrase");
+        let raw = EncryptionKey::from(vec![1u8; RAW_KEY_SIZE]);
+
+        assert!(KeyDerivation::Hkdf.check_key(&passphrase).is_err());
+        assert!(KeyDerivation::Hkdf.check_key(&raw).is_ok());
+        assert!(KeyDerivation::Raw.derive_key(&passphrase, &[]).is_err());
+        assert!(KeyDerivation::Raw
+            .derive_key(&EncryptionKey::from(vec![1u8; 16]), &[])
+            .is_err());
+        assert_eq!(
+            KeyDerivation::Raw.derive_key(&raw, &[]).unwrap(),
+            [1u8; RAW_KEY_SIZE]
+        );
+    }
+
+    #[test]
+    fn salts_depend_on_the_context() {
+        let kdf = KeyDerivation::Pbkdf2 { iterations: 1 };
+        let key = EncryptionKey::from("passphrase");
+
+        assert_eq!(derive_set_salt(b"a"), derive_set_salt(b"a"));
+        assert_ne!(derive_set_salt(b"a"), derive_set_salt(b"b"));
+        assert_ne!(
+            kdf.derive_key(&key, &derive_set_salt(b"a")).unwrap(),
+            kdf.derive_key(&key, &derive_set_salt(b"b")).unwrap()
+        );
+    }
+}
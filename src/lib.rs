@@ -1,14 +1,16 @@
-use napi::bindgen_prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use aes_gcm::{
     aead::{Aead, KeyInit},
     Aes256Gcm, Key, Nonce,
 };
-use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use sha2::{Digest, Sha256};
 use std::fs;
 
@@ -16,12 +18,35 @@ use std::fs;
 use memmap2::Mmap;
 use smallvec::SmallVec;
 
+mod api;
+mod archive;
+#[cfg(feature = "napi")]
+pub mod bindings;
+mod compression;
+mod dictionary;
+mod directory;
+mod kdf;
+mod merkle;
+mod naming;
+mod progress;
+mod segmented;
+pub use api::{list_capsule_sets, recover_capsule_directory, CapsuleReader, CapsuleWriter};
+pub use archive::{ArchiveEntry, ArchiveInput, ArchiveSource};
+use compression::{Codec, CompressionSettings};
+pub use directory::DirectoryFilter;
+pub use kdf::EncryptionKey;
+use kdf::KeyDerivation;
+pub use naming::{ConflictPolicy, NamingScheme};
+use progress::ProgressTracker;
+
+#[cfg(feature = "napi")]
 #[macro_use]
 extern crate napi_derive;
 
 // Constants for capsule sizes (NETWORK CONSENSUS CRITICAL)
 const KB: usize = 1024;
 const MB: usize = 1024 * KB;
+const HASH_BUFFER_SIZE: usize = MB; // Input hashed per progress update when creating from a file
 const CAPSULE_SIZES: [usize; 5] = [
     256 * KB,  // 262,144 bytes
     MB,        // 1,048,576 bytes
@@ -37,78 +62,165 @@ const MIN_PADDING_PERCENT: f64 = 0.05; // 5% minimum padding
 // CAPSULE FILE FORMAT CONSTANTS
 const CAPSULE_MAGIC: [u8; 8] = *b"DIGCAP01"; // Magic bytes for capsule identification
 const CAPSULE_HEADER_SIZE: usize = 44; // Total header size in bytes
-const CAPSULE_VERSION: u32 = 1; // Current capsule format version
+const CAPSULE_VERSION_V1: u32 = 1; // Padding framed by marker + size footer in the body
+const CAPSULE_VERSION: u32 = 2; // Current format: exact payload length in the header
 
 // Header flags
 const FLAG_ENCRYPTED: u32 = 0x01;
 const FLAG_COMPRESSED: u32 = 0x02;
+const FLAG_SET_NONCE: u32 = 0x04; // Nonce derived from per-set prefix instead of "DIG1"
+const FLAG_COMPRESS_FIRST: u32 = 0x08; // Plaintext compressed before encryption (DIG_CAPSULE_V2)
+const FLAG_INNER_PADDING: u32 = 0x10; // Padding inside the encryption envelope (postProcessPadding = false)
+const FLAG_SEGMENTED: u32 = 0x20; // Encrypted as 64 KiB AES-GCM segments under a per-capsule subkey
+
+// Envelope framing sizes
+const INNER_LENGTH_SIZE: usize = 4; // Payload length prefix inside the envelope
+
+const PENDING_FILE_SUFFIX: &str = ".tmp";
+// Recovery only removes temporary files left untouched this long; younger ones
+// may still belong to a running write
+const PENDING_FILE_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+// Capsule set metadata is stored as `{set id or its first 16 hex digits}_metadata.json`
+const METADATA_FILE_SUFFIX: &str = "_metadata.json";
+
+// Pipeline versions recorded in CapsuleMetadata.consensus_version
+const CONSENSUS_VERSION_V1: &str = "DIG_CAPSULE_V1"; // encrypt -> compress -> pad
+const CONSENSUS_VERSION: &str = "DIG_CAPSULE_V2"; // compress -> encrypt -> pad
+const SUPPORTED_CONSENSUS_VERSIONS: [&str; 2] = [CONSENSUS_VERSION_V1, CONSENSUS_VERSION];
+
+// Nonce schemes recorded in EncryptionInfo
+const NONCE_SCHEME_LEGACY: &str = "DIG1_INDEX"; // chunk index || "DIG1" || zeros
+const NONCE_SCHEME_SET_PREFIX: &str = "DIG_SET_PREFIX_V1"; // per-set prefix || chunk index
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(object)]
+#[cfg_attr(feature = "napi", napi(object))]
 pub struct Capsule {
     pub index: u32,
     pub size: u32,
     pub hash: String,
     pub encrypted: bool,
     pub compressed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding_post_process: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(object)]
+#[cfg_attr(feature = "napi", napi(object))]
 pub struct EncryptionInfo {
     pub algorithm: String,
-    #[napi(js_name = "keyDerivation")]
     pub key_derivation: String,
     pub iterations: u32,
     pub salt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_cost: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallelism: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_n: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce_scheme: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce_prefix: Option<String>,
+}
+
+// Key derivation selection for new capsule sets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct KeyDerivationOptions {
+    pub algorithm: String, // "PBKDF2-HMAC-SHA256", "ARGON2ID", "SCRYPT" or "HKDF-SHA256"
+    pub iterations: Option<u32>,
+    pub memory_cost: Option<u32>, // Argon2id memory in KiB
+    pub parallelism: Option<u32>,
+    pub log_n: Option<u32>,      // scrypt cost (N = 2^logN)
+    pub block_size: Option<u32>, // scrypt r
 }
 
+// Compression selection for new capsule sets
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(object)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct CompressionOptions {
+    pub algorithm: String,  // "gzip" (default), "zstd" or "lz4"
+    pub level: Option<u32>, // gzip: 6; zstd: 1, 3 (default), 9 or 19; lz4: none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dictionary: Option<String>, // zstd only: id of a dictionary set in the output directory
+}
+
+// Optional settings for capsule creation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct CreateCapsuleOptions {
+    pub key_derivation: Option<KeyDerivationOptions>,
+    pub compression: Option<CompressionOptions>,
+    pub naming_scheme: Option<String>, // "SET_ID" (default), "CAPSULE_HASH" or "SET_DIRECTORY"
+    pub on_conflict: Option<String>,   // "SKIP" (default), "FAIL" or "OVERWRITE"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
 pub struct CompressionInfo {
     pub algorithm: String,
     pub level: u32,
-    #[napi(js_name = "originalSize")]
     pub original_size: f64,
+    // Id of the dictionary set stored next to this one, needed to decompress it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dictionary: Option<String>,
+    // Capsules actually stored compressed; chunks that did not shrink are
+    // stored raw. Absent for sets created before raw chunks were possible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_capsules: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(object)]
+#[cfg_attr(feature = "napi", napi(object))]
 pub struct CapsuleMetadata {
-    #[napi(js_name = "originalSize")]
     pub original_size: f64,
-    #[napi(js_name = "capsuleCount")]
     pub capsule_count: u32,
-    #[napi(js_name = "capsuleSizes")]
     pub capsule_sizes: Vec<u32>,
     pub checksum: String,
-    #[napi(js_name = "chunkingAlgorithm")]
     pub chunking_algorithm: String,
-    #[napi(js_name = "consensusVersion")]
     pub consensus_version: String,
-    #[napi(js_name = "encryptionInfo")]
     pub encryption_info: Option<EncryptionInfo>,
-    #[napi(js_name = "compressionInfo")]
     pub compression_info: Option<CompressionInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub naming_scheme: Option<String>,
+    // "ZSTD_DICTIONARY" for compression dictionaries, "ARCHIVE" for archives
+    // of several files, absent for plain data sets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(object)]
+#[cfg_attr(feature = "napi", napi(object))]
 pub struct CapsuleSet {
     pub id: String,
     pub capsules: Vec<Capsule>,
     pub metadata: CapsuleMetadata,
 }
 
+// Inclusion proof that a capsule hash is leaf `capsule_index` of a set's Merkle tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct MerkleProof {
+    pub capsule_index: u32,
+    pub capsule_hash: String,
+    pub leaf_count: u32,
+    pub siblings: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct CapsuleHeader {
     pub magic: [u8; 8],       // "DIGCAP01"
     pub version: u32,         // Format version
     pub capsule_index: u32,   // Index in capsule set
     pub capsule_size: u32,    // Target capsule size
-    pub data_size: u32,       // Actual data size (before padding)
+    pub data_size: u32,       // Exact payload size in the body (before padding)
     pub flags: u32,           // Encryption, compression flags
-    pub reserved: [u8; 8],    // Reserved for future use
+    pub reserved: [u8; 8],    // [0]: compression codec id, rest reserved for future use
     pub header_checksum: u32, // CRC32 of header (excluding this field)
     pub data_offset: u32,     // Offset to actual capsule data
 }
@@ -118,17 +230,11 @@ impl CapsuleHeader {
         capsule_index: u32,
         capsule_size: u32,
         data_size: u32,
-        encrypted: bool,
-        compressed: bool,
+        flags: u32,
+        codec: Codec,
     ) -> Self {
-        let mut flags = 0u32;
-        if encrypted {
-            flags |= FLAG_ENCRYPTED;
-        }
-        if compressed {
-            flags |= FLAG_COMPRESSED;
-        }
-
+        let mut reserved = [0u8; 8];
+        reserved[0] = codec.id();
         let mut header = CapsuleHeader {
             magic: CAPSULE_MAGIC,
             version: CAPSULE_VERSION,
@@ -136,7 +242,7 @@ impl CapsuleHeader {
             capsule_size,
             data_size,
             flags,
-            reserved: [0u8; 8],
+            reserved,
             header_checksum: 0, // Will be calculated
             data_offset: CAPSULE_HEADER_SIZE as u32,
         };
@@ -235,7 +341,10 @@ impl CapsuleHeader {
         // Verify checksum
         let expected_checksum = header.calculate_checksum_without_field();
         if header_checksum != expected_checksum {
-            return Err(CapsuleError::ChecksumMismatch);
+            return Err(CapsuleError::HeaderChecksumMismatch {
+                expected: expected_checksum,
+                actual: header_checksum,
+            });
         }
 
         Ok(header)
@@ -263,51 +372,358 @@ impl CapsuleHeader {
     pub fn is_compressed(&self) -> bool {
         (self.flags & FLAG_COMPRESSED) != 0
     }
+
+    pub fn has_set_nonce(&self) -> bool {
+        (self.flags & FLAG_SET_NONCE) != 0
+    }
+
+    pub fn compresses_before_encrypting(&self) -> bool {
+        (self.flags & FLAG_COMPRESS_FIRST) != 0
+    }
+
+    pub fn has_inner_padding(&self) -> bool {
+        (self.flags & FLAG_INNER_PADDING) != 0
+    }
+
+    pub fn has_segmented_encryption(&self) -> bool {
+        (self.flags & FLAG_SEGMENTED) != 0
+    }
+
+    pub fn compression_codec(&self) -> CapsuleResult<Codec> {
+        Codec::from_id(self.reserved[0])
+    }
+
+    // Reject header versions this build cannot decode
+    fn check_version(&self) -> CapsuleResult<()> {
+        match self.version {
+            CAPSULE_VERSION_V1 | CAPSULE_VERSION => Ok(()),
+            _ => Err(CapsuleError::ConsensusViolation(
+                "Unsupported capsule version".to_string(),
+            )),
+        }
+    }
+
+    // Version 1 outer padding can only be located by scanning for the marker
+    pub fn has_marker_padding(&self) -> bool {
+        self.version == CAPSULE_VERSION_V1 && !self.has_inner_padding()
+    }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, thiserror::Error)]
+// Error sources sit behind an `Arc` so errors stay `Clone`; they serialize as
+// their message
+#[derive(Debug, Clone, Serialize, Deserialize, thiserror::Error)]
 pub enum CapsuleError {
     #[error("Invalid format")]
     InvalidFormat,
+    #[error("Invalid format: capsule body is {actual} bytes, expected {expected}")]
+    BodySizeMismatch { expected: u64, actual: u64 },
+    #[error("Invalid capsule set metadata: {0}")]
+    InvalidMetadata(
+        #[source]
+        #[serde(with = "error_message")]
+        Arc<serde_json::Error>,
+    ),
     #[error("Unsupported size")]
     UnsupportedSize,
-    #[error("Checksum mismatch")]
-    ChecksumMismatch,
+    #[error("Header checksum mismatch: expected {expected:08x}, found {actual:08x}")]
+    HeaderChecksumMismatch { expected: u32, actual: u32 },
+    #[error("Checksum mismatch: expected {expected}, found {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("Capsule hash mismatch at index {index}: expected {expected}, found {actual}")]
+    CapsuleHashMismatch {
+        index: u32,
+        expected: String,
+        actual: String,
+    },
     #[error("Consensus violation: {0}")]
     ConsensusViolation(String),
     #[error("Compression failed")]
     CompressionFailed,
-    #[error("Decryption failed")]
-    DecryptionFailed,
+    #[error("Decryption failed for capsule {index}")]
+    DecryptionFailed { index: u32 },
     #[error("Encryption failed")]
     EncryptionFailed,
-    #[error("IO error")]
-    IoError,
+    #[error("Nonce mismatch")]
+    NonceMismatch,
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("IO error: {0}")]
+    Io(
+        #[source]
+        #[serde(with = "error_message")]
+        Arc<std::io::Error>,
+    ),
+    #[error("IO error on {}: {source}", path.display())]
+    FileIo {
+        path: PathBuf,
+        #[source]
+        #[serde(with = "error_message")]
+        source: Arc<std::io::Error>,
+    },
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Byte range out of bounds")]
+    RangeOutOfBounds,
+    #[error("Capsule set metadata not found")]
+    MetadataNotFound,
+    #[error("Capsule set {set_id} not found")]
+    CapsuleSetNotFound { set_id: String },
+    #[error("Ambiguous capsule set: {count} sets match, specify a set id")]
+    AmbiguousCapsuleSet { count: usize },
+    #[error("File conflict: {} already exists with different contents", path.display())]
+    FileConflict { path: PathBuf },
+    #[error("Invalid option: {0}")]
+    InvalidOption(String),
+    #[error("Compression dictionary {hash} not found")]
+    DictionaryNotFound { hash: String },
+    #[error("Archive entry {name} not found")]
+    EntryNotFound { name: String },
+}
+
+impl CapsuleError {
+    // Stable identifier exposed to JS as the error's `code` property
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode(match self {
+            CapsuleError::InvalidFormat => "CAPSULE_INVALID_FORMAT",
+            CapsuleError::BodySizeMismatch { .. } => "CAPSULE_SIZE_MISMATCH",
+            CapsuleError::InvalidMetadata(_) => "CAPSULE_INVALID_METADATA",
+            CapsuleError::UnsupportedSize => "CAPSULE_UNSUPPORTED_SIZE",
+            CapsuleError::HeaderChecksumMismatch { .. } => "CAPSULE_HEADER_CHECKSUM_MISMATCH",
+            CapsuleError::ChecksumMismatch { .. } => "CAPSULE_CHECKSUM_MISMATCH",
+            CapsuleError::CapsuleHashMismatch { .. } => "CAPSULE_HASH_MISMATCH",
+            CapsuleError::ConsensusViolation(_) => "CAPSULE_CONSENSUS_VIOLATION",
+            CapsuleError::CompressionFailed => "CAPSULE_COMPRESSION_FAILED",
+            CapsuleError::DecryptionFailed { .. } => "CAPSULE_DECRYPT_FAILED",
+            CapsuleError::EncryptionFailed => "CAPSULE_ENCRYPT_FAILED",
+            CapsuleError::NonceMismatch => "CAPSULE_NONCE_MISMATCH",
+            CapsuleError::InvalidKey(_) => "CAPSULE_INVALID_KEY",
+            CapsuleError::Io(_) | CapsuleError::FileIo { .. } => "CAPSULE_IO_ERROR",
+            CapsuleError::Cancelled => "CAPSULE_CANCELLED",
+            CapsuleError::RangeOutOfBounds => "CAPSULE_RANGE_OUT_OF_BOUNDS",
+            CapsuleError::MetadataNotFound => "CAPSULE_METADATA_NOT_FOUND",
+            CapsuleError::CapsuleSetNotFound { .. } => "CAPSULE_SET_NOT_FOUND",
+            CapsuleError::AmbiguousCapsuleSet { .. } => "CAPSULE_AMBIGUOUS_SET",
+            CapsuleError::FileConflict { .. } => "CAPSULE_FILE_CONFLICT",
+            CapsuleError::InvalidOption(_) => "CAPSULE_INVALID_OPTION",
+            CapsuleError::DictionaryNotFound { .. } => "CAPSULE_DICTIONARY_NOT_FOUND",
+            CapsuleError::EntryNotFound { .. } => "CAPSULE_ENTRY_NOT_FOUND",
+        })
+    }
+
+    // Attach the file an IO error happened on
+    fn at_path(path: &Path) -> impl FnOnce(std::io::Error) -> CapsuleError + '_ {
+        move |err| match CapsuleError::from(err) {
+            CapsuleError::Io(source) => CapsuleError::FileIo {
+                path: path.to_path_buf(),
+                source,
+            },
+            other => other,
+        }
+    }
 }
 
 impl From<std::io::Error> for CapsuleError {
-    fn from(_: std::io::Error) -> Self {
-        CapsuleError::IoError
+    fn from(err: std::io::Error) -> Self {
+        // Errors raised inside streaming readers travel wrapped in an io::Error
+        if !err.get_ref().is_some_and(|e| e.is::<CapsuleError>()) {
+            return CapsuleError::Io(Arc::new(err));
+        }
+        let kind = err.kind();
+        match err.into_inner().map(|e| e.downcast::<CapsuleError>()) {
+            Some(Ok(inner)) => *inner,
+            Some(Err(other)) => CapsuleError::Io(Arc::new(std::io::Error::new(kind, other))),
+            None => CapsuleError::Io(Arc::new(kind.into())),
+        }
     }
 }
 
 impl From<serde_json::Error> for CapsuleError {
-    fn from(_: serde_json::Error) -> Self {
-        CapsuleError::InvalidFormat
+    fn from(err: serde_json::Error) -> Self {
+        CapsuleError::InvalidMetadata(Arc::new(err))
+    }
+}
+
+mod error_message {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::sync::Arc;
+
+    pub trait FromMessage {
+        fn from_message(message: String) -> Self;
+    }
+
+    impl FromMessage for std::io::Error {
+        fn from_message(message: String) -> Self {
+            std::io::Error::other(message)
+        }
+    }
+
+    impl FromMessage for serde_json::Error {
+        fn from_message(message: String) -> Self {
+            serde::de::Error::custom(message)
+        }
+    }
+
+    pub fn serialize<E: Display, S: Serializer>(
+        err: &Arc<E>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(err)
+    }
+
+    pub fn deserialize<'de, E: FromMessage, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<E>, D::Error> {
+        String::deserialize(deserializer).map(|message| Arc::new(E::from_message(message)))
     }
 }
 
-// NAPI Error conversion
-impl From<CapsuleError> for napi::Error {
-    fn from(err: CapsuleError) -> Self {
-        napi::Error::new(napi::Status::GenericFailure, format!("{}", err))
+// Error code carried as the status of errors thrown to JS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode(&'static str);
+
+impl AsRef<str> for ErrorCode {
+    fn as_ref(&self) -> &str {
+        self.0
     }
 }
 
 // Helper type alias for Results
 pub type CapsuleResult<T> = std::result::Result<T, CapsuleError>;
 
-// Temporary storage for capsule data before writing to disk
+// A file written under a temporary name, removed on drop unless persisted.
+// Temporary names look like `.{first 16 hex digits of the set id}_{label}.{pid}-{n}.tmp`,
+// where `n` is unique within the process, so concurrent writes of the same set
+// never share a temporary file.
+struct PendingFile {
+    path: Option<std::path::PathBuf>,
+}
+
+static PENDING_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl PendingFile {
+    // Create a new, empty temporary file; an existing file is never reused
+    fn create_for_set(dir: &Path, set_id: &str, label: &str) -> CapsuleResult<(Self, File)> {
+        loop {
+            let path = dir.join(format!(
+                ".{}_{}.{}-{}{}",
+                &set_id[..16],
+                label,
+                std::process::id(),
+                PENDING_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+                PENDING_FILE_SUFFIX
+            ));
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((PendingFile { path: Some(path) }, file)),
+                // Left behind by an earlier process that had the same pid
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(CapsuleError::at_path(&path)(err)),
+            }
+        }
+    }
+
+    fn is_pending_file_name(file_name: &str) -> bool {
+        file_name.ends_with(PENDING_FILE_SUFFIX)
+            && file_name.len() > 18
+            && file_name.starts_with('.')
+            && file_name.as_bytes()[1..17]
+                .iter()
+                .all(|b| b.is_ascii_hexdigit())
+            && file_name.as_bytes()[17] == b'_'
+    }
+
+    // Whether a temporary file has gone unwritten for PENDING_FILE_MAX_AGE.
+    // A modification time in the future counts as recent.
+    fn is_abandoned(path: &Path) -> CapsuleResult<bool> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(CapsuleError::at_path(path))?;
+        Ok(modified
+            .elapsed()
+            .is_ok_and(|age| age >= PENDING_FILE_MAX_AGE))
+    }
+
+    fn path(&self) -> &Path {
+        self.path
+            .as_deref()
+            .expect("pending file already persisted")
+    }
+
+    // Move the file to its final name
+    fn persist(mut self, final_path: &Path) -> std::io::Result<()> {
+        let path = self.path.take().expect("pending file already persisted");
+        if let Err(e) = fs::rename(&path, final_path) {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Writer that hashes everything passing through it
+struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            hasher: Sha256::default(),
+        }
+    }
+
+    fn finalize(self) -> sha2::digest::Output<Sha256> {
+        self.hasher.finalize()
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Writer that keeps `remaining` bytes of the stream after skipping the first
+// `skip`, discarding everything else
+struct RangeWriter<'a> {
+    output: &'a mut Vec<u8>,
+    skip: u64,
+    remaining: u64,
+}
+
+impl Write for RangeWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let skipped = std::cmp::min(self.skip, buf.len() as u64) as usize;
+        self.skip -= skipped as u64;
+
+        let kept = std::cmp::min(self.remaining, (buf.len() - skipped) as u64) as usize;
+        self.output.extend_from_slice(&buf[skipped..skipped + kept]);
+        self.remaining -= kept as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// A single encoded capsule, held only until it is written to disk
 struct CapsuleData {
     header: CapsuleHeader,
     data: Vec<u8>,
@@ -316,28 +732,415 @@ struct CapsuleData {
 
 struct StreamingCapsuleProcessor {
     encryption_key: Option<[u8; 32]>,
+    nonce_prefix: Option<[u8; 8]>, // None = legacy "DIG1" nonces
+    key_derivation: KeyDerivation,
+    kdf_salt: Vec<u8>,
+    post_process_padding: bool, // Pad after encryption instead of inside the envelope
+    compression: CompressionSettings,
+    dictionary: Option<Vec<u8>>, // zstd dictionary the set is compressed with
 }
 
 impl StreamingCapsuleProcessor {
-    pub fn new(encryption_key: Option<String>) -> CapsuleResult<Self> {
-        let encryption_key = if let Some(key) = encryption_key {
-            Some(Self::derive_consensus_key(&key)?)
-        } else {
-            None
+    // Build a processor for a new capsule set; the KDF salt and nonce prefix
+    // are both derived from the set id and the encoding parameters
+    pub fn for_new_set(
+        encryption_key: Option<EncryptionKey>,
+        key_derivation: KeyDerivation,
+        set_id: &[u8],
+        post_process_padding: bool,
+        compression: CompressionSettings,
+        dictionary: Option<Vec<u8>>,
+    ) -> CapsuleResult<Self> {
+        if let Some(key) = &encryption_key {
+            key_derivation.check_key(key)?;
+        }
+        let flags = Self::set_flags(post_process_padding, encryption_key.is_some(), true);
+        let context = Self::encoding_context(set_id, flags, &compression, dictionary.as_deref());
+        let kdf_salt = kdf::derive_set_salt(&context).to_vec();
+        let encryption_key = encryption_key
+            .map(|key| key_derivation.derive_key(&key, &kdf_salt))
+            .transpose()?;
+
+        Ok(StreamingCapsuleProcessor {
+            encryption_key,
+            nonce_prefix: Some(Self::derive_nonce_prefix(&context)),
+            key_derivation,
+            kdf_salt,
+            post_process_padding,
+            compression,
+            dictionary,
+        })
+    }
+
+    pub fn with_dictionary(mut self, dictionary: Option<Vec<u8>>) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    // Build a processor able to decode an existing capsule set, honoring the
+    // key derivation and nonce scheme recorded in its encryption info and
+    // loading its compression dictionary from `capsules_dir`
+    pub fn for_capsule_set(
+        capsule_set: &CapsuleSet,
+        capsules_dir: &Path,
+        decryption_key: Option<EncryptionKey>,
+    ) -> CapsuleResult<Self> {
+        let dictionary =
+            dictionary::load_for_set(capsule_set, capsules_dir, decryption_key.clone())?;
+        Ok(Self::for_encryption_info(
+            capsule_set.metadata.encryption_info.as_ref(),
+            decryption_key,
+        )?
+        .with_dictionary(dictionary))
+    }
+
+    pub fn for_encryption_info(
+        encryption_info: Option<&EncryptionInfo>,
+        decryption_key: Option<EncryptionKey>,
+    ) -> CapsuleResult<Self> {
+        let (key_derivation, kdf_salt, nonce_prefix) = match encryption_info {
+            Some(info) => {
+                let key_derivation = KeyDerivation::from_encryption_info(info)?;
+                let kdf_salt = kdf::parse_set_salt(&key_derivation, info)?;
+                (key_derivation, kdf_salt, Self::parse_nonce_prefix(info)?)
+            }
+            None => (KeyDerivation::LegacySha256, Vec::new(), None),
         };
 
-        Ok(StreamingCapsuleProcessor { encryption_key })
+        let encryption_key = decryption_key
+            .map(|key| key_derivation.derive_key(&key, &kdf_salt))
+            .transpose()?;
+
+        Ok(StreamingCapsuleProcessor {
+            encryption_key,
+            nonce_prefix,
+            key_derivation,
+            kdf_salt,
+            post_process_padding: true, // Decoding follows each capsule's header flags
+            compression: CompressionSettings::default(), // and codec id
+            dictionary: None,
+        })
     }
 
-    // NETWORK CONSENSUS CRITICAL: Deterministic key derivation
-    fn derive_consensus_key(key_str: &str) -> CapsuleResult<[u8; 32]> {
+    fn parse_nonce_prefix(info: &EncryptionInfo) -> CapsuleResult<Option<[u8; 8]>> {
+        match info.nonce_scheme.as_deref() {
+            None | Some(NONCE_SCHEME_LEGACY) => Ok(None),
+            Some(NONCE_SCHEME_SET_PREFIX) => {
+                let prefix_hex = info
+                    .nonce_prefix
+                    .as_deref()
+                    .ok_or(CapsuleError::InvalidFormat)?;
+                let prefix = hex::decode(prefix_hex).map_err(|_| CapsuleError::InvalidFormat)?;
+                let prefix: [u8; 8] = prefix
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| CapsuleError::InvalidFormat)?;
+                Ok(Some(prefix))
+            }
+            Some(_) => Err(CapsuleError::ConsensusViolation(
+                "Unsupported nonce scheme".to_string(),
+            )),
+        }
+    }
+
+    // NETWORK CONSENSUS CRITICAL: Digest of the set id (SHA-256 of the
+    // plaintext, so it commits to every chunk) and of every parameter that
+    // changes the encrypted bytes: header flags (pipeline, padding mode, nonce
+    // scheme), consensus version, codec, level and dictionary. Encoding the
+    // same input another way must never reuse a key/nonce pair on different
+    // plaintext, and each chunk is still compressed only once.
+    fn encoding_context(
+        set_id: &[u8],
+        flags: u32,
+        compression: &CompressionSettings,
+        dictionary: Option<&[u8]>,
+    ) -> [u8; 32] {
         let mut hasher = Sha256::default();
-        hasher.update(key_str.as_bytes());
-        hasher.update(b"DIG_CAPSULE_SALT_V1"); // Consensus salt
-        let result = hasher.finalize();
-        let mut key = [0u8; 32];
-        key.copy_from_slice(&result);
-        Ok(key)
+        hasher.update(b"DIG_ENCODING_CONTEXT_V1");
+        hasher.update(set_id);
+        hasher.update(CONSENSUS_VERSION.as_bytes());
+        hasher.update(flags.to_be_bytes());
+        hasher.update([compression.codec.id()]);
+        hasher.update(compression.level.to_be_bytes());
+        match dictionary {
+            Some(dictionary) => {
+                hasher.update([1]);
+                hasher.update(Sha256::digest(dictionary));
+            }
+            None => hasher.update([0]),
+        }
+        hasher.finalize().into()
+    }
+
+    // NETWORK CONSENSUS CRITICAL: Per-set nonce prefix, which also salts the
+    // per-capsule subkeys. Derived from the encoding context, so capsule N of
+    // two different files, or of one file encoded two ways, never shares a
+    // key/nonce pair, while identical encodings still encrypt identically.
+    fn derive_nonce_prefix(context: &[u8]) -> [u8; 8] {
+        let mut hasher = Sha256::default();
+        hasher.update(b"DIG_NONCE_PREFIX_V1");
+        hasher.update(context);
+        let hash = hasher.finalize();
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&hash[..8]);
+        prefix
+    }
+
+    // CONSENSUS CRITICAL: Deterministic nonce for a chunk
+    fn nonce_for_chunk(&self, chunk_index: u32) -> [u8; 12] {
+        let mut nonce_bytes = [0u8; 12];
+        match &self.nonce_prefix {
+            Some(prefix) => {
+                nonce_bytes[..8].copy_from_slice(prefix);
+                nonce_bytes[8..].copy_from_slice(&chunk_index.to_be_bytes());
+            }
+            None => {
+                nonce_bytes[..4].copy_from_slice(&chunk_index.to_be_bytes());
+                nonce_bytes[4..8].copy_from_slice(b"DIG1"); // Version marker
+                nonce_bytes[8..].copy_from_slice(&[0u8; 4]); // Reserved
+            }
+        }
+        nonce_bytes
+    }
+
+    fn encryption_info(&self) -> Option<EncryptionInfo> {
+        self.encryption_key.as_ref()?;
+        let mut info = self.key_derivation.encryption_info(&self.kdf_salt);
+        info.nonce_scheme = Some(
+            match self.nonce_prefix {
+                Some(_) => NONCE_SCHEME_SET_PREFIX,
+                None => NONCE_SCHEME_LEGACY,
+            }
+            .to_string(),
+        );
+        info.nonce_prefix = self.nonce_prefix.map(hex::encode);
+        Some(info)
+    }
+
+    fn header_flags(&self, compressed: bool) -> u32 {
+        let mut flags = Self::set_flags(
+            self.post_process_padding,
+            self.encryption_key.is_some(),
+            self.nonce_prefix.is_some(),
+        );
+        if compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        flags
+    }
+
+    // Header flags shared by every capsule of a set
+    fn set_flags(post_process_padding: bool, encrypted: bool, set_nonce: bool) -> u32 {
+        let mut flags = FLAG_COMPRESS_FIRST;
+        if !post_process_padding {
+            flags |= FLAG_INNER_PADDING;
+        }
+        if encrypted {
+            flags |= FLAG_ENCRYPTED;
+            if set_nonce {
+                flags |= FLAG_SET_NONCE | FLAG_SEGMENTED;
+            }
+        }
+        flags
+    }
+
+    // Turn one plaintext chunk into a finished capsule: compress -> encrypt -> pad,
+    // or compress -> pad -> encrypt when padding goes inside the envelope
+    fn encode_chunk(
+        &self,
+        chunk_data: &[u8],
+        target_chunk_size: usize,
+        chunk_index: u32,
+    ) -> CapsuleResult<CapsuleData> {
+        // Step 1: Stream compress the plaintext into the buffer that becomes
+        // the capsule body. Every later step works on it in place, so about
+        // one capsule is held in memory; the headroom covers the worst-case
+        // expansion of incompressible chunks by any codec.
+        let mut data = Vec::with_capacity(target_chunk_size + target_chunk_size / 128 + KB);
+        self.compress_stream(chunk_data, &mut data)?;
+
+        // Chunks compression does not shrink (already-compressed media,
+        // ciphertext) are stored as they are, with FLAG_COMPRESSED cleared
+        let compressed = data.len() < chunk_data.len();
+        if !compressed {
+            data.clear();
+            data.extend_from_slice(chunk_data);
+        }
+
+        let (optimal_capsule_size, payload_size) = if self.post_process_padding {
+            // Step 2: Find optimal capsule size for the encrypted payload
+            let payload_size = self.sealed_len(data.len());
+            let optimal_capsule_size =
+                Self::find_optimal_capsule_size(payload_size, target_chunk_size);
+            if payload_size >= optimal_capsule_size {
+                return Err(CapsuleError::ConsensusViolation(
+                    "No space available for padding".to_string(),
+                ));
+            }
+            data.reserve_exact(optimal_capsule_size - data.len());
+
+            // Step 3: Encrypt (if enabled)
+            self.seal_in_place(&mut data, chunk_index)?;
+
+            // Step 4: Add deterministic padding to reach optimal capsule size; the
+            // payload length goes in the header, so the padding needs no framing
+            Self::extend_with_padding(&mut data, optimal_capsule_size - payload_size, chunk_index);
+            (optimal_capsule_size, payload_size)
+        } else {
+            // Step 2: Find optimal capsule size once the envelope overhead is known
+            let optimal_capsule_size = Self::find_optimal_capsule_size(
+                self.sealed_len(data.len() + INNER_LENGTH_SIZE),
+                target_chunk_size,
+            );
+            data.reserve_exact(optimal_capsule_size.saturating_sub(data.len()));
+
+            // Step 3: Pad inside the envelope so the padding length is hidden by the AEAD
+            let padded_size = if self.encryption_key.is_some() {
+                segmented::plaintext_len(optimal_capsule_size).ok_or_else(|| {
+                    CapsuleError::ConsensusViolation(
+                        "Capsule size does not hold whole segments".to_string(),
+                    )
+                })?
+            } else {
+                optimal_capsule_size
+            };
+            Self::add_inner_padding(&mut data, padded_size, chunk_index)?;
+
+            // Step 4: Encrypt (if enabled) to exactly the capsule size; the whole
+            // envelope is the payload, its inner length stays encrypted
+            self.seal_in_place(&mut data, chunk_index)?;
+            (optimal_capsule_size, data.len())
+        };
+
+        // Create capsule header
+        let header = CapsuleHeader::new(
+            chunk_index,
+            optimal_capsule_size as u32,
+            payload_size as u32,
+            self.header_flags(compressed),
+            self.compression.codec,
+        );
+
+        // Calculate final hash
+        let mut hasher = Sha256::default();
+        hasher.update(header.to_bytes());
+        hasher.update(&data);
+
+        Ok(CapsuleData {
+            header,
+            data,
+            hash: hex::encode(hasher.finalize()),
+        })
+    }
+
+    // Recover the plaintext of one capsule body, dispatching on the pipeline
+    // recorded in its header
+    fn decode_capsule_body<R: Read + Seek, W: Write>(
+        &self,
+        header: &CapsuleHeader,
+        reader: R,
+        writer: W,
+    ) -> CapsuleResult<u64> {
+        let streamable = !header.has_marker_padding()
+            && header.compresses_before_encrypting()
+            && (!header.is_encrypted() || header.has_segmented_encryption());
+        if !streamable {
+            return self.decode_buffered_capsule_body(header, reader, writer);
+        }
+
+        // Bounded memory: payload -> segment decryptor -> (unpad) -> decompress
+        let payload = Self::payload_reader(header, reader)?;
+        if header.is_encrypted() {
+            let cipher = self.segment_cipher(header.capsule_index)?.ok_or(
+                CapsuleError::DecryptionFailed {
+                    index: header.capsule_index,
+                },
+            )?;
+            let plaintext = segmented::SegmentDecryptor::new(
+                cipher,
+                payload,
+                header.data_size as u64,
+                header.capsule_index,
+            );
+            self.decompress_envelope(header, plaintext, writer)
+        } else {
+            self.decompress_envelope(header, payload, writer)
+        }
+    }
+
+    fn decompress_envelope<R: Read, W: Write>(
+        &self,
+        header: &CapsuleHeader,
+        mut plaintext: R,
+        writer: W,
+    ) -> CapsuleResult<u64> {
+        if !header.has_inner_padding() {
+            return self.decompress_stream(header, plaintext, writer);
+        }
+
+        // Padding lives inside the envelope: [payload length][payload][padding]
+        let mut length_bytes = [0u8; INNER_LENGTH_SIZE];
+        plaintext.read_exact(&mut length_bytes)?;
+        let payload_size = u32::from_le_bytes(length_bytes) as u64;
+        let bytes_written =
+            self.decompress_stream(header, (&mut plaintext).take(payload_size), writer)?;
+
+        // Drain the padding so every segment is authenticated
+        std::io::copy(&mut plaintext, &mut std::io::sink())?;
+        Ok(bytes_written)
+    }
+
+    // Capsules from before segmented encryption are decoded in memory, one
+    // pipeline stage at a time
+    fn decode_buffered_capsule_body<R: Read + Seek, W: Write>(
+        &self,
+        header: &CapsuleHeader,
+        reader: R,
+        writer: W,
+    ) -> CapsuleResult<u64> {
+        // Step 1: Remove padding
+        let mut no_padding_data = Vec::new();
+        if header.has_marker_padding() {
+            self.remove_padding(reader, std::io::Cursor::new(&mut no_padding_data))?;
+        } else {
+            Self::payload_reader(header, reader)?.read_to_end(&mut no_padding_data)?;
+        }
+
+        if header.has_inner_padding() {
+            // Padding lives inside the envelope: decrypt -> unpad -> decompress
+            let mut padded_data = Vec::new();
+            self.decrypt_stream(
+                std::io::Cursor::new(&no_padding_data),
+                std::io::Cursor::new(&mut padded_data),
+                header.capsule_index,
+            )?;
+            let payload = Self::remove_inner_padding(&padded_data)?;
+            return self.decompress_stream(header, payload, writer);
+        }
+
+        if header.compresses_before_encrypting() {
+            // DIG_CAPSULE_V2: decrypt -> decompress
+            let mut decrypted_data = Vec::new();
+            self.decrypt_stream(
+                std::io::Cursor::new(&no_padding_data),
+                std::io::Cursor::new(&mut decrypted_data),
+                header.capsule_index,
+            )?;
+            self.decompress_stream(header, std::io::Cursor::new(&decrypted_data), writer)
+        } else {
+            // DIG_CAPSULE_V1: decompress -> decrypt
+            let mut decompressed_data = Vec::new();
+            self.decompress_stream(
+                header,
+                std::io::Cursor::new(&no_padding_data),
+                std::io::Cursor::new(&mut decompressed_data),
+            )?;
+            self.decrypt_stream(
+                std::io::Cursor::new(&decompressed_data),
+                writer,
+                header.capsule_index,
+            )
+        }
     }
 
     // NETWORK CONSENSUS CRITICAL: Deterministic chunk size determination
@@ -366,7 +1169,7 @@ impl StreamingCapsuleProcessor {
     // This should only upgrade from the target size if absolutely necessary for padding
     fn find_optimal_capsule_size(processed_data_size: usize, target_capsule_size: usize) -> usize {
         let min_padding = (processed_data_size as f64 * MIN_PADDING_PERCENT) as usize;
-        let required_space = processed_data_size + PADDING_MARKER.len() + 4 + min_padding; // data + marker + footer + min padding
+        let required_space = processed_data_size + PADDING_MARKER.len() + 4 + min_padding; // marker + footer space kept so bucket choices match version 1
 
         // First, try the target size from consensus algorithm
         if required_space <= target_capsule_size {
@@ -397,57 +1200,56 @@ impl StreamingCapsuleProcessor {
         CAPSULE_SIZES[CAPSULE_SIZES.len() - 1]
     }
 
-    // Stream-based encryption
-    fn encrypt_stream<R: Read, W: Write>(
-        &self,
-        mut reader: R,
-        mut writer: W,
-        chunk_index: u32,
-    ) -> CapsuleResult<u64> {
-        if let Some(key) = &self.encryption_key {
-            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-
-            // CONSENSUS CRITICAL: Deterministic nonce using chunk index
-            let mut nonce_bytes = [0u8; 12];
-            let index_bytes = chunk_index.to_be_bytes();
-            nonce_bytes[..4].copy_from_slice(&index_bytes);
-            nonce_bytes[4..8].copy_from_slice(b"DIG1"); // Version marker
-            nonce_bytes[8..].copy_from_slice(&[0u8; 4]); // Reserved
-
-            let nonce = Nonce::from_slice(&nonce_bytes);
-
-            // Write nonce first
-            writer.write_all(&nonce_bytes)?;
-            let mut total_written = 12u64;
-
-            // Read all data for encryption (AES-GCM requires full data)
-            let mut data = Vec::new();
-            reader.read_to_end(&mut data)?;
-
-            let ciphertext = cipher
-                .encrypt(nonce, data.as_slice())
-                .map_err(|_| CapsuleError::EncryptionFailed)?;
+    // Per-capsule cipher for segmented encryption, None when not encrypting
+    fn segment_cipher(&self, chunk_index: u32) -> CapsuleResult<Option<Aes256Gcm>> {
+        let Some(key) = &self.encryption_key else {
+            return Ok(None);
+        };
+        // Segment subkeys are bound to the per-set nonce prefix
+        let nonce_prefix = self
+            .nonce_prefix
+            .as_ref()
+            .ok_or(CapsuleError::InvalidFormat)?;
+        Ok(Some(segmented::capsule_cipher(
+            key,
+            nonce_prefix,
+            chunk_index,
+        )))
+    }
 
-            writer.write_all(&ciphertext)?;
-            total_written += ciphertext.len() as u64;
-            Ok(total_written)
+    // Size of a payload once encrypted (if enabled)
+    fn sealed_len(&self, payload_len: usize) -> usize {
+        if self.encryption_key.is_some() {
+            segmented::encrypted_len(payload_len)
         } else {
-            // No encryption, just copy
-            std::io::copy(&mut reader, &mut writer).map_err(|_| CapsuleError::IoError)
+            payload_len
+        }
+    }
+
+    // Encrypt (if enabled) the capsule payload in `data` with segmented AES-256-GCM
+    fn seal_in_place(&self, data: &mut Vec<u8>, chunk_index: u32) -> CapsuleResult<()> {
+        if let Some(cipher) = self.segment_cipher(chunk_index)? {
+            segmented::encrypt_in_place(&cipher, data)?;
         }
+        Ok(())
     }
 
+    // Whole-payload decryption for capsules written before segmented encryption
     fn decrypt_stream<R: Read, W: Write>(
         &self,
         mut reader: R,
         mut writer: W,
+        chunk_index: u32,
     ) -> CapsuleResult<u64> {
         if let Some(key) = &self.encryption_key {
             let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
 
-            // Read nonce
+            // Read nonce and make sure it belongs to this set and position
             let mut nonce_bytes = [0u8; 12];
             reader.read_exact(&mut nonce_bytes)?;
+            if nonce_bytes != self.nonce_for_chunk(chunk_index) {
+                return Err(CapsuleError::NonceMismatch);
+            }
 
             // Read rest of encrypted data
             let mut ciphertext = Vec::new();
@@ -455,78 +1257,118 @@ impl StreamingCapsuleProcessor {
 
             let plaintext = cipher
                 .decrypt(Nonce::from_slice(&nonce_bytes), ciphertext.as_slice())
-                .map_err(|_| CapsuleError::DecryptionFailed)?;
+                .map_err(|_| CapsuleError::DecryptionFailed { index: chunk_index })?;
 
             writer.write_all(&plaintext)?;
             Ok(plaintext.len() as u64)
         } else {
             // No decryption, just copy
-            std::io::copy(&mut reader, &mut writer).map_err(|_| CapsuleError::IoError)
+            Ok(std::io::copy(&mut reader, &mut writer)?)
         }
     }
 
-    // Stream-based compression with fixed level for consensus
+    // Stream-based compression with the set's codec at a consensus-pinned level
     fn compress_stream<R: Read, W: Write>(&self, reader: R, writer: W) -> CapsuleResult<u64> {
-        let mut encoder = GzEncoder::new(writer, Compression::new(6)); // Fixed level for consensus
-        let bytes_written = std::io::copy(&mut BufReader::new(reader), &mut encoder)?;
-        encoder.finish()?;
-        Ok(bytes_written)
+        self.compression
+            .compress(self.dictionary.as_deref(), reader, writer)
     }
 
-    fn decompress_stream<R: Read, W: Write>(&self, reader: R, writer: W) -> CapsuleResult<u64> {
-        let mut decoder = GzDecoder::new(reader);
-        let bytes_written = std::io::copy(&mut decoder, &mut BufWriter::new(writer))?;
-        Ok(bytes_written)
-    }
-
-    // NETWORK CONSENSUS CRITICAL: Deterministic padding
-    fn add_deterministic_padding(
+    // Decompress with the codec recorded in the capsule header; capsules
+    // stored uncompressed are copied through
+    fn decompress_stream<R: Read, W: Write>(
         &self,
-        data: &mut Vec<u8>,
-        current_size: usize,
-        target_size: usize,
-        chunk_index: u32,
-    ) -> CapsuleResult<()> {
-        if current_size >= target_size {
-            return Ok(());
-        }
-
-        let available_space = target_size - current_size - PADDING_MARKER.len() - 4; // 4 bytes for size footer
-
-        // Check if we have any space for padding
-        if available_space == 0 {
-            return Err(CapsuleError::ConsensusViolation(
-                "No space available for padding".to_string(),
-            ));
+        header: &CapsuleHeader,
+        mut reader: R,
+        mut writer: W,
+    ) -> CapsuleResult<u64> {
+        if !header.is_compressed() {
+            return Ok(std::io::copy(&mut reader, &mut writer)?);
         }
+        compression::decompress(
+            header.compression_codec()?,
+            self.dictionary.as_deref(),
+            reader,
+            writer,
+        )
+    }
 
-        let padding_size = available_space;
-
-        // CONSENSUS CRITICAL: Deterministic padding using chunk index as seed
+    // CONSENSUS CRITICAL: Deterministic padding bytes using chunk index as seed
+    fn extend_with_padding(data: &mut Vec<u8>, padding_size: usize, chunk_index: u32) {
         let seed = chunk_index.to_be_bytes();
         let mut hasher = Sha256::default();
         hasher.update(seed);
         hasher.update(b"DIG_PADDING_SEED_V1");
         let hash = hasher.finalize();
 
-        // Add padding marker
-        data.extend_from_slice(&PADDING_MARKER);
-
-        // Add deterministic padding
         let mut remaining_padding = padding_size;
         while remaining_padding > 0 {
             let chunk_size = std::cmp::min(remaining_padding, 32);
             data.extend_from_slice(&hash[..chunk_size]);
             remaining_padding -= chunk_size;
         }
+    }
 
-        // Add original size footer
-        let original_size = current_size as u32;
-        data.extend_from_slice(&original_size.to_le_bytes());
+    // NETWORK CONSENSUS CRITICAL: Padding placed inside the encryption envelope
+    // Layout before encryption: [payload length (u32 LE)][payload][padding],
+    // framed around the payload already in `data`
+    fn add_inner_padding(
+        data: &mut Vec<u8>,
+        padded_size: usize,
+        chunk_index: u32,
+    ) -> CapsuleResult<()> {
+        let payload_size = data.len();
+        let framed_size = INNER_LENGTH_SIZE + payload_size;
+        if framed_size >= padded_size {
+            return Err(CapsuleError::ConsensusViolation(
+                "No space available for padding".to_string(),
+            ));
+        }
 
+        data.resize(framed_size, 0);
+        data.copy_within(..payload_size, INNER_LENGTH_SIZE);
+        data[..INNER_LENGTH_SIZE].copy_from_slice(&(payload_size as u32).to_le_bytes());
+        Self::extend_with_padding(data, padded_size - framed_size, chunk_index);
         Ok(())
     }
 
+    fn remove_inner_padding(data: &[u8]) -> CapsuleResult<&[u8]> {
+        let length_bytes = data
+            .get(..INNER_LENGTH_SIZE)
+            .ok_or(CapsuleError::InvalidFormat)?;
+        let payload_size = u32::from_le_bytes(
+            length_bytes
+                .try_into()
+                .map_err(|_| CapsuleError::InvalidFormat)?,
+        ) as usize;
+
+        data.get(INNER_LENGTH_SIZE..INNER_LENGTH_SIZE + payload_size)
+            .ok_or(CapsuleError::InvalidFormat)
+    }
+
+    // Limit the body to its `data_size` payload bytes, rejecting bodies whose
+    // length disagrees with the capsule size recorded in the header
+    fn payload_reader<R: Read + Seek>(
+        header: &CapsuleHeader,
+        mut reader: R,
+    ) -> CapsuleResult<std::io::Take<R>> {
+        if header.data_size > header.capsule_size {
+            return Err(CapsuleError::InvalidFormat);
+        }
+
+        let body_start = reader.stream_position()?;
+        let body_end = reader.seek(SeekFrom::End(0))?;
+        if body_end - body_start != header.capsule_size as u64 {
+            return Err(CapsuleError::BodySizeMismatch {
+                expected: header.capsule_size as u64,
+                actual: body_end - body_start,
+            });
+        }
+        reader.seek(SeekFrom::Start(body_start))?;
+
+        Ok(reader.take(header.data_size as u64))
+    }
+
+    // Legacy (version 1) unpadding: scan backwards for the padding marker
     fn remove_padding<R: Read + Seek, W: Write>(
         &self,
         mut reader: R,
@@ -565,262 +1407,198 @@ impl StreamingCapsuleProcessor {
     }
 }
 
-#[napi]
-pub fn create_data_capsule(
-    buffer_data: Buffer,
-    output_directory: String,
-    _post_process_padding: bool, // Ignored - always pad after encrypt+compress
-    encryption_key: Option<String>,
-) -> Result<CapsuleSet> {
-    use std::io::Write;
+// Internal helper function
+fn create_data_capsule_internal(
+    buffer_data: &[u8],
+    output_directory: &Path,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+    progress: &ProgressTracker,
+) -> CapsuleResult<CapsuleSet> {
     use tempfile::NamedTempFile;
 
     // Create a temporary file from the buffer
     let mut temp_file = NamedTempFile::new()?;
-    temp_file.write_all(&buffer_data)?;
-    let temp_path = temp_file.path().to_string_lossy().to_string();
+    temp_file.write_all(buffer_data)?;
 
     // Use the internal file-based implementation
     create_data_capsule_from_file_internal(
-        temp_path,
+        temp_file.path(),
         output_directory,
-        _post_process_padding,
+        post_process_padding,
         encryption_key,
+        options,
+        progress,
     )
 }
 
 // Internal helper function
 fn create_data_capsule_from_file_internal(
-    input_file_path: String,
-    output_directory: String,
-    _post_process_padding: bool, // Ignored - always pad after encrypt+compress
-    encryption_key: Option<String>,
-) -> Result<CapsuleSet> {
-    let processor = StreamingCapsuleProcessor::new(encryption_key.clone())
-        .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-    // Get file size for determining optimal capsule sizes
-    let input_size = fs::metadata(&input_file_path)?.len();
-
-    // Create output directory
-    fs::create_dir_all(&output_directory)?;
-
-    // Handle empty files
-    if input_size == 0 {
-        // Create a single 256KB capsule for empty files
-        let target_chunk_size = CAPSULE_SIZES[0];
-        let mut final_data = vec![0u8; 0]; // Empty data
-
-        let processor = StreamingCapsuleProcessor::new(encryption_key.clone())
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Encrypt empty data
-        let mut encrypted_data = Vec::new();
-        processor
-            .encrypt_stream(
-                std::io::Cursor::new(&[]),
-                std::io::Cursor::new(&mut encrypted_data),
-                0,
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Compress empty encrypted data
-        let mut compressed_data = Vec::new();
-        processor
-            .compress_stream(
-                std::io::Cursor::new(&encrypted_data),
-                std::io::Cursor::new(&mut compressed_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+    input_path: &Path,
+    output_directory: &Path,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+    progress: &ProgressTracker,
+) -> CapsuleResult<CapsuleSet> {
+    create_capsule_set_from_file(
+        input_path,
+        output_directory,
+        post_process_padding,
+        encryption_key,
+        options,
+        None,
+        progress,
+    )
+}
 
-        // Add padding to reach target size
-        final_data.extend_from_slice(&compressed_data);
-        processor
-            .add_deterministic_padding(&mut final_data, compressed_data.len(), target_chunk_size, 0)
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+// Capsule a file into a set of the given kind (None for data sets)
+fn create_capsule_set_from_file(
+    input_path: &Path,
+    output_directory: &Path,
+    post_process_padding: bool,
+    encryption_key: Option<EncryptionKey>,
+    options: Option<CreateCapsuleOptions>,
+    kind: Option<&str>,
+    progress: &ProgressTracker,
+) -> CapsuleResult<CapsuleSet> {
+    let options = options.unwrap_or_default();
+    let naming_scheme = NamingScheme::from_options(&options)?;
+    let conflict_policy = ConflictPolicy::from_options(&options)?;
+    let compression = options
+        .compression
+        .as_ref()
+        .map(CompressionSettings::from_options)
+        .transpose()?
+        .unwrap_or_default();
+    let key_derivation = match (&options.key_derivation, &encryption_key) {
+        (Some(kdf_options), _) => KeyDerivation::from_options(kdf_options)?,
+        (None, Some(key)) => key.default_derivation(),
+        (None, None) => KeyDerivation::default(),
+    };
 
-        // Create header and write capsule
-        let header = CapsuleHeader::new(
-            0,
-            target_chunk_size as u32,
-            final_data.len() as u32,
-            processor.encryption_key.is_some(),
-            true,
-        );
+    // Dictionaries are stored in the directory of the sets compressed with them
+    let (dictionary_id, dictionary) = match options
+        .compression
+        .as_ref()
+        .and_then(|compression| compression.dictionary.as_deref())
+    {
+        Some(dictionary_id) => {
+            let dictionary_set = dictionary::find(output_directory, dictionary_id)?;
+            let dictionary =
+                dictionary::decode(&dictionary_set, output_directory, encryption_key.clone())?;
+            (Some(dictionary_set.id), Some(dictionary))
+        }
+        None => (None, None),
+    };
 
-        let mut hasher = Sha256::default();
-        hasher.update(&header.to_bytes());
-        hasher.update(&final_data);
-        let capsule_hash = hasher.finalize();
-
-        let id = hex::encode(Sha256::default().finalize()); // Empty file checksum
-        let capsule_file_name = format!("{}_{:03}.capsule", &id[..16], 0);
-        let capsule_path = Path::new(&output_directory).join(capsule_file_name);
-        let mut capsule_file = BufWriter::new(File::create(capsule_path)?);
-        capsule_file.write_all(&header.to_bytes())?;
-        capsule_file.write_all(&final_data)?;
-        capsule_file.flush()?;
-
-        let capsule = Capsule {
-            index: 0,
-            size: target_chunk_size as u32,
-            hash: hex::encode(capsule_hash),
-            encrypted: processor.encryption_key.is_some(),
-            compressed: true,
-        };
+    // Get file size for determining optimal capsule sizes
+    let input_size = fs::metadata(input_path)
+        .map_err(CapsuleError::at_path(input_path))?
+        .len();
 
-        let capsule_set = CapsuleSet {
-            id: hex::encode(Sha256::default().finalize()),
-            capsules: vec![capsule],
-            metadata: CapsuleMetadata {
-                original_size: 0.0,
-                capsule_count: 1,
-                capsule_sizes: vec![target_chunk_size as u32],
-                checksum: hex::encode(Sha256::default().finalize()),
-                chunking_algorithm: "DIG_DETERMINISTIC_V1".to_string(),
-                consensus_version: "DIG_CAPSULE_V1".to_string(),
-                encryption_info: encryption_key.map(|_| EncryptionInfo {
-                    algorithm: "AES-256-GCM".to_string(),
-                    key_derivation: "PBKDF2-HMAC-SHA256".to_string(),
-                    iterations: 100000,
-                    salt: "DIG_CAPSULE_SALT_V1".to_string(),
-                }),
-                compression_info: Some(CompressionInfo {
-                    algorithm: "gzip".to_string(),
-                    level: 6,
-                    original_size: 0.0,
-                }),
-            },
-        };
+    // Create output directory
+    fs::create_dir_all(output_directory).map_err(CapsuleError::at_path(output_directory))?;
 
-        // Save metadata
-        let metadata_file_name = format!("{}_metadata.json", &capsule_set.id[..16]);
-        let metadata_path = Path::new(&output_directory).join(metadata_file_name);
-        let metadata_json = serde_json::to_string_pretty(&capsule_set)
-            .map_err(|e| Error::new(Status::GenericFailure, format!("JSON error: {}", e)))?;
-        fs::write(metadata_path, metadata_json)?;
+    // Use memory-mapped file for efficient large file access
+    let input_file = File::open(input_path).map_err(CapsuleError::at_path(input_path))?;
+    let mmap = if input_size > 0 {
+        Some(unsafe { Mmap::map(&input_file)? })
+    } else {
+        None
+    };
 
-        return Ok(capsule_set);
+    // NETWORK CONSENSUS CRITICAL: Determine chunk sizes using consensus algorithm
+    let mut chunk_sizes = StreamingCapsuleProcessor::determine_chunk_sizes(input_size);
+    if chunk_sizes.is_empty() {
+        // Empty files still produce a single 256KB capsule
+        chunk_sizes.push(CAPSULE_SIZES[0]);
+    }
+    progress.start(input_size, chunk_sizes.len() as u32);
+    let input_data: &[u8] = mmap.as_deref().unwrap_or(&[]);
+
+    // The set id must be known up front: the KDF salt and nonce prefix are derived from it
+    let mut id_hasher = Sha256::default();
+    for buffer in input_data.chunks(HASH_BUFFER_SIZE) {
+        progress.check_cancelled()?;
+        id_hasher.update(buffer);
+        progress.hashed(buffer.len() as u64);
     }
+    let expected_checksum = id_hasher.finalize();
+    let set_id_hex = hex::encode(expected_checksum);
+
+    let processor = StreamingCapsuleProcessor::for_new_set(
+        encryption_key,
+        key_derivation,
+        &expected_checksum,
+        post_process_padding,
+        compression,
+        dictionary,
+    )?;
 
-    // NETWORK CONSENSUS CRITICAL: Determine chunk sizes using consensus algorithm
-    let chunk_sizes = StreamingCapsuleProcessor::determine_chunk_sizes(input_size);
     let chunk_sizes_for_metadata = chunk_sizes.clone();
     let mut chunk_index = 0u32;
     let mut total_checksum = Sha256::default();
     let mut capsules = Vec::with_capacity(chunk_sizes.len()); // Pre-allocate
-    let mut capsule_data_list: Vec<CapsuleData> = Vec::with_capacity(chunk_sizes.len()); // Store all capsule data
-
-    // Use memory-mapped file for efficient large file access
-    let input_file = File::open(&input_file_path)?;
-    let mmap = unsafe { Mmap::map(&input_file)? };
+    let mut capsule_files = Vec::with_capacity(chunk_sizes.len()); // Written capsules awaiting their final name
     let mut bytes_processed = 0u64;
 
     // Process each chunk according to consensus algorithm
     for target_chunk_size in chunk_sizes {
+        progress.check_cancelled()?;
         let actual_read_size =
             std::cmp::min(target_chunk_size as u64, input_size - bytes_processed) as usize;
 
-        if actual_read_size == 0 {
-            break; // All data processed
-        }
-
-        // Stream processing: chunk -> encrypt -> compress -> pad with automatic size optimization
-
-        // Step 1: Get chunk from memory map and update checksum
+        // Get chunk from memory map and update checksum
         let start_offset = bytes_processed as usize;
         let end_offset = start_offset + actual_read_size;
-        let chunk_data = &mmap[start_offset..end_offset];
+        let chunk_data = &input_data[start_offset..end_offset];
         total_checksum.update(chunk_data);
         bytes_processed += actual_read_size as u64;
 
-        // Step 2: Stream encrypt (if enabled)
-        let mut encrypted_data = Vec::with_capacity(actual_read_size + 16);
-        processor
-            .encrypt_stream(
-                std::io::Cursor::new(chunk_data),
-                std::io::Cursor::new(&mut encrypted_data),
-                chunk_index,
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Step 3: Stream compress
-        let mut compressed_data = Vec::with_capacity(encrypted_data.len() / 2);
-        processor
-            .compress_stream(
-                std::io::Cursor::new(&encrypted_data),
-                std::io::Cursor::new(&mut compressed_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+        // Stream processing: chunk -> compress -> encrypt -> pad
+        let capsule_data = processor.encode_chunk(chunk_data, target_chunk_size, chunk_index)?;
+
+        // Write the capsule straight away under a temporary name so only one
+        // capsule is held in memory; temp files are removed if we bail out early
+        let (capsule_file, file) = PendingFile::create_for_set(
+            output_directory,
+            &set_id_hex,
+            &format!("{:03}", chunk_index),
+        )?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&capsule_data.header.to_bytes())?;
+        writer.write_all(&capsule_data.data)?;
+        sync_file(writer).map_err(CapsuleError::at_path(capsule_file.path()))?;
+        capsule_files.push(capsule_file);
 
-        // Step 4: Find optimal capsule size for this compressed data
-        let optimal_capsule_size = StreamingCapsuleProcessor::find_optimal_capsule_size(
-            compressed_data.len(),
-            target_chunk_size,
-        );
-
-        // Step 5: Add deterministic padding to reach optimal capsule size
-        let mut final_data = Vec::with_capacity(optimal_capsule_size);
-        final_data.extend_from_slice(&compressed_data);
-        processor
-            .add_deterministic_padding(
-                &mut final_data,
-                compressed_data.len(),
-                optimal_capsule_size,
-                chunk_index,
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Create capsule header
-        let header = CapsuleHeader::new(
-            chunk_index,
-            optimal_capsule_size as u32,
-            final_data.len() as u32,
-            processor.encryption_key.is_some(),
-            true, // Always compressed
-        );
-
-        // Calculate final hash
-        let mut hasher = Sha256::default();
-        hasher.update(&header.to_bytes());
-        hasher.update(&final_data);
-        let capsule_hash = hasher.finalize();
-
-        // Store capsule data temporarily (we'll write files after calculating final ID)
-        let capsule_data = CapsuleData {
-            header,
-            data: final_data,
-            hash: hex::encode(capsule_hash),
-        };
-
-        // Store capsule data for later writing
-        capsule_data_list.push(capsule_data);
+        // Create capsule metadata
+        capsules.push(Capsule {
+            index: chunk_index,
+            size: capsule_data.header.capsule_size,
+            hash: capsule_data.hash,
+            encrypted: capsule_data.header.is_encrypted(),
+            compressed: capsule_data.header.is_compressed(),
+            padding_post_process: Some(!capsule_data.header.has_inner_padding()),
+        });
         chunk_index += 1;
+        progress.capsule_completed(actual_read_size as u64);
     }
 
-    // Calculate final checksum and write all capsule files with consistent naming
+    // Calculate final checksum and give the capsule files their consistent names
     let final_checksum = total_checksum.finalize();
+    if final_checksum != expected_checksum {
+        // The input changed while it was being capsuled
+        return Err(CapsuleError::ChecksumMismatch {
+            expected: set_id_hex,
+            actual: hex::encode(final_checksum),
+        });
+    }
     let final_id = hex::encode(final_checksum);
 
-    // Write all capsule files using the final ID
-    for (index, capsule_data) in capsule_data_list.iter().enumerate() {
-        let capsule_file_name = format!("{}_{:03}.capsule", &final_id[..16], index);
-        let capsule_path = Path::new(&output_directory).join(capsule_file_name);
-        let mut capsule_file = BufWriter::new(File::create(capsule_path)?);
-        capsule_file.write_all(&capsule_data.header.to_bytes())?;
-        capsule_file.write_all(&capsule_data.data)?;
-        capsule_file.flush()?;
-
-        // Create capsule metadata
-        let capsule = Capsule {
-            index: index as u32,
-            size: capsule_data.header.capsule_size,
-            hash: capsule_data.hash.clone(),
-            encrypted: processor.encryption_key.is_some(),
-            compressed: true,
-        };
-        capsules.push(capsule);
-    }
+    let merkle_root = hex::encode(merkle::root(&merkle_leaves(&capsules)?)?);
+    let compressed_capsules = capsules.iter().filter(|capsule| capsule.compressed).count();
 
     // Create final capsule set
     let capsule_set = CapsuleSet {
@@ -835,379 +1613,699 @@ fn create_data_capsule_from_file_internal(
                 .collect(),
             checksum: final_id.clone(),
             chunking_algorithm: "DIG_DETERMINISTIC_V1".to_string(),
-            consensus_version: "DIG_CAPSULE_V1".to_string(),
-            encryption_info: if encryption_key.is_some() {
-                Some(EncryptionInfo {
-                    algorithm: "AES-256-GCM".to_string(),
-                    key_derivation: "PBKDF2-HMAC-SHA256".to_string(),
-                    iterations: 100000,
-                    salt: "DIG_CAPSULE_SALT_V1".to_string(),
-                })
-            } else {
-                None
-            },
+            consensus_version: CONSENSUS_VERSION.to_string(),
+            encryption_info: processor.encryption_info(),
             compression_info: Some(CompressionInfo {
-                algorithm: "gzip".to_string(),
-                level: 6,
+                algorithm: compression.codec.name().to_string(),
+                level: compression.level,
                 original_size: input_size as f64,
+                dictionary: dictionary_id,
+                compressed_capsules: Some(compressed_capsules as u32),
             }),
+            merkle_root: Some(merkle_root),
+            naming_scheme: Some(naming_scheme.as_str().to_string()),
+            kind: kind.map(str::to_string),
         },
     };
 
-    // Save metadata
-    let metadata_file_name = format!("{}_metadata.json", &capsule_set.id[..16]);
-    let metadata_path = Path::new(&output_directory).join(metadata_file_name);
-    let metadata_json = serde_json::to_string_pretty(&capsule_set)
-        .map_err(|e| Error::new(Status::GenericFailure, format!("JSON error: {}", e)))?;
-    fs::write(metadata_path, metadata_json)?;
+    let metadata_path = output_directory.join(naming_scheme.metadata_file_name(&final_id)?);
+    let capsule_paths = capsule_set
+        .capsules
+        .iter()
+        .map(|capsule| naming_scheme.capsule_path(output_directory, &final_id, capsule))
+        .collect::<CapsuleResult<Vec<_>>>()?;
+
+    // Files from an earlier run may already use these names
+    if conflict_policy != ConflictPolicy::Overwrite {
+        if let Some(conflict_path) =
+            find_conflicting_file(&capsule_set, &capsule_paths, &metadata_path)?
+        {
+            if conflict_policy == ConflictPolicy::Fail {
+                return Err(CapsuleError::FileConflict {
+                    path: conflict_path,
+                });
+            }
+            // Otherwise the files are damaged remains of the same set, which
+            // the new set replaces
+            if let Some(existing_set) =
+                intact_capsule_set(&metadata_path, &final_id, output_directory)?
+            {
+                return Ok(existing_set);
+            }
+        }
+    }
+
+    let capsules_dir = match naming_scheme {
+        NamingScheme::SetDirectory => {
+            let set_dir = output_directory.join(&final_id);
+            fs::create_dir_all(&set_dir).map_err(CapsuleError::at_path(&set_dir))?;
+            set_dir
+        }
+        _ => output_directory.to_path_buf(),
+    };
+    for (capsule_file, capsule_path) in capsule_files.into_iter().zip(&capsule_paths) {
+        capsule_file
+            .persist(capsule_path)
+            .map_err(CapsuleError::at_path(capsule_path))?;
+    }
+    sync_dir(&capsules_dir).map_err(CapsuleError::at_path(&capsules_dir))?;
+
+    // The metadata goes last and marks the set as complete: a crash before this
+    // point leaves only capsule files without metadata, which recovery removes
+    let (metadata_file, file) =
+        PendingFile::create_for_set(output_directory, &final_id, "metadata")?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &capsule_set)?;
+    sync_file(writer).map_err(CapsuleError::at_path(metadata_file.path()))?;
+    metadata_file
+        .persist(&metadata_path)
+        .map_err(CapsuleError::at_path(&metadata_path))?;
+    sync_dir(output_directory).map_err(CapsuleError::at_path(output_directory))?;
 
     Ok(capsule_set)
 }
 
-#[napi]
-pub fn extract_data_capsule(
-    capsule_set_path: String,
-    decryption_key: Option<String>,
-) -> Result<Buffer> {
-    use tempfile::NamedTempFile;
+// Flush a file and wait until its contents are on disk
+fn sync_file(writer: BufWriter<File>) -> std::io::Result<()> {
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()
+}
 
-    // Create temporary output file
-    let temp_output = NamedTempFile::new()?;
-    let temp_output_path = temp_output.path().to_string_lossy().to_string();
+// Make renames into `dir` durable. Directories cannot be opened for syncing on
+// Windows, where renames are journaled by NTFS anyway.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    File::open(dir)?.sync_all()
+}
 
-    // Extract to temporary file using internal function
-    extract_data_capsule_to_file_internal(
-        capsule_set_path,
-        temp_output_path.clone(),
-        decryption_key,
-    )?;
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// The first existing file among a new set's capsule and metadata paths whose
+// contents differ from what would be written there
+fn find_conflicting_file(
+    capsule_set: &CapsuleSet,
+    capsule_paths: &[PathBuf],
+    metadata_path: &Path,
+) -> CapsuleResult<Option<PathBuf>> {
+    for (capsule, capsule_path) in capsule_set.capsules.iter().zip(capsule_paths) {
+        if !capsule_path.exists() {
+            continue;
+        }
+        let mut existing_file =
+            File::open(capsule_path).map_err(CapsuleError::at_path(capsule_path))?;
+        match verify_capsule_hash(&mut existing_file, capsule) {
+            Ok(()) => {}
+            Err(CapsuleError::CapsuleHashMismatch { .. }) => return Ok(Some(capsule_path.clone())),
+            Err(err) => return Err(err),
+        }
+    }
 
-    // Read the extracted data and return as Buffer
-    let extracted_data = std::fs::read(temp_output_path)?;
-    Ok(Buffer::from(extracted_data))
+    // Metadata of the same set from an older version may differ in formatting
+    // or optional fields, so only the id and capsule hashes are compared
+    if metadata_path.exists() {
+        let same_set = read_capsule_set(metadata_path).is_ok_and(|existing_set| {
+            existing_set.id == capsule_set.id
+                && existing_set.capsules.len() == capsule_set.capsules.len()
+                && existing_set
+                    .capsules
+                    .iter()
+                    .zip(&capsule_set.capsules)
+                    .all(|(existing, new)| existing.hash.eq_ignore_ascii_case(&new.hash))
+        });
+        if !same_set {
+            return Ok(Some(metadata_path.to_path_buf()));
+        }
+    }
+
+    Ok(None)
+}
+
+// The set stored under `metadata_path` if it has id `set_id` and every one of
+// its capsule files is present with the recorded hash
+fn intact_capsule_set(
+    metadata_path: &Path,
+    set_id: &str,
+    dir: &Path,
+) -> CapsuleResult<Option<CapsuleSet>> {
+    let Ok(existing_set) = read_capsule_set(metadata_path) else {
+        return Ok(None);
+    };
+    if existing_set.id != set_id {
+        return Ok(None);
+    }
+    for capsule in &existing_set.capsules {
+        let Ok(mut existing_file) = File::open(capsule_path(&existing_set, dir, capsule)?) else {
+            return Ok(None);
+        };
+        match verify_capsule_hash(&mut existing_file, capsule) {
+            Ok(()) => {}
+            Err(CapsuleError::CapsuleHashMismatch { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Some(existing_set))
 }
 
 // Internal helper function
-fn extract_data_capsule_to_file_internal(
-    capsule_set_path: String,
-    output_file_path: String,
-    decryption_key: Option<String>,
-) -> Result<()> {
-    // Load capsule set metadata
-    let (capsule_set, _) = load_capsule_set_from_path(&capsule_set_path)
-        .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-    let processor = StreamingCapsuleProcessor::new(decryption_key)
-        .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-    // Open output file for writing
-    let output_file = File::create(output_file_path)?;
+fn reconstruct_file_from_capsules_internal(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    output_file_path: &Path,
+    decryption_key: Option<EncryptionKey>,
+    progress: &ProgressTracker,
+) -> CapsuleResult<()> {
+    let output_file =
+        File::create(output_file_path).map_err(CapsuleError::at_path(output_file_path))?;
     let mut writer = BufWriter::new(output_file);
+    decode_capsule_set(
+        capsule_set,
+        capsules_dir,
+        decryption_key,
+        &mut writer,
+        progress,
+    )?;
+    writer
+        .flush()
+        .map_err(CapsuleError::at_path(output_file_path))?;
+    Ok(())
+}
 
-    // Process each capsule in order
-    let input_dir = if Path::new(&capsule_set_path).is_dir() {
-        capsule_set_path
-    } else {
-        Path::new(&capsule_set_path)
-            .parent()
-            .unwrap()
-            .to_string_lossy()
-            .to_string()
-    };
-
-    // Calculate expected checksum
-    let mut total_checksum = Sha256::default();
+// Decode every capsule of a set in order into `writer`, then check what was
+// written against the set checksum
+fn decode_capsule_set<W: Write>(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    decryption_key: Option<EncryptionKey>,
+    writer: W,
+    progress: &ProgressTracker,
+) -> CapsuleResult<()> {
+    progress.start(
+        capsule_set.metadata.original_size as u64,
+        capsule_set.metadata.capsule_count,
+    );
+
+    let processor =
+        StreamingCapsuleProcessor::for_capsule_set(capsule_set, capsules_dir, decryption_key)?;
+
+    // Hash everything written so the result can be checked without re-reading it
+    let mut writer = ChecksumWriter::new(writer);
 
+    // Process each capsule in order
     for i in 0..capsule_set.metadata.capsule_count {
-        let capsule_file_name = format!("{}_{:03}.capsule", &capsule_set.id[..16], i);
-        let capsule_path = Path::new(&input_dir).join(capsule_file_name);
-
-        let mut capsule_file = File::open(capsule_path)?;
+        progress.check_cancelled()?;
+        let capsule = capsule_set
+            .capsules
+            .iter()
+            .find(|capsule| capsule.index == i)
+            .ok_or(CapsuleError::InvalidFormat)?;
+        let capsule_path = capsule_path(capsule_set, capsules_dir, capsule)?;
+        let mut capsule_file =
+            File::open(&capsule_path).map_err(CapsuleError::at_path(&capsule_path))?;
+
+        // Check the capsule against its recorded hash before decoding anything
+        verify_capsule_hash(&mut capsule_file, capsule)?;
 
         // Read and validate header
-        let mut header_bytes = vec![0u8; CAPSULE_HEADER_SIZE];
-        capsule_file.read_exact(&mut header_bytes)?;
-        let _header = CapsuleHeader::from_bytes(&header_bytes)
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Stream processing: remove_padding -> decompress -> decrypt
-
-        // Step 1: Remove padding
-        let mut no_padding_data = Vec::new();
-        processor
-            .remove_padding(
-                &mut capsule_file,
-                std::io::Cursor::new(&mut no_padding_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Step 2: Decompress
-        let mut decompressed_data = Vec::new();
-        processor
-            .decompress_stream(
-                std::io::Cursor::new(&no_padding_data),
-                std::io::Cursor::new(&mut decompressed_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-        // Step 3: Decrypt
-        let mut decrypted_data = Vec::new();
-        processor
-            .decrypt_stream(
-                std::io::Cursor::new(&decompressed_data),
-                std::io::Cursor::new(&mut decrypted_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+        let header = read_capsule_header(&mut capsule_file, i)?;
 
-        // Write to output file and update checksum
-        total_checksum.update(&decrypted_data);
-        writer.write_all(&decrypted_data)?;
+        // Stream processing: decode straight into the output, updating the checksum
+        let bytes_decoded =
+            processor.decode_capsule_body(&header, &mut capsule_file, &mut writer)?;
+        progress.capsule_completed(bytes_decoded);
     }
 
     writer.flush()?;
 
     // Verify checksum
-    let computed_checksum = hex::encode(total_checksum.finalize());
+    let computed_checksum = hex::encode(writer.finalize());
     if computed_checksum != capsule_set.metadata.checksum {
-        return Err(Error::new(
-            Status::GenericFailure,
-            "Checksum mismatch".to_string(),
-        ));
+        return Err(CapsuleError::ChecksumMismatch {
+            expected: capsule_set.metadata.checksum.clone(),
+            actual: computed_checksum,
+        });
     }
 
     Ok(())
 }
 
-// Utility function for working directly with files (more efficient for large files)
-#[napi]
-pub fn create_data_capsule_from_file(
-    input_file_path: String,
-    output_directory: String,
-    _post_process_padding: bool, // Ignored - always pad after encrypt+compress
-    encryption_key: Option<String>,
-) -> Result<CapsuleSet> {
-    create_data_capsule_from_file_internal(
-        input_file_path,
-        output_directory,
-        _post_process_padding,
-        encryption_key,
-    )
-}
+// Read `length` bytes of the original data starting at `offset`, decoding only
+// the capsules that overlap the range. Ranges running past the end are cut short.
+fn extract_range_internal(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    offset: u64,
+    length: u64,
+    decryption_key: Option<EncryptionKey>,
+) -> CapsuleResult<Vec<u8>> {
+    let original_size = capsule_set.metadata.original_size as u64;
+    if offset > original_size {
+        return Err(CapsuleError::RangeOutOfBounds);
+    }
+    let start = offset;
+    let end = std::cmp::min(start.saturating_add(length), original_size);
 
-// Utility function for extracting directly to file (more efficient for large files)
-#[napi]
-pub fn extract_data_capsule_to_file(
-    capsule_set_path: String,
-    output_file_path: String,
-    decryption_key: Option<String>,
-) -> Result<()> {
-    extract_data_capsule_to_file_internal(capsule_set_path, output_file_path, decryption_key)
-}
+    // NETWORK CONSENSUS CRITICAL: capsule boundaries follow the consensus chunking
+    let mut chunk_sizes = StreamingCapsuleProcessor::determine_chunk_sizes(original_size);
+    if chunk_sizes.is_empty() {
+        chunk_sizes.push(CAPSULE_SIZES[0]);
+    }
+    if chunk_sizes.len() != capsule_set.metadata.capsule_count as usize {
+        return Err(CapsuleError::InvalidFormat);
+    }
 
-#[napi]
-pub fn load_capsule_set(path: String) -> Result<CapsuleSet> {
-    let (capsule_set, _) = load_capsule_set_from_path(&path)
-        .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-    Ok(capsule_set)
-}
+    let processor =
+        StreamingCapsuleProcessor::for_capsule_set(capsule_set, capsules_dir, decryption_key)?;
 
-#[napi]
-pub fn reconstruct_file_from_capsules(
-    capsule_set: CapsuleSet,
-    capsules_dir: String,
-    output_file_path: String,
-    decryption_key: Option<String>,
-) -> Result<()> {
-    let processor = StreamingCapsuleProcessor::new(decryption_key)
-        .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
-
-    // Open output file for writing
-    let output_file = File::create(output_file_path)?;
-    let mut writer = BufWriter::new(output_file);
+    let mut output = Vec::with_capacity((end - start) as usize);
+    let mut capsule_start = 0u64;
+    for (index, &chunk_size) in chunk_sizes.iter().enumerate() {
+        let capsule_end = std::cmp::min(capsule_start + chunk_size as u64, original_size);
+        if capsule_start >= end {
+            break;
+        }
+        if capsule_end <= start {
+            capsule_start = capsule_end;
+            continue;
+        }
 
-    // Create checksum verifier
-    let mut verifier_hasher = Sha256::default();
+        let index = index as u32;
+        let capsule = capsule_set
+            .capsules
+            .iter()
+            .find(|capsule| capsule.index == index)
+            .ok_or(CapsuleError::InvalidFormat)?;
+        let capsule_path = capsule_path(capsule_set, capsules_dir, capsule)?;
+        let mut capsule_file =
+            File::open(&capsule_path).map_err(CapsuleError::at_path(&capsule_path))?;
+
+        // Check the capsule against its recorded hash before decoding anything
+        verify_capsule_hash(&mut capsule_file, capsule)?;
+
+        let header = read_capsule_header(&mut capsule_file, index)?;
+
+        let mut writer = RangeWriter {
+            output: &mut output,
+            skip: start.saturating_sub(capsule_start),
+            remaining: end - std::cmp::max(start, capsule_start),
+        };
+        let bytes_decoded =
+            processor.decode_capsule_body(&header, &mut capsule_file, &mut writer)?;
 
-    // Sort capsules by index
-    let mut sorted_capsules: Vec<_> = capsule_set.capsules.iter().collect();
-    sorted_capsules.sort_by_key(|capsule| capsule.index);
+        // Without the whole set there is no set checksum to check, so at least
+        // make sure the capsule decoded to exactly its share of the data
+        if bytes_decoded != capsule_end - capsule_start {
+            return Err(CapsuleError::InvalidFormat);
+        }
 
-    // Process each capsule in order
-    for capsule in sorted_capsules {
-        // Load capsule file
-        let capsule_file_name = format!("{}_{:03}.capsule", &capsule_set.id[..16], capsule.index);
-        let capsule_path = Path::new(&capsules_dir).join(capsule_file_name);
-        let mut capsule_file = File::open(capsule_path)?;
+        capsule_start = capsule_end;
+    }
 
-        // Skip header
-        capsule_file.seek(SeekFrom::Start(CAPSULE_HEADER_SIZE as u64))?;
+    Ok(output)
+}
 
-        // Stream processing: remove_padding -> decompress -> decrypt
+// Decode a single capsule file without the rest of its set. The key derivation
+// and nonce scheme come from the set metadata: the given capsule set, else the
+// metadata file next to the capsule.
+fn decode_capsule_internal<W: Write>(
+    file_path: &Path,
+    decryption_key: Option<EncryptionKey>,
+    capsule_set: Option<&CapsuleSet>,
+    writer: W,
+) -> CapsuleResult<u64> {
+    let header = validate_capsule_file_internal(file_path)?;
+
+    let sibling_set;
+    let capsule_set = match capsule_set {
+        Some(capsule_set) => Some(capsule_set),
+        None => {
+            sibling_set = find_sibling_capsule_set(file_path)?;
+            sibling_set.as_ref()
+        }
+    };
 
-        // Step 1: Remove padding
-        let mut no_padding_data = Vec::new();
-        processor
-            .remove_padding(
-                &mut capsule_file,
-                std::io::Cursor::new(&mut no_padding_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+    let mut capsule_file = File::open(file_path).map_err(CapsuleError::at_path(file_path))?;
+    let processor = match capsule_set {
+        Some(capsule_set) => {
+            // Check the capsule against its recorded hash before decoding anything
+            let capsule = capsule_set
+                .capsules
+                .iter()
+                .find(|capsule| capsule.index == header.capsule_index)
+                .ok_or(CapsuleError::InvalidFormat)?;
+            verify_capsule_hash(&mut capsule_file, capsule)?;
 
-        // Step 2: Decompress
-        let mut decompressed_data = Vec::new();
-        processor
-            .decompress_stream(
-                std::io::Cursor::new(&no_padding_data),
-                std::io::Cursor::new(&mut decompressed_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+            let capsules_dir = set_directory_of_capsule(capsule_set, file_path)?;
+            StreamingCapsuleProcessor::for_capsule_set(capsule_set, &capsules_dir, decryption_key)
+        }
+        // Unencrypted capsules decode without any set metadata
+        None if !header.is_encrypted() => {
+            StreamingCapsuleProcessor::for_encryption_info(None, decryption_key)
+        }
+        None => Err(CapsuleError::MetadataNotFound),
+    }?;
 
-        // Step 3: Decrypt
-        let mut decrypted_data = Vec::new();
-        processor
-            .decrypt_stream(
-                std::io::Cursor::new(&decompressed_data),
-                std::io::Cursor::new(&mut decrypted_data),
-            )
-            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+    let header = read_capsule_header(&mut capsule_file, header.capsule_index)?;
+    processor.decode_capsule_body(&header, &mut capsule_file, writer)
+}
 
-        // Write to output file and update checksum
-        writer.write_all(&decrypted_data)?;
-        verifier_hasher.update(&decrypted_data);
+// Where a capsule of a set stored in `capsules_dir` lives, following the
+// naming scheme recorded in its metadata
+fn capsule_path(
+    capsule_set: &CapsuleSet,
+    capsules_dir: &Path,
+    capsule: &Capsule,
+) -> CapsuleResult<PathBuf> {
+    NamingScheme::from_metadata(capsule_set.metadata.naming_scheme.as_deref())?.capsule_path(
+        capsules_dir,
+        &capsule_set.id,
+        capsule,
+    )
+}
+
+// The directory holding the metadata of the set a capsule file belongs to:
+// the capsule's own directory, or its parent for SET_DIRECTORY sets
+fn set_directory_of_capsule(
+    capsule_set: &CapsuleSet,
+    capsule_path: &Path,
+) -> CapsuleResult<PathBuf> {
+    let capsules_dir = match capsule_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(
+        match NamingScheme::from_metadata(capsule_set.metadata.naming_scheme.as_deref())? {
+            NamingScheme::SetDirectory => capsules_dir
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            _ => capsules_dir.to_path_buf(),
+        },
+    )
+}
+
+// Find the metadata of the set a capsule file belongs to from the file's name
+fn find_sibling_capsule_set(capsule_path: &Path) -> CapsuleResult<Option<CapsuleSet>> {
+    let file_stem = capsule_path
+        .file_stem()
+        .ok_or(CapsuleError::InvalidFormat)?
+        .to_string_lossy();
+
+    // `{set id or prefix}_{index}.capsule` next to `{set id or prefix}_metadata.json`
+    if let Some((set_prefix, _)) = file_stem.rsplit_once('_') {
+        let metadata_path =
+            capsule_path.with_file_name(format!("{}{}", set_prefix, METADATA_FILE_SUFFIX));
+        return read_capsule_set_if_present(&metadata_path);
     }
 
-    writer.flush()?;
+    let capsules_dir = match capsule_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
 
-    // Verify checksum
-    let calculated_checksum = hex::encode(verifier_hasher.finalize());
-    if calculated_checksum != capsule_set.metadata.checksum {
-        return Err(Error::new(
-            Status::GenericFailure,
-            "Checksum mismatch".to_string(),
+    // `{set id}/{index}.capsule`, with `{set id}_metadata.json` next to the set directory
+    if file_stem.bytes().all(|b| b.is_ascii_digit()) {
+        let (Some(set_id), Some(parent)) = (capsules_dir.file_name(), capsules_dir.parent()) else {
+            return Ok(None);
+        };
+        let metadata_path = parent.join(format!(
+            "{}{}",
+            set_id.to_string_lossy(),
+            METADATA_FILE_SUFFIX
         ));
+        return read_capsule_set_if_present(&metadata_path);
     }
 
-    Ok(())
+    // `{capsule hash}.capsule`: any set in the directory recording that hash
+    for metadata_path in metadata_files(capsules_dir)? {
+        let capsule_set = read_capsule_set(&metadata_path)?;
+        if capsule_set
+            .capsules
+            .iter()
+            .any(|capsule| capsule.hash.eq_ignore_ascii_case(&file_stem))
+        {
+            return Ok(Some(capsule_set));
+        }
+    }
+    Ok(None)
 }
 
-#[napi]
-pub fn is_valid_capsule_file(file_path: String) -> Result<bool> {
-    match validate_capsule_file_internal(&file_path) {
-        Ok(_) => Ok(true),
-        Err(_) => Ok(false),
+fn read_capsule_set_if_present(metadata_path: &Path) -> CapsuleResult<Option<CapsuleSet>> {
+    if !metadata_path.is_file() {
+        return Ok(None);
     }
+    Ok(Some(read_capsule_set(metadata_path)?))
 }
 
-#[napi]
-pub fn get_capsule_file_info(file_path: String) -> Result<Option<CapsuleFileInfo>> {
-    match validate_capsule_file_internal(&file_path) {
-        Ok(header) => Ok(Some(CapsuleFileInfo {
-            magic: hex::encode(header.magic),
-            version: header.version,
-            capsule_index: header.capsule_index,
-            capsule_size: header.capsule_size,
-            data_size: header.data_size,
-            is_encrypted: header.flags & FLAG_ENCRYPTED != 0,
-            is_compressed: header.flags & FLAG_COMPRESSED != 0,
-            checksum: format!("{:08x}", header.header_checksum),
-        })),
-        Err(_) => Ok(None),
+// Read the header at the start of a capsule file and check it sits at the expected position
+fn read_capsule_header<R: Read>(
+    reader: &mut R,
+    expected_index: u32,
+) -> CapsuleResult<CapsuleHeader> {
+    let mut header_bytes = vec![0u8; CAPSULE_HEADER_SIZE];
+    reader.read_exact(&mut header_bytes)?;
+    let header = CapsuleHeader::from_bytes(&header_bytes)?;
+    header.check_version()?;
+
+    if header.capsule_index != expected_index {
+        return Err(CapsuleError::InvalidFormat);
     }
+
+    Ok(header)
 }
 
-#[napi]
-pub fn calculate_storage_overhead(original_size: i64, capsule_count: u32) -> f64 {
-    if original_size == 0 {
-        return 0.0;
+// Hash the whole capsule file (header + padded body) and compare it with the
+// hash recorded in the capsule set, leaving the reader at the start of the file
+fn verify_capsule_hash<R: Read + Seek>(reader: &mut R, capsule: &Capsule) -> CapsuleResult<()> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::default();
+    std::io::copy(reader, &mut hasher)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let actual = hex::encode(hasher.finalize());
+    if actual != capsule.hash.to_lowercase() {
+        return Err(CapsuleError::CapsuleHashMismatch {
+            index: capsule.index,
+            expected: capsule.hash.clone(),
+            actual,
+        });
     }
 
-    let min_padding_per_capsule = (CAPSULE_SIZES[0] as f64 * MIN_PADDING_PERCENT) as i64;
-    let total_min_padding = min_padding_per_capsule * capsule_count as i64;
-
-    (total_min_padding as f64 / original_size as f64) * 100.0
+    Ok(())
 }
 
-fn load_capsule_set_from_path(path: &str) -> CapsuleResult<(CapsuleSet, String)> {
-    let path_obj = Path::new(path);
+// Capsule hashes ordered by index; the indices must cover 0..n exactly
+fn merkle_leaves(capsules: &[Capsule]) -> CapsuleResult<Vec<merkle::Hash>> {
+    let mut ordered: Vec<&Capsule> = capsules.iter().collect();
+    ordered.sort_by_key(|capsule| capsule.index);
 
-    let (metadata_path, input_dir) = if path_obj.is_dir() {
-        // Directory path - find metadata file
-        let entries = fs::read_dir(path_obj)?;
+    ordered
+        .iter()
+        .enumerate()
+        .map(|(position, capsule)| {
+            if capsule.index as usize != position {
+                return Err(CapsuleError::InvalidFormat);
+            }
+            merkle::parse_hash(&capsule.hash)
+        })
+        .collect()
+}
 
-        let mut metadata_file = None;
-        for entry in entries {
-            let entry = entry?;
-            let file_name = entry.file_name();
-            let file_name_str = file_name.to_string_lossy();
+// `path` is a capsule directory or a metadata file. A directory holding several
+// sets needs `set_id` (the full id or a prefix of it) to pick one.
+fn load_capsule_set_from_path(
+    path: &Path,
+    set_id: Option<&str>,
+) -> CapsuleResult<(CapsuleSet, PathBuf)> {
+    if path.is_dir() {
+        let capsule_set = find_capsule_set(path, set_id)?;
+        return Ok((capsule_set, path.to_path_buf()));
+    }
 
-            if file_name_str.ends_with("_metadata.json") {
-                metadata_file = Some(entry.path());
-                break;
-            }
+    // Single file path - assume it's a metadata file
+    let input_dir = path.parent().ok_or(CapsuleError::InvalidFormat)?;
+    let capsule_set = read_capsule_set(path)?;
+    if let Some(set_id) = set_id {
+        if !capsule_set.id.starts_with(&set_id.to_lowercase()) {
+            return Err(CapsuleError::CapsuleSetNotFound {
+                set_id: set_id.to_string(),
+            });
         }
+    }
+    Ok((capsule_set, input_dir.to_path_buf()))
+}
 
-        let metadata_path = metadata_file.ok_or(CapsuleError::InvalidFormat)?;
+fn find_capsule_set(dir: &Path, set_id: Option<&str>) -> CapsuleResult<CapsuleSet> {
+    let metadata_paths = metadata_files(dir)?;
 
-        (metadata_path, path.to_string())
-    } else {
-        // Single file path - assume it's a metadata file
-        (
-            path_obj.to_path_buf(),
-            path_obj
-                .parent()
-                .ok_or(CapsuleError::InvalidFormat)?
-                .to_string_lossy()
-                .to_string(),
-        )
+    let Some(set_id) = set_id else {
+        // Compression dictionaries stored next to a set are never picked for it
+        let mut capsule_sets = Vec::new();
+        for metadata_path in &metadata_paths {
+            let capsule_set = read_capsule_set(metadata_path)?;
+            if !capsule_set.is_dictionary() {
+                capsule_sets.push(capsule_set);
+            }
+        }
+        return match capsule_sets.len() {
+            0 => Err(CapsuleError::MetadataNotFound),
+            1 => Ok(capsule_sets.remove(0)),
+            count => Err(CapsuleError::AmbiguousCapsuleSet { count }),
+        };
     };
 
-    // Load and parse metadata
-    let metadata_content = fs::read_to_string(metadata_path)?;
-
-    let capsule_set: CapsuleSet = serde_json::from_str(&metadata_content)?;
+    let set_id = set_id.to_lowercase();
+    let mut matches = Vec::new();
+    for metadata_path in metadata_paths {
+        // Metadata file names carry the set id or its first 16 hex digits, so
+        // only files that can match are parsed
+        let file_prefix = metadata_file_prefix(&metadata_path);
+        if !file_prefix.starts_with(&set_id) && !set_id.starts_with(&file_prefix) {
+            continue;
+        }
 
-    Ok((capsule_set, input_dir))
-}
+        let capsule_set = read_capsule_set(&metadata_path)?;
+        if capsule_set.id.starts_with(&set_id) {
+            matches.push(capsule_set);
+        }
+    }
 
-#[napi]
-pub fn get_capsule_sizes() -> Vec<u32> {
-    CAPSULE_SIZES.iter().map(|&size| size as u32).collect()
+    match matches.len() {
+        0 => Err(CapsuleError::CapsuleSetNotFound { set_id }),
+        1 => Ok(matches.remove(0)),
+        count => Err(CapsuleError::AmbiguousCapsuleSet { count }),
+    }
 }
 
-#[napi]
-pub fn get_consensus_version() -> String {
-    "DIG_CAPSULE_V1".to_string()
+// Every capsule set whose metadata is stored in `dir`, ordered by id
+fn list_capsule_sets_internal(dir: &Path) -> CapsuleResult<Vec<CapsuleSet>> {
+    let mut capsule_sets = metadata_files(dir)?
+        .iter()
+        .map(|metadata_path| read_capsule_set(metadata_path))
+        .collect::<CapsuleResult<Vec<_>>>()?;
+    capsule_sets.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(capsule_sets)
 }
 
-#[napi]
-pub fn validate_consensus_parameters(capsule_set: CapsuleSet) -> napi::Result<bool> {
-    // Validate consensus-critical parameters
-    if capsule_set.metadata.consensus_version != "DIG_CAPSULE_V1" {
-        return Err(
-            CapsuleError::ConsensusViolation("Invalid consensus version".to_string()).into(),
-        );
+// Remove what interrupted writes leave behind in a capsule directory: temporary
+// files older than PENDING_FILE_MAX_AGE, and files with a valid capsule header
+// and a capsule file name that no metadata file refers to. Must not run while
+// sets are being written to the directory: a set whose capsules are in place
+// but whose metadata is not yet would lose them. With `dry_run`, only reports
+// what would be removed.
+fn recover_capsule_directory_internal(dir: &Path, dry_run: bool) -> CapsuleResult<RecoveryReport> {
+    // Unreadable metadata stops recovery, so the capsules of a set are never
+    // mistaken for orphans
+    let mut referenced = HashSet::new();
+    for metadata_path in metadata_files(dir)? {
+        let capsule_set = read_capsule_set(&metadata_path)?;
+        for capsule in &capsule_set.capsules {
+            referenced.insert(capsule_path(&capsule_set, dir, capsule)?);
+        }
     }
 
-    if capsule_set.metadata.chunking_algorithm != "DIG_DETERMINISTIC_V1" {
-        return Err(
-            CapsuleError::ConsensusViolation("Invalid chunking algorithm".to_string()).into(),
-        );
+    let mut removed_files = Vec::new();
+    let mut remove_file = |path: PathBuf| -> CapsuleResult<()> {
+        if !dry_run {
+            fs::remove_file(&path).map_err(CapsuleError::at_path(&path))?;
+        }
+        removed_files.push(path.to_string_lossy().into_owned());
+        Ok(())
+    };
+    // Files named like capsules are only removed if they are capsules
+    let is_orphan_capsule =
+        |path: &Path| !referenced.contains(path) && validate_capsule_file_internal(path).is_ok();
+
+    for entry in fs::read_dir(dir).map_err(CapsuleError::at_path(dir))? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+
+        if entry.file_type()?.is_dir() {
+            if !naming::is_set_directory_name(&file_name) {
+                continue;
+            }
+            for set_entry in fs::read_dir(&path).map_err(CapsuleError::at_path(&path))? {
+                let set_entry = set_entry?;
+                let capsule_path = set_entry.path();
+                if set_entry.file_type()?.is_file()
+                    && naming::is_set_directory_capsule_name(
+                        &set_entry.file_name().to_string_lossy(),
+                    )
+                    && is_orphan_capsule(&capsule_path)
+                {
+                    remove_file(capsule_path)?;
+                }
+            }
+            // Only succeeds once the set directory is empty
+            if !dry_run {
+                let _ = fs::remove_dir(&path);
+            }
+            continue;
+        }
+
+        let is_leftover = if PendingFile::is_pending_file_name(&file_name) {
+            PendingFile::is_abandoned(&path)?
+        } else {
+            naming::is_capsule_file_name(&file_name) && is_orphan_capsule(&path)
+        };
+        if is_leftover {
+            remove_file(path)?;
+        }
     }
 
-    // Validate capsule sizes are from allowed set
-    for capsule in &capsule_set.capsules {
-        if !CAPSULE_SIZES.contains(&(capsule.size as usize)) {
-            return Err(
-                CapsuleError::ConsensusViolation("Invalid capsule size".to_string()).into(),
-            );
+    removed_files.sort();
+    Ok(RecoveryReport { removed_files })
+}
+
+// The `*_metadata.json` files in a directory, sorted by name
+fn metadata_files(dir: &Path) -> CapsuleResult<Vec<PathBuf>> {
+    let mut metadata_paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(CapsuleError::at_path(dir))? {
+        let entry = entry?;
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .ends_with(METADATA_FILE_SUFFIX)
+        {
+            metadata_paths.push(entry.path());
         }
     }
+    metadata_paths.sort();
+    Ok(metadata_paths)
+}
+
+fn metadata_file_prefix(metadata_path: &Path) -> String {
+    let file_name = metadata_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    file_name
+        .strip_suffix(METADATA_FILE_SUFFIX)
+        .unwrap_or(&file_name)
+        .to_lowercase()
+}
 
-    Ok(true)
+fn read_capsule_set(metadata_path: &Path) -> CapsuleResult<CapsuleSet> {
+    let metadata_content =
+        fs::read_to_string(metadata_path).map_err(CapsuleError::at_path(metadata_path))?;
+    Ok(serde_json::from_str(&metadata_content)?)
 }
 
+// Files removed by a capsule directory recovery, or that a dry run would remove
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[napi(object)]
+#[cfg_attr(feature = "napi", napi(object))]
+pub struct RecoveryReport {
+    pub removed_files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "napi", napi(object))]
 pub struct CapsuleFileInfo {
     pub magic: String,
     pub version: u32,
@@ -1219,15 +2317,14 @@ pub struct CapsuleFileInfo {
     pub checksum: String,
 }
 
-fn validate_capsule_file_internal(file_path: &str) -> CapsuleResult<CapsuleHeader> {
+fn validate_capsule_file_internal(path: &Path) -> CapsuleResult<CapsuleHeader> {
     // Check if file exists
-    let path = Path::new(file_path);
     if !path.exists() {
         return Err(CapsuleError::InvalidFormat);
     }
 
     // Read just the header portion
-    let mut file = File::open(path)?;
+    let mut file = File::open(path).map_err(CapsuleError::at_path(path))?;
     let mut header_bytes = vec![0u8; CAPSULE_HEADER_SIZE];
 
     match file.read_exact(&mut header_bytes) {
@@ -1236,10 +2333,11 @@ fn validate_capsule_file_internal(file_path: &str) -> CapsuleResult<CapsuleHeade
             let header = CapsuleHeader::from_bytes(&header_bytes)?;
 
             // Additional validation
-            if header.version != CAPSULE_VERSION {
-                return Err(CapsuleError::ConsensusViolation(
-                    "Unsupported capsule version".to_string(),
-                ));
+            header.check_version()?;
+
+            // The payload must fit inside the capsule
+            if header.data_size > header.capsule_size {
+                return Err(CapsuleError::InvalidFormat);
             }
 
             // Validate capsule size is from allowed set
@@ -0,0 +1,572 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use data_capsules::{
+    list_capsule_sets, recover_capsule_directory, ArchiveInput, CapsuleError, CapsuleFileInfo,
+    CapsuleReader, CapsuleResult, CapsuleWriter, CompressionOptions, ConflictPolicy,
+    DirectoryFilter, EncryptionKey, KeyDerivationOptions, NamingScheme,
+};
+
+// Keys are never taken from argv, where they would end up in shell history
+// and process listings
+const KEY_ENV: &str = "DATA_CAPSULES_KEY";
+
+#[derive(Parser)]
+#[command(
+    name = "data-capsules",
+    version,
+    about = "Create, inspect and extract DIG data capsules"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Capsule a file into a directory, encrypting it when a key is supplied
+    Create {
+        input: PathBuf,
+        output_dir: PathBuf,
+        /// Put the padding inside the encryption envelope
+        #[arg(long)]
+        inner_padding: bool,
+        /// Passphrase key derivation: PBKDF2-HMAC-SHA256, ARGON2ID, SCRYPT or HKDF-SHA256
+        #[arg(long, value_name = "ALGORITHM")]
+        kdf: Option<String>,
+        /// Compression codec: gzip (default), zstd or lz4
+        #[arg(long, value_name = "ALGORITHM")]
+        compression: Option<String>,
+        /// Compression level; zstd accepts 1, 3 (default), 9 or 19
+        #[arg(long, value_name = "LEVEL", requires = "compression")]
+        compression_level: Option<u32>,
+        /// Compress with this dictionary set stored in the output directory (zstd)
+        #[arg(long, value_name = "ID")]
+        dictionary: Option<String>,
+        /// Capsule file names: SET_ID (default), CAPSULE_HASH or SET_DIRECTORY
+        #[arg(long, value_name = "SCHEME")]
+        naming: Option<String>,
+        /// When files of the set already exist with different contents: SKIP (default), FAIL or OVERWRITE
+        #[arg(long, value_name = "POLICY")]
+        on_conflict: Option<String>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Reassemble the original data of a capsule set
+    Extract {
+        #[command(flatten)]
+        capsule_set: SetArgs,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Print the header of capsule files
+    Info {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Check a capsule set's parameters and capsule hashes; with a key the data
+    /// is also decoded and checked against the set checksum
+    Verify {
+        #[command(flatten)]
+        capsule_set: SetArgs,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Pack several small files into one capsule set
+    Archive {
+        output_dir: PathBuf,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Put the padding inside the encryption envelope
+        #[arg(long)]
+        inner_padding: bool,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Capsule a directory tree, with permissions, modification times and symlinks
+    ArchiveDir {
+        input_dir: PathBuf,
+        output_dir: PathBuf,
+        /// Put the padding inside the encryption envelope
+        #[arg(long)]
+        inner_padding: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Restore the directory tree stored in an archive set into an empty directory
+    ExtractDir {
+        #[command(flatten)]
+        capsule_set: SetArgs,
+        output_dir: PathBuf,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// List the files stored in an archive set
+    Entries {
+        #[command(flatten)]
+        capsule_set: SetArgs,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Extract one file of an archive set
+    ExtractEntry {
+        #[command(flatten)]
+        capsule_set: SetArgs,
+        name: String,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Train a zstd dictionary from sample files and store it in a directory
+    TrainDictionary {
+        output_dir: PathBuf,
+        #[arg(required = true)]
+        samples: Vec<PathBuf>,
+        /// Largest dictionary to train, in bytes
+        #[arg(long, value_name = "BYTES")]
+        max_size: Option<usize>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// List the capsule sets stored in a directory
+    List { directory: PathBuf },
+    /// Remove temporary files and capsules without metadata left by interrupted writes
+    Recover {
+        directory: PathBuf,
+        /// List the files that would be removed without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Extract a byte range of the original data
+    Range {
+        #[command(flatten)]
+        capsule_set: SetArgs,
+        offset: u64,
+        length: u64,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+}
+
+#[derive(Args)]
+struct SetArgs {
+    /// Capsule directory or metadata file
+    capsule_set: PathBuf,
+    /// Id, or unique id prefix, of the set to read when the directory holds several
+    #[arg(long = "set", value_name = "ID")]
+    set_id: Option<String>,
+}
+
+#[derive(Args)]
+struct FilterArgs {
+    /// Only paths matching this glob, e.g. 'assets/**' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip paths matching this glob, e.g. '**/*.log' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+}
+
+impl FilterArgs {
+    fn filter(self) -> DirectoryFilter {
+        DirectoryFilter {
+            include: (!self.include.is_empty()).then_some(self.include),
+            exclude: (!self.exclude.is_empty()).then_some(self.exclude),
+        }
+    }
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    /// Read the key from this file instead of $DATA_CAPSULES_KEY
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+    /// Use the key as raw bytes (hex in $DATA_CAPSULES_KEY) instead of a passphrase
+    #[arg(long)]
+    raw_key: bool,
+}
+
+impl KeyArgs {
+    fn load(&self) -> CapsuleResult<Option<EncryptionKey>> {
+        if let Some(path) = &self.key_file {
+            let contents = fs::read(path).map_err(|source| CapsuleError::FileIo {
+                path: path.clone(),
+                source: source.into(),
+            })?;
+            if self.raw_key {
+                return Ok(Some(EncryptionKey::Raw(contents)));
+            }
+            let passphrase = String::from_utf8(contents)
+                .map_err(|_| CapsuleError::InvalidKey("Key file is not valid UTF-8".to_string()))?;
+            return Ok(Some(passphrase.trim_end_matches(['\r', '\n']).into()));
+        }
+
+        match std::env::var(KEY_ENV) {
+            Ok(key) if key.is_empty() => Ok(None),
+            Ok(key) if self.raw_key => hex::decode(key.trim())
+                .map(|bytes| Some(EncryptionKey::Raw(bytes)))
+                .map_err(|_| CapsuleError::InvalidKey(format!("{} is not valid hex", KEY_ENV))),
+            Ok(key) => Ok(Some(key.into())),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(_)) => Err(CapsuleError::InvalidKey(format!(
+                "{} is not valid UTF-8",
+                KEY_ENV
+            ))),
+        }
+    }
+
+    fn reader(&self, capsule_set: &SetArgs) -> CapsuleResult<CapsuleReader> {
+        open_reader(capsule_set, self.load()?)
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("error: {} [{}]", err, err.code().as_ref());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> CapsuleResult<ExitCode> {
+    match command {
+        Command::Create {
+            input,
+            output_dir,
+            inner_padding,
+            kdf,
+            compression,
+            compression_level,
+            dictionary,
+            naming,
+            on_conflict,
+            key,
+        } => {
+            let mut writer = CapsuleWriter::new().with_post_process_padding(!inner_padding);
+            if let Some(key) = key.load()? {
+                writer = writer.with_encryption_key(key);
+            }
+            if let Some(algorithm) = kdf {
+                writer = writer.with_key_derivation(KeyDerivationOptions {
+                    algorithm,
+                    iterations: None,
+                    memory_cost: None,
+                    parallelism: None,
+                    log_n: None,
+                    block_size: None,
+                });
+            }
+            if let Some(algorithm) = compression {
+                writer = writer.with_compression(CompressionOptions {
+                    algorithm,
+                    level: compression_level,
+                    dictionary: None,
+                });
+            }
+            if let Some(dictionary) = dictionary {
+                writer = writer.with_dictionary(&dictionary);
+            }
+            if let Some(naming) = naming {
+                writer = writer.with_naming_scheme(NamingScheme::from_name(&naming)?);
+            }
+            if let Some(on_conflict) = on_conflict {
+                writer = writer.with_conflict_policy(ConflictPolicy::from_name(&on_conflict)?);
+            }
+
+            let capsule_set = writer.write_file(&input, &output_dir)?;
+            println!(
+                "{} {} capsules in {}",
+                capsule_set.id,
+                capsule_set.metadata.capsule_count,
+                output_dir.display()
+            );
+        }
+        Command::Extract {
+            capsule_set,
+            output,
+            key,
+        } => {
+            let reader = key.reader(&capsule_set)?;
+            match output {
+                Some(output) => reader.extract_to_file(output)?,
+                None => {
+                    let mut stdout = BufWriter::new(io::stdout().lock());
+                    reader.extract_to(&mut stdout)?;
+                    stdout.flush()?;
+                }
+            }
+        }
+        Command::Info { files } => {
+            let mut failed = false;
+            for file in files {
+                match CapsuleFileInfo::read(&file) {
+                    Ok(info) => print_file_info(&file, &info),
+                    Err(err) => {
+                        eprintln!("{}: {} [{}]", file.display(), err, err.code().as_ref());
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Verify { capsule_set, key } => {
+            let key = key.load()?;
+            let has_key = key.is_some();
+            let reader = open_reader(&capsule_set, key)?;
+            let capsule_set = reader.capsule_set();
+            capsule_set.validate_consensus()?;
+            reader.verify_capsules()?;
+
+            // Encrypted data can only be checked against the set checksum with the key
+            let encrypted = capsule_set.capsules.iter().any(|capsule| capsule.encrypted);
+            if encrypted && !has_key {
+                println!(
+                    "{} capsules ok; data not checked without a key",
+                    capsule_set.id
+                );
+            } else {
+                reader.extract_to(io::sink())?;
+                println!("{} ok", capsule_set.id);
+            }
+        }
+        Command::Archive {
+            output_dir,
+            files,
+            inner_padding,
+            key,
+        } => {
+            let inputs = files
+                .iter()
+                .map(|file| ArchiveInput::from_file(archive_entry_name(file), file))
+                .collect::<Vec<_>>();
+            let mut writer = CapsuleWriter::new().with_post_process_padding(!inner_padding);
+            if let Some(key) = key.load()? {
+                writer = writer.with_encryption_key(key);
+            }
+
+            let capsule_set = writer.write_archive(&inputs, &output_dir)?;
+            println!(
+                "{} {} files in {} capsules in {}",
+                capsule_set.id,
+                inputs.len(),
+                capsule_set.metadata.capsule_count,
+                output_dir.display()
+            );
+        }
+        Command::ArchiveDir {
+            input_dir,
+            output_dir,
+            inner_padding,
+            filter,
+            key,
+        } => {
+            let mut writer = CapsuleWriter::new().with_post_process_padding(!inner_padding);
+            if let Some(key) = key.load()? {
+                writer = writer.with_encryption_key(key);
+            }
+
+            let capsule_set = writer.write_directory(&input_dir, &output_dir, &filter.filter())?;
+            println!(
+                "{} {} in {} capsules in {}",
+                capsule_set.id,
+                input_dir.display(),
+                capsule_set.metadata.capsule_count,
+                output_dir.display()
+            );
+        }
+        Command::ExtractDir {
+            capsule_set,
+            output_dir,
+            filter,
+            key,
+        } => {
+            let entries = key
+                .reader(&capsule_set)?
+                .extract_to_directory(&output_dir, &filter.filter())?;
+            println!(
+                "{} entries restored to {}",
+                entries.len(),
+                output_dir.display()
+            );
+        }
+        Command::Entries { capsule_set, key } => {
+            for entry in key.reader(&capsule_set)?.list_entries()? {
+                let suffix = if entry.is_directory() {
+                    "/".to_string()
+                } else if let Some(target) = &entry.link_target {
+                    format!(" -> {}", target)
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{:04o}  {:>12} bytes  {}  {}{}",
+                    entry.mode, entry.size, entry.hash, entry.name, suffix
+                );
+            }
+        }
+        Command::ExtractEntry {
+            capsule_set,
+            name,
+            output,
+            key,
+        } => {
+            let data = key.reader(&capsule_set)?.extract_entry(&name)?;
+            write_output(output, &data)?;
+        }
+        Command::TrainDictionary {
+            output_dir,
+            samples,
+            max_size,
+            key,
+        } => {
+            let samples = samples
+                .iter()
+                .map(|path| {
+                    fs::read(path).map_err(|source| CapsuleError::FileIo {
+                        path: path.clone(),
+                        source: source.into(),
+                    })
+                })
+                .collect::<CapsuleResult<Vec<_>>>()?;
+            let mut writer = CapsuleWriter::new();
+            if let Some(key) = key.load()? {
+                writer = writer.with_encryption_key(key);
+            }
+
+            let dictionary_set = writer.write_dictionary(&samples, max_size, &output_dir)?;
+            println!(
+                "{} {} byte dictionary in {}",
+                dictionary_set.id,
+                dictionary_set.metadata.original_size,
+                output_dir.display()
+            );
+        }
+        Command::List { directory } => print_capsule_sets(&directory)?,
+        Command::Recover { directory, dry_run } => {
+            let report = recover_capsule_directory(&directory, dry_run)?;
+            let verb = if dry_run { "would remove" } else { "removed" };
+            for removed_file in &report.removed_files {
+                println!("{} {}", verb, removed_file);
+            }
+            if dry_run {
+                println!("{} files would be removed", report.removed_files.len());
+            } else {
+                println!("{} files removed", report.removed_files.len());
+            }
+        }
+        Command::Range {
+            capsule_set,
+            offset,
+            length,
+            output,
+            key,
+        } => {
+            let data = key.reader(&capsule_set)?.read_range(offset, length)?;
+            write_output(output, &data)?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Relative paths are kept as `/`-separated entry names; absolute ones are
+// stored under their file name
+fn archive_entry_name(file: &Path) -> String {
+    if file.is_absolute() {
+        return file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    file.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Write to `output`, or to stdout without one
+fn write_output(output: Option<PathBuf>, data: &[u8]) -> CapsuleResult<()> {
+    match output {
+        Some(output) => fs::write(&output, data).map_err(|source| CapsuleError::FileIo {
+            path: output.clone(),
+            source: source.into(),
+        }),
+        None => {
+            let mut stdout = BufWriter::new(io::stdout().lock());
+            stdout.write_all(data)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+fn open_reader(capsule_set: &SetArgs, key: Option<EncryptionKey>) -> CapsuleResult<CapsuleReader> {
+    let reader = match &capsule_set.set_id {
+        Some(set_id) => CapsuleReader::open_by_id(&capsule_set.capsule_set, set_id)?,
+        None => CapsuleReader::open(&capsule_set.capsule_set)?,
+    };
+    Ok(match key {
+        Some(key) => reader.with_decryption_key(key),
+        None => reader,
+    })
+}
+
+fn print_file_info(file: &Path, info: &CapsuleFileInfo) {
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    println!("{}", file.display());
+    println!("  version:         {}", info.version);
+    println!("  capsule index:   {}", info.capsule_index);
+    println!("  capsule size:    {}", info.capsule_size);
+    println!("  data size:       {}", info.data_size);
+    println!("  encrypted:       {}", yes_no(info.is_encrypted));
+    println!("  compressed:      {}", yes_no(info.is_compressed));
+    println!("  header checksum: {}", info.checksum);
+}
+
+// One line per capsule set in the directory
+fn print_capsule_sets(directory: &Path) -> CapsuleResult<()> {
+    for capsule_set in list_capsule_sets(directory)? {
+        println!(
+            "{}  {:>12} bytes  {:>4} capsules  {}  {}{}",
+            capsule_set.id,
+            capsule_set.metadata.original_size,
+            capsule_set.metadata.capsule_count,
+            if capsule_set.metadata.encryption_info.is_some() {
+                "encrypted"
+            } else {
+                "plain    "
+            },
+            capsule_set.metadata.consensus_version,
+            if capsule_set.is_dictionary() {
+                "  dictionary"
+            } else if capsule_set.is_archive() {
+                "  archive"
+            } else {
+                ""
+            }
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use sha2::{Digest, Sha256};
+
+use crate::{CapsuleError, CapsuleResult};
+
+// NETWORK CONSENSUS CRITICAL: Merkle tree over capsule hashes. Leaves and inner
+// nodes are domain separated (RFC 6962 style) so a leaf can never be passed off
+// as a node; an unpaired node at the end of a level is carried up unchanged.
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+pub type Hash = [u8; 32];
+
+pub fn parse_hash(hash_hex: &str) -> CapsuleResult<Hash> {
+    let bytes = hex::decode(hash_hex).map_err(|_| CapsuleError::InvalidFormat)?;
+    bytes.try_into().map_err(|_| CapsuleError::InvalidFormat)
+}
+
+fn leaf_hash(capsule_hash: &Hash) -> Hash {
+    let mut hasher = Sha256::default();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(capsule_hash);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::default();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn root(capsule_hashes: &[Hash]) -> CapsuleResult<Hash> {
+    if capsule_hashes.is_empty() {
+        return Err(CapsuleError::InvalidFormat);
+    }
+
+    let mut level: Vec<Hash> = capsule_hashes.iter().map(leaf_hash).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    Ok(level[0])
+}
+
+// Sibling hashes from the leaf up to the root; levels where the node is
+// carried up contribute no sibling
+pub fn proof(capsule_hashes: &[Hash], index: usize) -> CapsuleResult<Vec<Hash>> {
+    if index >= capsule_hashes.len() {
+        return Err(CapsuleError::InvalidFormat);
+    }
+
+    let mut siblings = Vec::new();
+    let mut level: Vec<Hash> = capsule_hashes.iter().map(leaf_hash).collect();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if sibling < level.len() {
+            siblings.push(level[sibling]);
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+    Ok(siblings)
+}
+
+// `leaf_count` must come from trusted metadata: with a smaller count an
+// unpaired node is carried up where the real tree pairs it, which lets a
+// capsule verify at another index
+pub fn verify(
+    capsule_hash: &Hash,
+    index: usize,
+    leaf_count: usize,
+    siblings: &[Hash],
+    expected_root: &Hash,
+) -> bool {
+    if index >= leaf_count {
+        return false;
+    }
+
+    let mut current = leaf_hash(capsule_hash);
+    let mut siblings = siblings.iter();
+    let mut position = index;
+    let mut width = leaf_count;
+    while width > 1 {
+        if position % 2 == 1 {
+            let Some(left) = siblings.next() else {
+                return false;
+            };
+            current = node_hash(left, &current);
+        } else if position + 1 < width {
+            let Some(right) = siblings.next() else {
+                return false;
+            };
+            current = node_hash(&current, right);
+        }
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+
+    siblings.next().is_none() && current == *expected_root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capsule_hashes(count: usize) -> Vec<Hash> {
+        (0..count)
+            .map(|i| Sha256::digest([i as u8]).into())
+            .collect()
+    }
+
+    #[test]
+    fn every_capsule_proves_its_own_index() {
+        for count in 1..=9 {
+            let hashes = capsule_hashes(count);
+            let root = root(&hashes).unwrap();
+            for (index, hash) in hashes.iter().enumerate() {
+                let siblings = proof(&hashes, index).unwrap();
+                assert!(verify(hash, index, count, &siblings, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_do_not_verify_elsewhere() {
+        let hashes = capsule_hashes(5);
+        let root = root(&hashes).unwrap();
+        let siblings = proof(&hashes, 4).unwrap();
+
+        assert!(!verify(&hashes[4], 3, 5, &siblings, &root));
+        assert!(!verify(&hashes[4], 4, 4, &siblings, &root));
+        assert!(!verify(&hashes[4], 4, 6, &siblings, &root));
+        assert!(!verify(&hashes[3], 4, 5, &siblings, &root));
+        assert!(!verify(&hashes[4], 4, 5, &siblings[1..], &root));
+    }
+
+    #[test]
+    fn leaves_are_domain_separated_from_nodes() {
+        let hashes = capsule_hashes(2);
+        let inner = node_hash(&leaf_hash(&hashes[0]), &leaf_hash(&hashes[1]));
+
+        assert_eq!(root(&hashes).unwrap(), inner);
+        assert_ne!(root(&[inner]).unwrap(), inner);
+    }
+
+    #[test]
+    fn empty_sets_and_bad_input_are_rejected() {
+        assert!(root(&[]).is_err());
+        assert!(proof(&capsule_hashes(2), 2).is_err());
+        assert!(parse_hash("abcd").is_err());
+        assert!(parse_hash(&"zz".repeat(32)).is_err());
+    }
+}
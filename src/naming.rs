@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Capsule, CapsuleError, CapsuleResult, CreateCapsuleOptions, METADATA_FILE_SUFFIX};
+
+// Naming schemes recorded in CapsuleMetadata.naming_scheme
+pub const NAMING_SET_ID_PREFIX: &str = "SET_ID_PREFIX"; // {first 16 hex digits of the set id}_{index}.capsule, legacy sets only
+pub const NAMING_SET_ID: &str = "SET_ID"; // {set id}_{index}.capsule
+pub const NAMING_CAPSULE_HASH: &str = "CAPSULE_HASH"; // {capsule hash}.capsule
+pub const NAMING_SET_DIRECTORY: &str = "SET_DIRECTORY"; // {set id}/{index}.capsule
+
+pub const CONFLICT_FAIL: &str = "FAIL";
+pub const CONFLICT_SKIP: &str = "SKIP";
+pub const CONFLICT_OVERWRITE: &str = "OVERWRITE";
+
+const SET_ID_PREFIX_LEN: usize = 16;
+const CAPSULE_FILE_EXTENSION: &str = ".capsule";
+
+// How the files of a capsule set are named inside its directory. Metadata is
+// always stored directly in the directory so sets can be listed in one scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamingScheme {
+    // Sets created before the scheme was recorded use the 16 digit prefix. It
+    // is only read: prefixes of different sets can collide in one directory.
+    SetIdPrefix,
+    #[default]
+    SetId,
+    // Capsules named by their own hash, so identical capsules are stored once
+    CapsuleHash,
+    // Capsules in a subdirectory named after the set id
+    SetDirectory,
+}
+
+impl NamingScheme {
+    pub fn from_name(name: &str) -> CapsuleResult<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            NAMING_SET_ID_PREFIX => Ok(NamingScheme::SetIdPrefix),
+            NAMING_SET_ID => Ok(NamingScheme::SetId),
+            NAMING_CAPSULE_HASH => Ok(NamingScheme::CapsuleHash),
+            NAMING_SET_DIRECTORY => Ok(NamingScheme::SetDirectory),
+            _ => Err(CapsuleError::InvalidOption(format!(
+                "Unsupported naming scheme: {}",
+                name
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NamingScheme::SetIdPrefix => NAMING_SET_ID_PREFIX,
+            NamingScheme::SetId => NAMING_SET_ID,
+            NamingScheme::CapsuleHash => NAMING_CAPSULE_HASH,
+            NamingScheme::SetDirectory => NAMING_SET_DIRECTORY,
+        }
+    }
+
+    // Scheme for a new set
+    pub(crate) fn from_options(options: &CreateCapsuleOptions) -> CapsuleResult<Self> {
+        match options.naming_scheme.as_deref() {
+            None => Ok(NamingScheme::default()),
+            Some(name) => match Self::from_name(name)? {
+                NamingScheme::SetIdPrefix => Err(CapsuleError::InvalidOption(format!(
+                    "Naming scheme {} is only supported for existing sets",
+                    NAMING_SET_ID_PREFIX
+                ))),
+                naming_scheme => Ok(naming_scheme),
+            },
+        }
+    }
+
+    // Scheme of an existing set; sets without one predate the other schemes
+    pub(crate) fn from_metadata(naming_scheme: Option<&str>) -> CapsuleResult<Self> {
+        naming_scheme.map_or(Ok(NamingScheme::SetIdPrefix), |name| {
+            Self::from_name(name).map_err(|_| {
+                CapsuleError::ConsensusViolation("Unsupported naming scheme".to_string())
+            })
+        })
+    }
+
+    pub(crate) fn metadata_file_name(&self, set_id: &str) -> CapsuleResult<String> {
+        check_hex_id(set_id)?;
+        Ok(match self {
+            NamingScheme::SetIdPrefix => {
+                format!("{}{}", &set_id[..SET_ID_PREFIX_LEN], METADATA_FILE_SUFFIX)
+            }
+            _ => format!("{}{}", set_id, METADATA_FILE_SUFFIX),
+        })
+    }
+
+    // Ids and hashes come from metadata files, so they are checked before
+    // being used in a path
+    pub(crate) fn capsule_path(
+        &self,
+        capsules_dir: &Path,
+        set_id: &str,
+        capsule: &Capsule,
+    ) -> CapsuleResult<PathBuf> {
+        check_hex_id(set_id)?;
+        Ok(match self {
+            NamingScheme::SetIdPrefix => capsules_dir.join(format!(
+                "{}_{:03}.capsule",
+                &set_id[..SET_ID_PREFIX_LEN],
+                capsule.index
+            )),
+            NamingScheme::SetId => {
+                capsules_dir.join(format!("{}_{:03}.capsule", set_id, capsule.index))
+            }
+            NamingScheme::CapsuleHash => {
+                check_hex_id(&capsule.hash)?;
+                capsules_dir.join(format!("{}.capsule", capsule.hash.to_lowercase()))
+            }
+            NamingScheme::SetDirectory => capsules_dir
+                .join(set_id)
+                .join(format!("{:03}.capsule", capsule.index)),
+        })
+    }
+}
+
+// What to do when a file of a new capsule set already exists with different contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    Fail,
+    // Keep the set already stored under the same id and return it, unless its
+    // files are missing or damaged, in which case the new set replaces them
+    #[default]
+    Skip,
+    Overwrite,
+}
+
+impl ConflictPolicy {
+    pub fn from_name(name: &str) -> CapsuleResult<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            CONFLICT_FAIL => Ok(ConflictPolicy::Fail),
+            CONFLICT_SKIP => Ok(ConflictPolicy::Skip),
+            CONFLICT_OVERWRITE => Ok(ConflictPolicy::Overwrite),
+            _ => Err(CapsuleError::InvalidOption(format!(
+                "Unsupported conflict policy: {}",
+                name
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Fail => CONFLICT_FAIL,
+            ConflictPolicy::Skip => CONFLICT_SKIP,
+            ConflictPolicy::Overwrite => CONFLICT_OVERWRITE,
+        }
+    }
+
+    pub(crate) fn from_options(options: &CreateCapsuleOptions) -> CapsuleResult<Self> {
+        options
+            .on_conflict
+            .as_deref()
+            .map_or(Ok(ConflictPolicy::default()), Self::from_name)
+    }
+}
+
+// File names the schemes produce, used to tell capsule files apart from
+// anything else stored in a directory
+
+// `{set id or prefix}_{index}.capsule` or `{capsule hash}.capsule`
+pub(crate) fn is_capsule_file_name(file_name: &str) -> bool {
+    let Some(stem) = file_name.strip_suffix(CAPSULE_FILE_EXTENSION) else {
+        return false;
+    };
+    match stem.rsplit_once('_') {
+        Some((set_id, index)) => {
+            (set_id.len() == SET_ID_PREFIX_LEN || set_id.len() == 64)
+                && is_hex(set_id)
+                && is_index(index)
+        }
+        None => stem.len() == 64 && is_hex(stem),
+    }
+}
+
+// `{set id}` directories of the SET_DIRECTORY scheme
+pub(crate) fn is_set_directory_name(dir_name: &str) -> bool {
+    dir_name.len() == 64 && is_hex(dir_name)
+}
+
+// `{index}.capsule` inside a set directory
+pub(crate) fn is_set_directory_capsule_name(file_name: &str) -> bool {
+    file_name
+        .strip_suffix(CAPSULE_FILE_EXTENSION)
+        .is_some_and(is_index)
+}
+
+fn is_hex(value: &str) -> bool {
+    value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_index(value: &str) -> bool {
+    value.len() >= 3 && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn check_hex_id(id: &str) -> CapsuleResult<()> {
+    if id.len() != 64 || !is_hex(id) {
+        return Err(CapsuleError::InvalidFormat);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SET_ID: &str = "6be231e91b4e8481c2bd7a0f5f2fcd4a9e7f2c9d1b64b0b7c1d1b4b3a2f0e9d8";
+
+    fn capsule(index: u32) -> Capsule {
+        Capsule {
+            index,
+            size: 262_144,
+            hash: SET_ID.chars().rev().collect(),
+            encrypted: false,
+            compressed: false,
+            padding_post_process: None,
+        }
+    }
+
+    #[test]
+    fn new_sets_default_to_set_id_naming() {
+        let options = |naming_scheme: Option<&str>| CreateCapsuleOptions {
+            naming_scheme: naming_scheme.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            NamingScheme::from_options(&options(None)).unwrap(),
+            NamingScheme::SetId
+        );
+        assert_eq!(
+            NamingScheme::from_options(&options(Some("capsule_hash"))).unwrap(),
+            NamingScheme::CapsuleHash
+        );
+        assert!(NamingScheme::from_options(&options(Some(NAMING_SET_ID_PREFIX))).is_err());
+        assert!(NamingScheme::from_options(&options(Some("RANDOM"))).is_err());
+    }
+
+    #[test]
+    fn sets_without_a_recorded_scheme_use_the_prefix() {
+        assert_eq!(
+            NamingScheme::from_metadata(None).unwrap(),
+            NamingScheme::SetIdPrefix
+        );
+        assert_eq!(
+            NamingScheme::from_metadata(Some(NAMING_SET_DIRECTORY)).unwrap(),
+            NamingScheme::SetDirectory
+        );
+        assert!(matches!(
+            NamingScheme::from_metadata(Some("RANDOM")),
+            Err(CapsuleError::ConsensusViolation(_))
+        ));
+    }
+
+    #[test]
+    fn every_scheme_names_files_it_recognises() {
+        let dir = Path::new("capsules");
+        for scheme in [
+            NamingScheme::SetIdPrefix,
+            NamingScheme::SetId,
+            NamingScheme::CapsuleHash,
+        ] {
+            let path = scheme.capsule_path(dir, SET_ID, &capsule(7)).unwrap();
+            assert_eq!(path.parent(), Some(dir));
+            assert!(is_capsule_file_name(
+                path.file_name().unwrap().to_str().unwrap()
+            ));
+        }
+
+        let path = NamingScheme::SetDirectory
+            .capsule_path(dir, SET_ID, &capsule(7))
+            .unwrap();
+        assert_eq!(path, dir.join(SET_ID).join("007.capsule"));
+        assert!(is_set_directory_name(SET_ID));
+        assert!(is_set_directory_capsule_name("007.capsule"));
+
+        assert_eq!(
+            NamingScheme::SetIdPrefix
+                .metadata_file_name(SET_ID)
+                .unwrap(),
+            format!("{}_metadata.json", &SET_ID[..16])
+        );
+        assert_eq!(
+            NamingScheme::SetId.metadata_file_name(SET_ID).unwrap(),
+            format!("{}_metadata.json", SET_ID)
+        );
+    }
+
+    #[test]
+    fn other_file_names_are_not_capsules() {
+        assert!(!is_capsule_file_name("notes_000.capsule"));
+        assert!(!is_capsule_file_name(&format!(
+            "{}_00.capsule",
+            &SET_ID[..16]
+        )));
+        assert!(!is_capsule_file_name(&format!(
+            "{}_000.capsule",
+            &SET_ID[..20]
+        )));
+        assert!(!is_capsule_file_name(&format!("{}.tmp", SET_ID)));
+        assert!(!is_set_directory_capsule_name("7.capsule"));
+    }
+
+    #[test]
+    fn ids_from_metadata_cannot_escape_the_directory() {
+        let dir = Path::new("capsules");
+        assert!(NamingScheme::SetId
+            .capsule_path(dir, "../../etc/passwd", &capsule(0))
+            .is_err());
+        assert!(NamingScheme::SetDirectory
+            .metadata_file_name(&SET_ID[..63])
+            .is_err());
+
+        let mut traversal = capsule(0);
+        traversal.hash = "../".repeat(21) + "x";
+        assert!(NamingScheme::CapsuleHash
+            .capsule_path(dir, SET_ID, &traversal)
+            .is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::{CapsuleError, CapsuleResult};
+
+// Shared counters updated from the capsule loops and read by the JS handle
+#[derive(Default)]
+pub struct ProgressTracker {
+    pub(crate) cancelled: AtomicBool,
+    pub(crate) bytes_hashed: AtomicU64,
+    pub(crate) bytes_processed: AtomicU64,
+    pub(crate) total_bytes: AtomicU64,
+    pub(crate) capsules_completed: AtomicU32,
+    pub(crate) total_capsules: AtomicU32,
+}
+
+impl ProgressTracker {
+    pub fn start(&self, total_bytes: u64, total_capsules: u32) {
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.total_capsules.store(total_capsules, Ordering::Relaxed);
+    }
+
+    // Creation hashes the whole input for the set id before any capsule is written
+    pub fn hashed(&self, bytes: u64) {
+        self.bytes_hashed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn capsule_completed(&self, bytes: u64) {
+        self.bytes_processed.fetch_add(bytes, Ordering::Relaxed);
+        self.capsules_completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Checked between capsules and hashed buffers so a cancel takes effect quickly
+    pub fn check_cancelled(&self) -> CapsuleResult<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(CapsuleError::Cancelled);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use aes_gcm::{
+    aead::{AeadInPlace, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::io::{self, Read};
+
+use crate::{CapsuleError, CapsuleResult};
+
+// Segmented AES-256-GCM (STREAM construction): each capsule payload is sealed
+// in fixed-size segments so it can be decrypted with a single segment buffer.
+// Every ciphertext segment except the last is exactly SEGMENT_SIZE bytes, so
+// all consensus capsule sizes hold a whole number of segments.
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const SEGMENT_PLAINTEXT_SIZE: usize = SEGMENT_SIZE - TAG_SIZE;
+const SEGMENT_KEY_INFO: &[u8] = b"DIG_SEGMENT_KEY_V1";
+
+// NETWORK CONSENSUS CRITICAL: Per-capsule subkey bound to the set's nonce prefix
+// (and through it to the set's encoding parameters), so segment nonces only
+// need to be unique within one capsule
+pub fn capsule_cipher(key: &[u8; 32], nonce_prefix: &[u8; 8], capsule_index: u32) -> Aes256Gcm {
+    let hkdf = Hkdf::<Sha256>::new(Some(nonce_prefix), key);
+    let mut info = Vec::with_capacity(SEGMENT_KEY_INFO.len() + 4);
+    info.extend_from_slice(SEGMENT_KEY_INFO);
+    info.extend_from_slice(&capsule_index.to_be_bytes());
+
+    let mut subkey = [0u8; 32];
+    hkdf.expand(&info, &mut subkey)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&subkey))
+}
+
+// Nonce layout: zeros (7) || segment counter (u32 BE) || last-segment flag,
+// so truncating or reordering segments fails authentication
+fn segment_nonce(counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+// Ciphertext length for a plaintext of the given length
+pub fn encrypted_len(plaintext_len: usize) -> usize {
+    let segments = std::cmp::max(1, plaintext_len.div_ceil(SEGMENT_PLAINTEXT_SIZE));
+    plaintext_len + segments * TAG_SIZE
+}
+
+// Plaintext length whose ciphertext is exactly `ciphertext_len` bytes, if any
+pub fn plaintext_len(ciphertext_len: usize) -> Option<usize> {
+    let full_segments = ciphertext_len / SEGMENT_SIZE;
+    match ciphertext_len % SEGMENT_SIZE {
+        0 if full_segments > 0 => Some(full_segments * SEGMENT_PLAINTEXT_SIZE),
+        remainder if remainder >= TAG_SIZE => {
+            Some(full_segments * SEGMENT_PLAINTEXT_SIZE + remainder - TAG_SIZE)
+        }
+        _ => None,
+    }
+}
+
+// Seal the plaintext in `data` in place, growing it by one tag per segment.
+// Reserve `encrypted_len` bytes beforehand to avoid a reallocation.
+pub fn encrypt_in_place(cipher: &Aes256Gcm, data: &mut Vec<u8>) -> CapsuleResult<()> {
+    let plaintext_len = data.len();
+    // An empty payload is still sealed as one (final) segment
+    let segments = std::cmp::max(1, plaintext_len.div_ceil(SEGMENT_PLAINTEXT_SIZE));
+    data.resize(encrypted_len(plaintext_len), 0);
+
+    // Move each segment to its sealed position, last first so no segment is
+    // overwritten before it has moved, and seal it there
+    for counter in (0..segments).rev() {
+        let start = counter * SEGMENT_PLAINTEXT_SIZE;
+        let end = std::cmp::min(start + SEGMENT_PLAINTEXT_SIZE, plaintext_len);
+        let sealed_start = counter * SEGMENT_SIZE;
+        data.copy_within(start..end, sealed_start);
+
+        let nonce = segment_nonce(counter as u32, counter == segments - 1);
+        let (segment, rest) = data[sealed_start..].split_at_mut(end - start);
+        let tag = cipher
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), b"", segment)
+            .map_err(|_| CapsuleError::EncryptionFailed)?;
+        rest[..TAG_SIZE].copy_from_slice(&tag);
+    }
+    Ok(())
+}
+
+// Reader that decrypts `ciphertext_len` bytes of segmented ciphertext one
+// segment at a time
+pub struct SegmentDecryptor<R> {
+    cipher: Aes256Gcm,
+    reader: R,
+    remaining: u64,
+    counter: u32,
+    segment: Vec<u8>,
+    position: usize,
+    capsule_index: u32,
+}
+
+impl<R: Read> SegmentDecryptor<R> {
+    pub fn new(cipher: Aes256Gcm, reader: R, ciphertext_len: u64, capsule_index: u32) -> Self {
+        SegmentDecryptor {
+            cipher,
+            reader,
+            remaining: ciphertext_len,
+            counter: 0,
+            segment: Vec::with_capacity(SEGMENT_SIZE),
+            position: 0,
+            capsule_index,
+        }
+    }
+
+    fn next_segment(&mut self) -> CapsuleResult<()> {
+        let segment_len = std::cmp::min(self.remaining, SEGMENT_SIZE as u64) as usize;
+        if segment_len < TAG_SIZE {
+            return Err(CapsuleError::InvalidFormat);
+        }
+
+        self.segment.resize(segment_len, 0);
+        self.reader.read_exact(&mut self.segment)?;
+        self.remaining -= segment_len as u64;
+
+        let nonce = segment_nonce(self.counter, self.remaining == 0);
+        self.cipher
+            .decrypt_in_place(Nonce::from_slice(&nonce), b"", &mut self.segment)
+            .map_err(|_| CapsuleError::DecryptionFailed {
+                index: self.capsule_index,
+            })?;
+        self.counter += 1;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SegmentDecryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.segment.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.next_segment()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        let available = &self.segment[self.position..];
+        let count = std::cmp::min(available.len(), buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+    const NONCE_PREFIX: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn seal(plaintext: &[u8], capsule_index: u32) -> Vec<u8> {
+        let mut data = plaintext.to_vec();
+        encrypt_in_place(
+            &capsule_cipher(&KEY, &NONCE_PREFIX, capsule_index),
+            &mut data,
+        )
+        .unwrap();
+        data
+    }
+
+    fn open(ciphertext: &[u8], capsule_index: u32) -> io::Result<Vec<u8>> {
+        let cipher = capsule_cipher(&KEY, &NONCE_PREFIX, capsule_index);
+        let mut decryptor =
+            SegmentDecryptor::new(cipher, ciphertext, ciphertext.len() as u64, capsule_index);
+        let mut plaintext = Vec::new();
+        decryptor.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn round_trips_across_segment_boundaries() {
+        for len in [
+            0,
+            1,
+            SEGMENT_PLAINTEXT_SIZE,
+            SEGMENT_PLAINTEXT_SIZE + 1,
+            3 * SEGMENT_SIZE,
+        ] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let ciphertext = seal(&plaintext, 3);
+
+            assert_eq!(ciphertext.len(), encrypted_len(len));
+            assert_eq!(plaintext_len(ciphertext.len()), Some(len));
+            assert_eq!(open(&ciphertext, 3).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn full_segments_are_exactly_segment_size() {
+        assert_eq!(encrypted_len(SEGMENT_PLAINTEXT_SIZE), SEGMENT_SIZE);
+        assert_eq!(encrypted_len(2 * SEGMENT_PLAINTEXT_SIZE), 2 * SEGMENT_SIZE);
+        assert_eq!(plaintext_len(TAG_SIZE - 1), None);
+        assert_eq!(plaintext_len(SEGMENT_SIZE + TAG_SIZE - 1), None);
+    }
+
+    #[test]
+    fn truncated_or_foreign_ciphertext_is_rejected() {
+        let ciphertext = seal(&[9; 2 * SEGMENT_PLAINTEXT_SIZE], 0);
+
+        // Dropping the final segment leaves a non-final segment at the end
+        assert!(open(&ciphertext[..SEGMENT_SIZE], 0).is_err());
+        // Each capsule index has its own subkey
+        assert!(open(&ciphertext, 1).is_err());
+
+        let mut tampered = ciphertext.clone();
+        tampered[SEGMENT_SIZE + 5] ^= 1;
+        assert!(open(&tampered, 0).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+// The binary needs the `cli` feature: `cargo test --features cli`
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const KEY: &str = "cli-test-key";
+
+fn data_capsules(args: &[&str], key: Option<&str>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_data-capsules"));
+    command.args(args).env_remove("DATA_CAPSULES_KEY");
+    if let Some(key) = key {
+        command.env("DATA_CAPSULES_KEY", key);
+    }
+    command.output().unwrap()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn test_data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 7 % 253) as u8).collect()
+}
+
+#[test]
+fn create_extract_and_range_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.dat");
+    let capsules = dir.path().join("capsules");
+    let data = test_data(1024 * 1024 + 100);
+    fs::write(&input, &data).unwrap();
+
+    let created = data_capsules(&["create", path(&input), path(&capsules)], Some(KEY));
+    assert!(created.status.success());
+    let stdout = String::from_utf8(created.stdout).unwrap();
+    assert!(stdout.contains("2 capsules"), "{}", stdout);
+
+    let extracted = data_capsules(&["extract", path(&capsules)], Some(KEY));
+    assert!(extracted.status.success());
+    assert_eq!(extracted.stdout, data);
+
+    let range = data_capsules(&["range", path(&capsules), "1048570", "20"], Some(KEY));
+    assert!(range.status.success());
+    assert_eq!(range.stdout, &data[1048570..1048590]);
+
+    let wrong_key = data_capsules(&["extract", path(&capsules)], Some("wrong-key"));
+    assert!(!wrong_key.status.success());
+    assert!(String::from_utf8_lossy(&wrong_key.stderr).contains("CAPSULE_DECRYPT_FAILED"));
+}
+
+#[test]
+fn keys_can_be_read_from_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.dat");
+    let key_file = dir.path().join("key.txt");
+    let capsules = dir.path().join("capsules");
+    let output = dir.path().join("output.dat");
+    fs::write(&input, test_data(5000)).unwrap();
+    fs::write(&key_file, format!("{}\n", KEY)).unwrap();
+
+    let key_args = ["--key-file", path(&key_file)];
+    let created = data_capsules(
+        &[&["create", path(&input), path(&capsules)], &key_args[..]].concat(),
+        None,
+    );
+    assert!(created.status.success());
+
+    // The trailing newline in the key file is not part of the passphrase
+    let extracted = data_capsules(
+        &["extract", path(&capsules), "-o", path(&output)],
+        Some(KEY),
+    );
+    assert!(extracted.status.success());
+    assert_eq!(fs::read(&output).unwrap(), test_data(5000));
+}
+
+#[test]
+fn info_verify_and_list_inspect_capsules() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.dat");
+    fs::write(&input, test_data(300 * 1024)).unwrap();
+    assert!(
+        data_capsules(&["create", path(&input), path(dir.path())], Some(KEY))
+            .status
+            .success()
+    );
+
+    let listed = data_capsules(&["list", path(dir.path())], None);
+    let listing = String::from_utf8(listed.stdout).unwrap();
+    assert_eq!(listing.lines().count(), 1);
+    assert!(listing.contains("encrypted"));
+
+    let capsule = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().ends_with("_000.capsule"))
+        .unwrap();
+    let info = data_capsules(&["info", path(&capsule)], None);
+    let info = String::from_utf8(info.stdout).unwrap();
+    assert!(info.contains("encrypted:       yes"), "{}", info);
+
+    let verified = data_capsules(&["verify", path(dir.path())], None);
+    assert!(verified.status.success());
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("data not checked"));
+    assert!(data_capsules(&["verify", path(dir.path())], Some(KEY))
+        .status
+        .success());
+
+    let mut contents = fs::read(&capsule).unwrap();
+    let last = contents.len() - 1;
+    contents[last] ^= 0xff;
+    fs::write(&capsule, contents).unwrap();
+    let tampered = data_capsules(&["verify", path(dir.path())], None);
+    assert!(!tampered.status.success());
+    assert!(String::from_utf8_lossy(&tampered.stderr).contains("CAPSULE_HASH_MISMATCH"));
+}
@@ -0,0 +1,412 @@
+// The Node bindings reference N-API symbols only a Node process provides, so
+// this links either without them or with `cli`, which resolves them at load
+// time: `cargo test --no-default-features` or `cargo test --all-features`
+#![cfg(any(feature = "cli", not(feature = "napi")))]
+
+use data_capsules::{
+    list_capsule_sets, ArchiveInput, CapsuleError, CapsuleReader, CapsuleSet, CapsuleWriter,
+    CompressionOptions, ConflictPolicy, DirectoryFilter, NamingScheme,
+};
+use sha2::{Digest, Sha256};
+
+const KEY: &str = "rust-api-test-key";
+
+fn test_data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+#[test]
+fn writer_and_reader_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = test_data(3 * 1024 * 1024 + 17);
+
+    let capsule_set = CapsuleWriter::new()
+        .with_encryption_key(KEY)
+        .write(&data, dir.path())
+        .unwrap();
+    assert_eq!(capsule_set.metadata.capsule_count, 4);
+    capsule_set.validate_consensus().unwrap();
+
+    let reader = CapsuleReader::open(dir.path())
+        .unwrap()
+        .with_decryption_key(KEY);
+    assert_eq!(reader.capsule_set().id, capsule_set.id);
+    assert_eq!(reader.extract().unwrap(), data);
+
+    let range = reader.read_range(1024 * 1024 - 10, 20).unwrap();
+    assert_eq!(range, &data[1024 * 1024 - 10..1024 * 1024 + 10]);
+
+    let capsule = reader.decode_capsule(3).unwrap();
+    assert_eq!(capsule, &data[3 * 1024 * 1024..]);
+}
+
+#[test]
+fn file_round_trip_and_merkle_proofs() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.dat");
+    let output = dir.path().join("output.dat");
+    let capsules_dir = dir.path().join("capsules");
+    let data = test_data(750 * 1024);
+    std::fs::write(&input, &data).unwrap();
+
+    let capsule_set = CapsuleWriter::new()
+        .with_post_process_padding(false)
+        .write_file(&input, &capsules_dir)
+        .unwrap();
+
+    let loaded = CapsuleSet::load(&capsules_dir).unwrap();
+    let root = loaded.metadata.merkle_root.clone().unwrap();
+    assert_eq!(loaded.merkle_root().unwrap(), root);
+    for capsule in &loaded.capsules {
+        assert!(loaded
+            .merkle_proof(capsule.index)
+            .unwrap()
+            .verify(&root, loaded.metadata.capsule_count)
+            .unwrap());
+    }
+
+    CapsuleReader::new(capsule_set, &capsules_dir)
+        .extract_to_file(&output)
+        .unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), data);
+}
+
+#[test]
+fn reader_reports_typed_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    CapsuleWriter::new()
+        .with_encryption_key(KEY)
+        .write(&test_data(1024), dir.path())
+        .unwrap();
+
+    let reader = CapsuleReader::open(dir.path())
+        .unwrap()
+        .with_decryption_key("wrong-key");
+    assert!(matches!(
+        reader.extract(),
+        Err(CapsuleError::DecryptionFailed { index: 0 })
+    ));
+    assert!(matches!(
+        reader.read_range(2048, 1),
+        Err(CapsuleError::RangeOutOfBounds)
+    ));
+    assert_eq!(
+        reader.decode_capsule(0).unwrap_err().code().as_ref(),
+        "CAPSULE_DECRYPT_FAILED"
+    );
+}
+
+#[test]
+fn errors_unwrap_from_io_and_serialize() {
+    let wrapped = std::io::Error::other(CapsuleError::Cancelled);
+    assert!(matches!(
+        CapsuleError::from(wrapped),
+        CapsuleError::Cancelled
+    ));
+
+    let err = CapsuleError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+    let json = serde_json::to_string(&err.clone()).unwrap();
+    let parsed: CapsuleError = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.code(), err.code());
+    assert_eq!(parsed.to_string(), err.to_string());
+}
+
+#[test]
+fn capsules_with_an_unknown_version_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    CapsuleWriter::new()
+        .with_encryption_key(KEY)
+        .write(&test_data(1024), dir.path())
+        .unwrap();
+    let reader = CapsuleReader::open(dir.path()).unwrap();
+    let capsule_path = reader.capsule_path(0).unwrap();
+
+    // Patch the version field and keep the header checksum valid
+    let mut capsule = std::fs::read(&capsule_path).unwrap();
+    capsule[8..12].copy_from_slice(&3u32.to_le_bytes());
+    let mut checksummed = capsule[..36].to_vec();
+    checksummed.extend_from_slice(&capsule[40..44]);
+    capsule[36..40].copy_from_slice(&crc32fast::hash(&checksummed).to_le_bytes());
+    std::fs::write(&capsule_path, &capsule).unwrap();
+
+    // Record the patched capsule in the metadata so only the version is wrong
+    let mut capsule_set = reader.capsule_set().clone();
+    capsule_set.capsules[0].hash = hex::encode(Sha256::digest(&capsule));
+    capsule_set.metadata.merkle_root = Some(capsule_set.merkle_root().unwrap());
+    let metadata_path = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().ends_with("_metadata.json"))
+        .unwrap();
+    std::fs::write(
+        &metadata_path,
+        serde_json::to_vec_pretty(&capsule_set).unwrap(),
+    )
+    .unwrap();
+
+    let reader = CapsuleReader::open(dir.path())
+        .unwrap()
+        .with_decryption_key(KEY);
+    for result in [reader.verify_capsules(), reader.extract().map(|_| ())] {
+        assert!(matches!(
+            result,
+            Err(CapsuleError::ConsensusViolation(message)) if message == "Unsupported capsule version"
+        ));
+    }
+}
+
+#[test]
+fn sets_sharing_a_directory_are_selected_by_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = CapsuleWriter::new()
+        .write(&test_data(1024), dir.path())
+        .unwrap();
+    let second = CapsuleWriter::new()
+        .write(&test_data(2048), dir.path())
+        .unwrap();
+
+    let mut ids = vec![first.id.clone(), second.id.clone()];
+    ids.sort();
+    let listed = list_capsule_sets(dir.path()).unwrap();
+    assert_eq!(
+        listed.iter().map(|set| set.id.clone()).collect::<Vec<_>>(),
+        ids
+    );
+
+    assert!(matches!(
+        CapsuleReader::open(dir.path()),
+        Err(CapsuleError::AmbiguousCapsuleSet { count: 2 })
+    ));
+    let reader = CapsuleReader::open_by_id(dir.path(), &second.id[..10]).unwrap();
+    assert_eq!(reader.extract().unwrap(), test_data(2048));
+    assert_eq!(
+        CapsuleSet::load_by_id(dir.path(), "xyz")
+            .unwrap_err()
+            .code()
+            .as_ref(),
+        "CAPSULE_SET_NOT_FOUND"
+    );
+}
+
+#[test]
+fn naming_schemes_and_conflict_policies() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = test_data(1500 * 1024);
+
+    let capsule_set = CapsuleWriter::new()
+        .with_naming_scheme(NamingScheme::SetDirectory)
+        .write(&data, dir.path())
+        .unwrap();
+    assert!(dir
+        .path()
+        .join(&capsule_set.id)
+        .join("001.capsule")
+        .is_file());
+    let reader = CapsuleReader::open(dir.path()).unwrap();
+    assert_eq!(
+        reader.capsule_path(1).unwrap(),
+        dir.path().join(&capsule_set.id).join("001.capsule")
+    );
+    assert_eq!(reader.extract().unwrap(), data);
+
+    // Same data under another key: same id, different capsule contents
+    let writer = CapsuleWriter::new()
+        .with_naming_scheme(NamingScheme::SetDirectory)
+        .with_encryption_key(KEY);
+    assert!(matches!(
+        writer
+            .clone()
+            .with_conflict_policy(ConflictPolicy::Fail)
+            .write(&data, dir.path()),
+        Err(CapsuleError::FileConflict { .. })
+    ));
+    let skipped = writer.write(&data, dir.path()).unwrap();
+    assert!(skipped.metadata.encryption_info.is_none());
+
+    writer
+        .with_conflict_policy(ConflictPolicy::Overwrite)
+        .write(&data, dir.path())
+        .unwrap();
+    let reader = CapsuleReader::open(dir.path())
+        .unwrap()
+        .with_decryption_key(KEY);
+    assert_eq!(reader.extract().unwrap(), data);
+}
+
+#[test]
+fn zstd_and_lz4_sets_round_trip() {
+    let data = test_data(1024 * 1024 + 5000);
+    for algorithm in ["zstd", "lz4"] {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule_set = CapsuleWriter::new()
+            .with_encryption_key(KEY)
+            .with_compression(CompressionOptions {
+                algorithm: algorithm.to_string(),
+                level: None,
+                dictionary: None,
+            })
+            .write(&data, dir.path())
+            .unwrap();
+        let compression_info = capsule_set.metadata.compression_info.unwrap();
+        assert_eq!(compression_info.algorithm, algorithm);
+
+        let reader = CapsuleReader::open(dir.path())
+            .unwrap()
+            .with_decryption_key(KEY);
+        assert_eq!(reader.extract().unwrap(), data);
+        assert_eq!(reader.decode_capsule(1).unwrap(), &data[1024 * 1024..]);
+    }
+}
+
+#[test]
+fn incompressible_chunks_are_stored_raw() {
+    // One periodic chunk followed by one of pseudo-random bytes
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let noise = (0..1024 * 1024).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    });
+    let data: Vec<u8> = test_data(1024 * 1024).into_iter().chain(noise).collect();
+
+    for post_process_padding in [true, false] {
+        let dir = tempfile::tempdir().unwrap();
+        let capsule_set = CapsuleWriter::new()
+            .with_post_process_padding(post_process_padding)
+            .with_encryption_key(KEY)
+            .write(&data, dir.path())
+            .unwrap();
+        let compressed: Vec<bool> = capsule_set.capsules.iter().map(|c| c.compressed).collect();
+        assert_eq!(compressed, [true, false]);
+
+        let reader = CapsuleReader::open(dir.path())
+            .unwrap()
+            .with_decryption_key(KEY);
+        assert_eq!(reader.extract().unwrap(), data);
+        assert_eq!(reader.decode_capsule(1).unwrap(), &data[1024 * 1024..]);
+    }
+}
+
+#[test]
+fn sets_use_dictionaries_stored_next_to_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let samples: Vec<Vec<u8>> = (0..500)
+        .map(|i| {
+            format!(
+                r#"{{"id":{},"kind":"record","owner":"user-{}","tags":["a","b"]}}"#,
+                i,
+                i % 7
+            )
+            .into_bytes()
+        })
+        .collect();
+    let writer = CapsuleWriter::new().with_encryption_key(KEY);
+
+    let dictionary_set = writer
+        .write_dictionary(&samples, Some(4096), dir.path())
+        .unwrap();
+    assert!(dictionary_set.is_dictionary());
+
+    let data = br#"{"id":9001,"kind":"record","owner":"user-3","tags":["a","b"]}"#;
+    let capsule_set = writer
+        .with_dictionary(&dictionary_set.id)
+        .write(data, dir.path())
+        .unwrap();
+    let compression_info = capsule_set.metadata.compression_info.unwrap();
+    assert_eq!(compression_info.algorithm, "zstd");
+    assert_eq!(compression_info.dictionary, Some(dictionary_set.id));
+
+    let reader = CapsuleReader::open(dir.path())
+        .unwrap()
+        .with_decryption_key(KEY);
+    assert_eq!(reader.capsule_set().id, capsule_set.id);
+    assert_eq!(reader.extract().unwrap(), data);
+}
+
+#[test]
+fn archives_list_and_extract_single_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("notes.txt");
+    std::fs::write(&file_path, b"notes").unwrap();
+    let large = test_data(2 * 1024 * 1024);
+
+    let capsule_set = CapsuleWriter::new()
+        .with_encryption_key(KEY)
+        .write_archive(
+            &[
+                ArchiveInput::from_data("data/large.bin", large.clone()),
+                ArchiveInput::from_file("notes.txt", &file_path).with_mode(0o600),
+                ArchiveInput::from_data("a.json", b"{}".to_vec()),
+            ],
+            dir.path().join("archive"),
+        )
+        .unwrap();
+    assert!(capsule_set.is_archive());
+
+    let reader = CapsuleReader::open(dir.path().join("archive"))
+        .unwrap()
+        .with_decryption_key(KEY);
+    let entries = reader.list_entries().unwrap();
+    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["a.json", "data/large.bin", "notes.txt"]);
+    assert_eq!(entries[2].mode, 0o600);
+
+    assert_eq!(reader.extract_entry("notes.txt").unwrap(), b"notes");
+    assert_eq!(reader.extract_entry("data/large.bin").unwrap(), large);
+    assert!(matches!(
+        reader.extract_entry("missing"),
+        Err(CapsuleError::EntryNotFound { .. })
+    ));
+}
+
+#[test]
+fn directory_trees_round_trip_through_archive_sets() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("site");
+    std::fs::create_dir_all(input.join("assets/img")).unwrap();
+    std::fs::create_dir_all(input.join("empty")).unwrap();
+    std::fs::write(input.join("index.html"), b"<h1>home</h1>").unwrap();
+    std::fs::write(input.join("assets/img/logo.bin"), test_data(300 * 1024)).unwrap();
+    std::fs::write(input.join("debug.log"), b"log").unwrap();
+
+    let capsule_set = CapsuleWriter::new()
+        .with_encryption_key(KEY)
+        .write_directory(
+            &input,
+            dir.path().join("capsules"),
+            &DirectoryFilter::new().with_exclude("*.log"),
+        )
+        .unwrap();
+    assert!(capsule_set.is_archive());
+
+    let reader = CapsuleReader::open(dir.path().join("capsules"))
+        .unwrap()
+        .with_decryption_key(KEY);
+    let restored = reader
+        .extract_to_directory(dir.path().join("restored"), &DirectoryFilter::new())
+        .unwrap();
+    let names: Vec<&str> = restored.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "assets",
+            "assets/img",
+            "assets/img/logo.bin",
+            "empty",
+            "index.html"
+        ]
+    );
+    assert!(restored[0].is_directory());
+    assert_eq!(
+        std::fs::read(dir.path().join("restored/assets/img/logo.bin")).unwrap(),
+        test_data(300 * 1024)
+    );
+    assert!(dir.path().join("restored/empty").is_dir());
+
+    // Only empty or missing directories are restored into
+    assert!(matches!(
+        reader.extract_to_directory(dir.path().join("restored"), &DirectoryFilter::new()),
+        Err(CapsuleError::InvalidOption(_))
+    ));
+}